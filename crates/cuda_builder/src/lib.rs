@@ -56,6 +56,12 @@ pub struct CudaBuilder {
     /// Whether to run libnvvm optimizations. This defaults to `false`
     /// but will be set to `true` if release is specified.
     pub nvvm_opts: bool,
+    /// Whether to generate full debug info, including the local variables of every function, so kernels
+    /// can be debugged with cuda-gdb. This builds the crate with `-Cdebuginfo=2` and gives `-g` to libnvvm,
+    /// which only works without libnvvm optimizations, so [`nvvm_opts`](Self::nvvm_opts) is ignored.
+    ///
+    /// `false` by default.
+    pub debug_info: bool,
     /// The virtual compute architecture to target for PTX generation. This
     /// dictates how certain things are codegenned and may affect performance
     /// and/or which gpus the code can run on.
@@ -86,7 +92,8 @@ pub struct CudaBuilder {
     /// `false` by default.
     pub fast_sqrt: bool,
     /// Use a fast approximation for single-precision floating point division.
-    /// `false` by default.
+    /// Double-precision division always rounds to the nearest value (`div.rn.f64`), use
+    /// `FloatExt::div_approx` to approximate it. `false` by default.
     pub fast_div: bool,
     /// Enable FMA (fused multiply-add) contraction.
     /// `true` by default.
@@ -105,6 +112,94 @@ pub struct CudaBuilder {
     ///
     /// `false` by default.
     pub optix: bool,
+    /// The NVVM IR version (major, minor) the installed libnvvm (CUDA toolkit) must support. If set, the
+    /// codegen will error early with a clear message if libnvvm cannot accept this IR version, instead of
+    /// failing with an opaque libnvvm error. This is only a check, the generated IR is the same either way.
+    ///
+    /// `None` by default.
+    pub required_nvvm_ir_version: Option<(i32, i32)>,
+    /// Whether recursion which is reachable from a kernel should be a hard error instead of a warning.
+    /// GPU stacks are small so recursion usually overflows them at runtime.
+    ///
+    /// `false` by default.
+    pub deny_recursion: bool,
+    /// Whether to stop before libnvvm and emit the optimized LLVM bitcode of the crate linked with its
+    /// dependencies (`<name>.device.bc`) instead of a PTX file. This is meant for linking the device code
+    /// with clang or other LLVM tools, libdevice is not linked in. [`build`](Self::build) then returns
+    /// the path of the bitcode file.
+    ///
+    /// `false` by default.
+    pub emit_device_bitcode: bool,
+    /// Whether to also codegen every kernel into its own PTX file next to the final PTX file, named
+    /// `<name>.<kernel>.ptx`. Each file contains a single kernel and every device function it uses,
+    /// which lets runtimes load only the kernels they need. This codegens the crate once per kernel,
+    /// so it makes builds slower.
+    ///
+    /// `false` by default.
+    pub split_kernels: bool,
+    /// Warn about kernels which use more than this many bytes of local memory per thread for their
+    /// stack, for example due to large local arrays or big structs passed by value. Local memory is
+    /// much slower than registers, so this is useful to catch accidental large stack allocations.
+    ///
+    /// `None` by default.
+    pub local_memory_warning: Option<u64>,
+    /// Whether to never unroll loops. Unrolling removes loop overhead but can greatly increase the
+    /// register pressure of a kernel, which lowers occupancy or makes registers spill. Unrolling is
+    /// already disabled when optimizing for size.
+    ///
+    /// `false` by default.
+    pub no_unroll: bool,
+    /// Whether to write a JSON manifest of the kernels next to the final PTX file, named `<name>.kernels.json`.
+    /// It lists every kernel with the size, alignment and offset of each of its params, as well as its launch
+    /// bounds and cluster dimensions, which is useful for host code that packs kernel params itself or
+    /// generates launch code.
+    ///
+    /// `false` by default.
+    pub emit_kernel_manifest: bool,
+    /// Whether to write a JSON table of the externally visible device functions and globals next to the final
+    /// PTX file, named `<name>.symbols.json`. It lists the name of every symbol in the PTX along with its
+    /// demangled name, which lets host code find functions and statics with mangled names through
+    /// `cuModuleGetFunction` and `cuModuleGetGlobal`.
+    ///
+    /// `false` by default.
+    pub emit_symbol_table: bool,
+    /// The size in bytes up to which consts which are partially uninit are emitted as chunks of init and
+    /// uninit bytes, instead of as fully init consts, like rustc's `-Zpartially-uninit-const-threshold`.
+    /// PTX has no uninit data, so this does not make the PTX smaller, and splitting consts can inhibit
+    /// optimizations.
+    ///
+    /// `None` by default, which never splits consts.
+    pub partially_uninit_const_threshold: Option<usize>,
+    /// Whether to report which intrinsics used by the crate need a newer architecture than the oldest one,
+    /// and error with the architecture they need for the ones [`arch`](Self::arch) does not have. Without this,
+    /// using such an intrinsic is only caught by ptxas or when loading the PTX, with an error which does not
+    /// say where it is used.
+    ///
+    /// `false` by default.
+    pub intrinsic_report: bool,
+    /// Whether to compile the final PTX file with ptxas for [`arch`](Self::arch) and report the registers,
+    /// shared memory, stack and register spills of every kernel, along with the amount of PTX instructions
+    /// it has. This is useful when tuning the occupancy of kernels, ptxas must be installed for it.
+    ///
+    /// `false` by default.
+    pub ptx_report: bool,
+    /// Other architectures to generate PTX for along with [`arch`](Self::arch), every one is written next to the
+    /// final PTX file as `<name>.sm_XX.ptx`. The crate is only compiled once for [`arch`](Self::arch), which is
+    /// what `cuda_std::arch` checks see, so these must not be older than it. Only libnvvm runs again for each of
+    /// them, which lets it use the instructions and scheduling of newer GPUs.
+    ///
+    /// Empty by default.
+    pub ptx_archs: Vec<NvvmArch>,
+    /// Whether an out of bounds index into a slice or array prints the index and the length and traps right
+    /// away, instead of going through the panic handler which only prints the location. Bounds checks are
+    /// never removed, this only changes what happens when one fails, which is useful when debugging
+    /// out of bounds accesses.
+    ///
+    /// `false` by default.
+    pub bounds_check_trap: bool,
+    /// Extra options given to LLVM's option parser, which affect the optimizations done before libnvvm.
+    /// For example `-unroll-threshold=500`. Options LLVM does not know are ignored with a warning.
+    pub llvm_args: Vec<String>,
 }
 
 impl CudaBuilder {
@@ -116,6 +211,7 @@ impl CudaBuilder {
             ptx_file_copy_path: None,
             generate_line_info: true,
             nvvm_opts: true,
+            debug_info: false,
             arch: NvvmArch::Compute61,
             ftz: false,
             fast_sqrt: false,
@@ -123,6 +219,20 @@ impl CudaBuilder {
             fma_contraction: true,
             emit: None,
             optix: false,
+            required_nvvm_ir_version: None,
+            deny_recursion: false,
+            emit_device_bitcode: false,
+            split_kernels: false,
+            local_memory_warning: None,
+            no_unroll: false,
+            emit_kernel_manifest: false,
+            emit_symbol_table: false,
+            partially_uninit_const_threshold: None,
+            intrinsic_report: false,
+            ptx_report: false,
+            ptx_archs: vec![],
+            bounds_check_trap: false,
+            llvm_args: vec![],
         }
     }
 
@@ -155,6 +265,12 @@ impl CudaBuilder {
         self
     }
 
+    /// Generate full debug info for cuda-gdb, see [`debug_info`](Self::debug_info) for more info.
+    pub fn debug_info(mut self, debug_info: bool) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+
     /// The virtual compute architecture to target for PTX generation. This
     /// dictates how certain things are codegenned and may affect performance
     /// and/or which gpus the code can run on.
@@ -231,11 +347,107 @@ impl CudaBuilder {
         self
     }
 
+    /// The NVVM IR version (major, minor) the installed libnvvm (CUDA toolkit) must support. If set, the
+    /// codegen will error early with a clear message if libnvvm cannot accept this IR version.
+    pub fn required_nvvm_ir_version(mut self, major: i32, minor: i32) -> Self {
+        self.required_nvvm_ir_version = Some((major, minor));
+        self
+    }
+
+    /// Whether recursion which is reachable from a kernel should be a hard error instead of a warning.
+    pub fn deny_recursion(mut self, deny_recursion: bool) -> Self {
+        self.deny_recursion = deny_recursion;
+        self
+    }
+
+    /// Emit the optimized and linked LLVM bitcode of the crate instead of PTX, see
+    /// [`emit_device_bitcode`](Self::emit_device_bitcode) for more info.
+    pub fn emit_device_bitcode(mut self, emit_device_bitcode: bool) -> Self {
+        self.emit_device_bitcode = emit_device_bitcode;
+        self
+    }
+
+    /// Also codegen every kernel into its own PTX file, see [`split_kernels`](Self::split_kernels)
+    /// for more info.
+    pub fn split_kernels(mut self, split_kernels: bool) -> Self {
+        self.split_kernels = split_kernels;
+        self
+    }
+
+    /// Warn about kernels which use more than `bytes` bytes of local memory per thread, see
+    /// [`local_memory_warning`](Self::local_memory_warning) for more info.
+    pub fn local_memory_warning(mut self, bytes: u64) -> Self {
+        self.local_memory_warning = Some(bytes);
+        self
+    }
+
+    /// Never unroll loops, see [`no_unroll`](Self::no_unroll) for more info.
+    pub fn no_unroll(mut self, no_unroll: bool) -> Self {
+        self.no_unroll = no_unroll;
+        self
+    }
+
+    /// Write a JSON manifest of the kernels and their params, see
+    /// [`emit_kernel_manifest`](Self::emit_kernel_manifest) for more info.
+    pub fn emit_kernel_manifest(mut self, emit_kernel_manifest: bool) -> Self {
+        self.emit_kernel_manifest = emit_kernel_manifest;
+        self
+    }
+
+    /// Write a JSON table of the visible device functions and globals, see
+    /// [`emit_symbol_table`](Self::emit_symbol_table) for more info.
+    pub fn emit_symbol_table(mut self, emit_symbol_table: bool) -> Self {
+        self.emit_symbol_table = emit_symbol_table;
+        self
+    }
+
+    /// Split partially uninit consts up to `bytes` bytes into init and uninit chunks, see
+    /// [`partially_uninit_const_threshold`](Self::partially_uninit_const_threshold) for more info.
+    pub fn partially_uninit_const_threshold(mut self, bytes: usize) -> Self {
+        self.partially_uninit_const_threshold = Some(bytes);
+        self
+    }
+
+    /// Report the intrinsics which need a newer architecture, see [`intrinsic_report`](Self::intrinsic_report)
+    /// for more info.
+    pub fn intrinsic_report(mut self, intrinsic_report: bool) -> Self {
+        self.intrinsic_report = intrinsic_report;
+        self
+    }
+
+    /// Report the resources every kernel uses after ptxas, see [`ptx_report`](Self::ptx_report) for more info.
+    pub fn ptx_report(mut self, ptx_report: bool) -> Self {
+        self.ptx_report = ptx_report;
+        self
+    }
+
+    /// Also generates PTX for `arch`, see [`ptx_archs`](Self::ptx_archs) for more info.
+    pub fn ptx_arch(mut self, arch: NvvmArch) -> Self {
+        self.ptx_archs.push(arch);
+        self
+    }
+
+    /// Print the index and length of failed bounds checks and trap, see
+    /// [`bounds_check_trap`](Self::bounds_check_trap) for more info.
+    pub fn bounds_check_trap(mut self, bounds_check_trap: bool) -> Self {
+        self.bounds_check_trap = bounds_check_trap;
+        self
+    }
+
+    /// Adds an option for LLVM's option parser, see [`llvm_args`](Self::llvm_args) for more info.
+    pub fn llvm_arg(mut self, arg: impl Into<String>) -> Self {
+        self.llvm_args.push(arg.into());
+        self
+    }
+
     /// Runs rustc to build the codegen and codegens the gpu crate, returning the path of the final
     /// ptx file. If [`ptx_file_copy_path`](Self::ptx_file_copy_path) is set, this returns the copied path.
     pub fn build(self) -> Result<PathBuf, CudaBuilderError> {
         println!("cargo:rerun-if-changed={}", self.path_to_crate.display());
-        let path = invoke_rustc(&self)?;
+        let mut path = invoke_rustc(&self)?;
+        if self.emit_device_bitcode {
+            path.set_extension("device.bc");
+        }
         if let Some(copy_path) = self.ptx_file_copy_path {
             std::fs::copy(path, &copy_path).map_err(CudaBuilderError::FailedToCopyPtxFile)?;
             Ok(copy_path)
@@ -311,25 +523,41 @@ fn invoke_rustc(builder: &CudaBuilder) -> Result<PathBuf, CudaBuilderError> {
 
     add_libnvvm_to_path();
 
-    let mut rustflags = vec![format!(
-        "-Zcodegen-backend={}",
-        rustc_codegen_nvvm.display(),
-    )];
+    let mut rustflags = vec![
+        format!("-Zcodegen-backend={}", rustc_codegen_nvvm.display()),
+        // rustc does not make local copies of `#[inline]` functions in every CGU at opt-level=0, which
+        // means `#[inline(always)]` functions from other CGUs or crates (such as cuda_std's intrinsic wrappers)
+        // would only be declared and could not be inlined. Those wrappers sometimes rely on being inlined, so force
+        // rustc to instantiate them locally.
+        "-Zinline-in-all-cgus=yes".to_string(),
+    ];
 
     if let Some(emit) = &builder.emit {
         let string = match emit {
             EmitOption::LlvmIr => "llvm-ir",
             EmitOption::Bitcode => "llvm-bc",
         };
-        rustflags.push(format!("--emit={}", string));
+        // still emit the final PTX file (`link`) along with the requested IR.
+        rustflags.push(format!("--emit={},link", string));
     }
 
     let mut llvm_args = vec![NvvmOption::Arch(builder.arch).to_string()];
 
-    if !builder.nvvm_opts {
+    if !builder.nvvm_opts || builder.debug_info {
         llvm_args.push("-opt=0".to_string());
     }
 
+    if builder.debug_info {
+        rustflags.push("-Cdebuginfo=2".to_string());
+        llvm_args.push("-g".to_string());
+    }
+
+    // libnvvm only emits line info for functions which have debuginfo, so this does nothing for
+    // crates built without it.
+    if builder.generate_line_info {
+        llvm_args.push("-generate-line-info".to_string());
+    }
+
     if builder.ftz {
         llvm_args.push("-ftz=1".to_string());
     }
@@ -346,6 +574,65 @@ fn invoke_rustc(builder: &CudaBuilder) -> Result<PathBuf, CudaBuilderError> {
         llvm_args.push("-fma=0".to_string());
     }
 
+    if let Some((major, minor)) = builder.required_nvvm_ir_version {
+        llvm_args.push(format!("--require-nvvm-ir-version={}.{}", major, minor));
+    }
+
+    if builder.deny_recursion {
+        llvm_args.push("--deny-recursion".to_string());
+    }
+
+    if builder.emit_device_bitcode {
+        llvm_args.push("--emit-device-bitcode".to_string());
+    }
+
+    if builder.split_kernels {
+        llvm_args.push("--split-kernels".to_string());
+    }
+
+    if let Some(bytes) = builder.local_memory_warning {
+        llvm_args.push(format!("--local-memory-warning={}", bytes));
+    }
+
+    if builder.no_unroll {
+        llvm_args.push("--no-unroll".to_string());
+    }
+
+    if builder.emit_kernel_manifest {
+        llvm_args.push("--emit-kernel-manifest".to_string());
+    }
+
+    if builder.emit_symbol_table {
+        llvm_args.push("--emit-symbol-table".to_string());
+    }
+
+    if let Some(bytes) = builder.partially_uninit_const_threshold {
+        llvm_args.push(format!("--partially-uninit-const-threshold={}", bytes));
+    }
+
+    if builder.intrinsic_report {
+        llvm_args.push("--intrinsic-report".to_string());
+    }
+
+    if builder.ptx_report {
+        llvm_args.push("--ptx-report".to_string());
+    }
+
+    if !builder.ptx_archs.is_empty() {
+        let archs = builder
+            .ptx_archs
+            .iter()
+            .map(|arch| arch.to_string())
+            .collect::<Vec<_>>();
+        llvm_args.push(format!("--ptx-archs={}", archs.join(",")));
+    }
+
+    if builder.bounds_check_trap {
+        llvm_args.push("--bounds-check-trap".to_string());
+    }
+
+    llvm_args.extend(builder.llvm_args.iter().cloned());
+
     let llvm_args = llvm_args.join(" ");
     if !llvm_args.is_empty() {
         rustflags.push(["-Cllvm-args=", &llvm_args].concat());
@@ -0,0 +1,18 @@
+//! A kernel using an instruction which needs compute_80, which `tests/codegen.rs` builds for compute_70.
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr, asm),
+    register_attr(nvvm_internal)
+)]
+#![allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+
+use cuda_std::prelude::*;
+
+#[kernel]
+pub unsafe fn warp_sum(value: u32, out: *mut u32) {
+    let sum: u32;
+    asm!("redux.sync.add.u32 {}, {}, 0xffffffff;", out(reg32) sum, in(reg32) value);
+    *out = sum;
+}
@@ -0,0 +1,347 @@
+//! Builds the kernels in `tests/kernels` and checks the PTX emitted for them.
+
+use cuda_builder::{CudaBuilder, NvvmArch};
+use std::process::Command;
+use std::sync::Once;
+
+/// Builds the test kernels once for all of the tests and returns their PTX.
+fn ptx() -> &'static str {
+    static BUILD: Once = Once::new();
+    static mut PTX: String = String::new();
+    BUILD.call_once(|| {
+        let path = CudaBuilder::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/kernels"))
+            // `st.async` and `red.async` need sm_90.
+            .arch(NvvmArch::Compute90)
+            .bounds_check_trap(true)
+            .build()
+            .expect("Failed to build the test kernels");
+        let ptx =
+            std::fs::read_to_string(path).expect("Failed to read the PTX of the test kernels");
+        unsafe { PTX = ptx };
+    });
+    unsafe { &PTX }
+}
+
+/// The PTX of the test kernel `name`, from its `.entry` to the end of its body.
+fn entry(name: &str) -> &'static str {
+    entry_in(ptx(), name)
+}
+
+/// The PTX of the kernel `name` in `ptx`, from its `.entry` to the end of its body.
+fn entry_in<'a>(ptx: &'a str, name: &str) -> &'a str {
+    let start = ptx
+        .find(&format!(".entry {}(", name))
+        .unwrap_or_else(|| panic!("No kernel named `{}` in the PTX", name));
+    let end = ptx[start..]
+        .find("\n}\n")
+        .map_or(ptx.len(), |end| start + end + 2);
+    &ptx[start..end]
+}
+
+/// The environment variable which tells [`separate_build`] which configuration of [`builder`] to build.
+const CONFIG_VAR: &str = "CODEGEN_TEST_CONFIG";
+/// The prefix of the line [`separate_build`] prints the path of the PTX file with.
+const PTX_PATH_PREFIX: &str = "codegen test ptx: ";
+
+/// The builders for the configurations which are built separately from the test kernels.
+fn builder(config: &str) -> CudaBuilder {
+    let tests = concat!(env!("CARGO_MANIFEST_DIR"), "/tests");
+    match config {
+        "arch_mismatch" => CudaBuilder::new(format!("{}/arch_mismatch", tests))
+            .arch(NvvmArch::Compute70)
+            .intrinsic_report(true),
+        "overflow_checks" => CudaBuilder::new(format!("{}/overflow_checks", tests)),
+        // `-debug-pass=Arguments` prints the passes of every pass manager LLVM runs.
+        "opt_level_0" => CudaBuilder::new(format!("{}/opt_levels", tests))
+            .release(false)
+            .llvm_arg("-debug-pass=Arguments"),
+        "opt_level_3" => CudaBuilder::new(format!("{}/opt_levels", tests))
+            .release(true)
+            .llvm_arg("-debug-pass=Arguments"),
+        _ => panic!("Unknown codegen test configuration `{}`", config),
+    }
+}
+
+/// The result of building a configuration of [`builder`] with [`build_separately`].
+struct SeparateBuild {
+    /// The PTX, `None` if the build failed.
+    ptx: Option<String>,
+    /// Everything cargo and rustc printed, including the diagnostics of the codegen.
+    stderr: String,
+}
+
+/// Builds a configuration of [`builder`] by running [`separate_build`] in a child process, so that the
+/// diagnostics can be captured and every configuration gets its own target directory.
+fn build_separately(config: &str) -> SeparateBuild {
+    let exe = std::env::current_exe().expect("Failed to get the path of the test binary");
+    // `target/<profile>/deps/codegen-<hash>` -> `target/<profile>/codegen-tests/<config>`.
+    let target_dir = exe
+        .parent()
+        .unwrap()
+        .with_file_name("codegen-tests")
+        .join(config);
+    let output = Command::new(&exe)
+        .args(&[
+            "separate_build",
+            "--exact",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(CONFIG_VAR, config)
+        .env("CARGO_TARGET_DIR", target_dir)
+        .output()
+        .expect("Failed to run the test binary");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let ptx = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix(PTX_PATH_PREFIX))
+        .map(|path| std::fs::read_to_string(path).expect("Failed to read the PTX"));
+    SeparateBuild {
+        ptx,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+}
+
+/// Builds the configuration in [`CONFIG_VAR`] when this is run by [`build_separately`], does nothing otherwise.
+#[test]
+fn separate_build() {
+    if let Ok(config) = std::env::var(CONFIG_VAR) {
+        if let Ok(path) = builder(&config).build() {
+            println!("{}{}", PTX_PATH_PREFIX, path.display());
+        }
+    }
+}
+
+#[test]
+fn red_async_is_emitted() {
+    let kernel = entry("red_async_add");
+    assert!(
+        kernel.contains(
+            "red.async.relaxed.cluster.shared::cluster.mbarrier::complete_tx::bytes.add.u32"
+        ),
+        "{}",
+        kernel
+    );
+    assert!(kernel.contains("cvta.to.shared::cluster.u64"), "{}", kernel);
+}
+
+#[test]
+fn failed_bounds_checks_print_and_trap() {
+    let kernel = entry("index_slice");
+    assert!(kernel.contains("vprintf"), "{}", kernel);
+    assert!(kernel.contains("trap;"), "{}", kernel);
+    assert!(!kernel.contains("panic_bounds_check"), "{}", kernel);
+}
+
+#[test]
+fn failed_overflow_checks_trap() {
+    let build = build_separately("overflow_checks");
+    let ptx = build.ptx.unwrap_or_else(|| panic!("{}", build.stderr));
+    let kernel = entry_in(&ptx, "checked_add");
+    assert!(kernel.contains("trap;"), "{}", kernel);
+    assert!(!kernel.contains("panicking"), "{}", kernel);
+}
+
+#[test]
+fn predicated_stores_are_emitted() {
+    let kernel = entry("store_global_if");
+    assert!(kernel.contains("@%p st.global.u32"), "{}", kernel);
+}
+
+#[test]
+fn launch_bounds_become_directives() {
+    let kernel = entry("launch_bounds");
+    assert!(kernel.contains(".maxntid 256"), "{}", kernel);
+    assert!(kernel.contains(".minnctapersm 2"), "{}", kernel);
+}
+
+#[test]
+fn float_statics_keep_their_bits() {
+    let ptx = ptx();
+    // -0.0, the smallest subnormal and a NaN with a payload, as little endian bytes.
+    let f32_bytes = "{0, 0, 0, 128, 1, 0, 0, 0, 52, 18, 192, 127}";
+    let f64_bytes =
+        "{0, 0, 0, 0, 0, 0, 0, 128, 1, 0, 0, 0, 0, 0, 0, 0, 52, 18, 0, 0, 0, 0, 248, 127}";
+    assert!(ptx.contains(f32_bytes), "{}", ptx);
+    assert!(ptx.contains(f64_bytes), "{}", ptx);
+}
+
+#[test]
+fn common_statics_are_merged() {
+    let ptx = ptx();
+    let definitions = ptx
+        .lines()
+        .filter(|line| line.starts_with('.') && line.contains("COMMON_COUNTER"))
+        .collect::<Vec<_>>();
+    assert_eq!(definitions.len(), 1, "{}", ptx);
+    assert!(definitions[0].contains(".common"), "{}", ptx);
+}
+
+#[test]
+fn large_statics_are_emitted_as_one_array() {
+    let ptx = ptx();
+    // the PTX only has the declaration of the static followed by its 4 MB of bytes, don't print all of it.
+    let start = ptx
+        .find("LARGE_TABLE[")
+        .expect("No definition of LARGE_TABLE in the PTX");
+    let line_end = ptx[start..].find('\n').map_or(ptx.len(), |end| start + end);
+    let line = &ptx[start..line_end];
+    let len = line.len().min(64);
+    assert!(
+        line.starts_with("LARGE_TABLE[4194304] = {7, 7, "),
+        "{}",
+        &line[..len]
+    );
+    assert!(line.ends_with("7, 7};"), "{}", &line[line.len() - len..]);
+}
+
+#[test]
+fn shared_atomics_stay_in_shared_memory() {
+    let kernel = entry("shared_atomics");
+    assert!(kernel.contains("atom.shared.add.u32"), "{}", kernel);
+    assert!(kernel.contains("atom.shared.exch.b64"), "{}", kernel);
+}
+
+#[test]
+fn over_aligned_params_keep_their_alignment() {
+    let kernel = entry("over_aligned_param");
+    assert!(
+        kernel.contains(".param .align 16 .b8 over_aligned_param_param_0[16]"),
+        "{}",
+        kernel
+    );
+    // pointer params are plain `.u64`s, the alignment of what they point to is not part of the param.
+    assert!(
+        kernel.contains(".param .u64 over_aligned_param_param_2\n"),
+        "{}",
+        kernel
+    );
+}
+
+#[test]
+fn rounded_fmas_use_the_rounding_modifier() {
+    let kernel = entry("fma_toward_zero");
+    assert!(kernel.contains("fma.rz.f32"), "{}", kernel);
+}
+
+#[test]
+fn instructions_newer_than_the_target_are_errors() {
+    let build = build_separately("arch_mismatch");
+    assert!(build.ptx.is_none(), "{}", build.stderr);
+    assert!(
+        build
+            .stderr
+            .contains("`redux.sync.add.u32` needs compute_80, but the target is compute_70"),
+        "{}",
+        build.stderr
+    );
+    assert!(
+        build.stderr.contains("compile for compute_80 or newer"),
+        "{}",
+        build.stderr
+    );
+}
+
+#[test]
+fn swap_bytes_is_prmt() {
+    // u16 swaps the two low bytes of the zero-extended value.
+    let kernel = entry("swap_bytes_u16");
+    assert_eq!(kernel.matches("prmt.b32").count(), 1, "{}", kernel);
+    assert!(kernel.contains(", 0, 0x4401;"), "{}", kernel);
+    // u32 reverses the four bytes.
+    let kernel = entry("swap_bytes_u32");
+    assert_eq!(kernel.matches("prmt.b32").count(), 1, "{}", kernel);
+    assert!(kernel.contains(", 0, 0x123;"), "{}", kernel);
+    // u64 reverses the bytes of both halves and swaps them.
+    let kernel = entry("swap_bytes_u64");
+    assert_eq!(kernel.matches(", 0, 0x123;").count(), 2, "{}", kernel);
+    assert_eq!(kernel.matches("prmt.b32").count(), 2, "{}", kernel);
+}
+
+#[test]
+fn int_min_max_are_single_instructions() {
+    let kernel = entry("int_min_max");
+    assert!(kernel.contains("min.s32"), "{}", kernel);
+    assert!(kernel.contains("max.u64"), "{}", kernel);
+    assert!(!kernel.contains("selp"), "{}", kernel);
+}
+
+#[test]
+fn unused_atomic_results_are_red() {
+    let kernel = entry("count_unused");
+    assert!(kernel.contains("red.global.add.u32"), "{}", kernel);
+    assert!(!kernel.contains("atom."), "{}", kernel);
+}
+
+#[test]
+fn sign_selects_are_slct() {
+    let kernel = entry("sign_select");
+    assert!(kernel.contains("slct.f32.f32"), "{}", kernel);
+    assert!(!kernel.contains("selp"), "{}", kernel);
+}
+
+#[test]
+fn float_classification_is_testp() {
+    let kernel = entry("float_classes");
+    assert!(kernel.contains("testp.notanumber.f32"), "{}", kernel);
+    assert!(kernel.contains("testp.infinite.f32"), "{}", kernel);
+    assert!(kernel.contains("testp.finite.f32"), "{}", kernel);
+    assert!(!kernel.contains("abs.f32"), "{}", kernel);
+}
+
+/// The amount of passes in the `Pass Arguments:` lines `-debug-pass=Arguments` printed.
+fn pass_count(stderr: &str) -> usize {
+    stderr
+        .lines()
+        .filter_map(|line| line.strip_prefix("Pass Arguments:"))
+        .map(|passes| passes.split_whitespace().count())
+        .sum()
+}
+
+#[test]
+fn only_the_always_inliner_runs_without_optimizations() {
+    let build = build_separately("opt_level_0");
+    assert!(build.ptx.is_some(), "{}", build.stderr);
+    let unoptimized = pass_count(&build.stderr);
+    let build = build_separately("opt_level_3");
+    assert!(build.ptx.is_some(), "{}", build.stderr);
+    let optimized = pass_count(&build.stderr);
+
+    assert!(unoptimized > 0, "no passes were printed");
+    assert!(
+        unoptimized * 4 < optimized,
+        "{} passes ran at opt-level=0 and {} at opt-level=3",
+        unoptimized,
+        optimized
+    );
+}
+
+#[test]
+fn local_copies_in_different_cgus_are_one_definition() {
+    let ptx = ptx();
+    // a `.func` is a definition if its body follows, and a declaration if it ends with `;`.
+    let definitions = ptx
+        .match_indices(".func")
+        .map(|(start, _)| &ptx[start..])
+        .filter(|func| {
+            let end = func.find(|c| c == '{' || c == ';').unwrap_or(func.len());
+            func[..end].contains("12dedup_helper") && func[end..].starts_with('{')
+        })
+        .count();
+    assert_eq!(definitions, 1, "{}", ptx);
+}
+
+#[test]
+fn zero_sized_mutable_allocations_share_a_global() {
+    let ptx = ptx();
+    // the initializers point to the same global if they are the same.
+    let initializer = |name: &str| {
+        let line = ptx
+            .lines()
+            .find(|line| line.starts_with('.') && line.contains(&format!(" {}[", name)))
+            .unwrap_or_else(|| panic!("No global named `{}` in the PTX", name));
+        line.split_once(" = ").map_or("", |(_, init)| init)
+    };
+    assert!(!initializer("EMPTY_A").is_empty(), "{}", ptx);
+    assert_eq!(initializer("EMPTY_A"), initializer("EMPTY_B"), "{}", ptx);
+}
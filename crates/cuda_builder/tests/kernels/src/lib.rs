@@ -0,0 +1,182 @@
+//! Kernels whose PTX is checked by the tests in `tests/codegen.rs`.
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr, linkage),
+    register_attr(nvvm_internal)
+)]
+#![allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use cuda_std::prelude::*;
+
+#[kernel]
+pub unsafe fn red_async_add(dst: *mut u32, value: u32, mbarrier: *mut u64) {
+    cuda_std::tma::red_async_add_u32(dst, value, mbarrier);
+}
+
+#[kernel]
+pub unsafe fn index_slice(a: &[u32], i: usize, out: *mut u32) {
+    *out = a[i];
+}
+
+#[kernel]
+pub unsafe fn store_global_if(out: *mut f32, len: usize, val: f32) {
+    let idx = thread::index_1d() as usize;
+    cuda_std::ptr::store_global_if(idx < len, out.wrapping_add(idx), val);
+}
+
+#[kernel(launch_bounds(256, 2))]
+pub unsafe fn launch_bounds(out: *mut u32) {
+    *out = thread::thread_idx_x();
+}
+
+static F32_BITS: [f32; 3] =
+    unsafe { core::mem::transmute::<[u32; 3], _>([0x8000_0000, 0x0000_0001, 0x7fc0_1234]) };
+static F64_BITS: [f64; 3] = unsafe {
+    core::mem::transmute::<[u64; 3], _>([
+        0x8000_0000_0000_0000,
+        0x0000_0000_0000_0001,
+        0x7ff8_0000_0000_1234,
+    ])
+};
+
+#[kernel]
+pub unsafe fn float_statics(i: usize, out_f32: *mut f32, out_f64: *mut f64) {
+    *out_f32 = F32_BITS[i];
+    *out_f64 = F64_BITS[i];
+}
+
+// two tentative definitions of the same symbol in different modules (and so different CGUs).
+mod common_a {
+    #[export_name = "COMMON_COUNTER"]
+    #[cfg_attr(target_os = "cuda", linkage = "common")]
+    pub static mut COUNTER: u32 = 0;
+}
+
+mod common_b {
+    #[export_name = "COMMON_COUNTER"]
+    #[cfg_attr(target_os = "cuda", linkage = "common")]
+    pub static mut COUNTER: u32 = 0;
+}
+
+#[kernel]
+pub unsafe fn common_statics() {
+    common_a::COUNTER += 1;
+    common_b::COUNTER += 1;
+}
+
+#[no_mangle]
+static LARGE_TABLE: [u8; 4 << 20] = [7; 4 << 20];
+
+#[kernel]
+pub unsafe fn large_static(i: usize, out: *mut u8) {
+    *out = LARGE_TABLE[i];
+}
+
+#[kernel]
+pub unsafe fn shared_atomics(value: *mut u32, out_count: *mut u32, out: *mut *mut u32) {
+    let counter = &*cuda_std::shared_array!([AtomicU32; 1]).cast::<AtomicU32>();
+    *out_count = counter.fetch_add(1, Ordering::Relaxed);
+    let slot = &*cuda_std::shared_array!([AtomicPtr<u32>; 1]).cast::<AtomicPtr<u32>>();
+    *out = slot.swap(value, Ordering::Relaxed);
+}
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct Aligned16 {
+    pub x: u32,
+}
+
+#[kernel]
+pub unsafe fn over_aligned_param(a: Aligned16, b: u32, out: *mut u32) {
+    *out = a.x + b;
+}
+
+#[kernel]
+pub unsafe fn fma_toward_zero(a: f32, b: f32, c: f32, out: *mut f32) {
+    use cuda_std::{FloatExt, RoundingMode};
+    *out = a.mul_add_rounded(b, c, RoundingMode::TowardZero);
+}
+
+#[kernel]
+pub unsafe fn swap_bytes_u16(x: u16, out: *mut u16) {
+    *out = x.swap_bytes();
+}
+
+#[kernel]
+pub unsafe fn swap_bytes_u32(x: u32, out: *mut u32) {
+    *out = x.swap_bytes();
+}
+
+#[kernel]
+pub unsafe fn swap_bytes_u64(x: u64, out: *mut u64) {
+    *out = x.swap_bytes();
+}
+
+#[kernel]
+pub unsafe fn int_min_max(a: i32, b: i32, c: u64, d: u64, out_min: *mut i32, out_max: *mut u64) {
+    *out_min = a.min(b);
+    *out_max = c.max(d);
+}
+
+#[kernel]
+pub unsafe fn count_unused(counter: *mut u32) {
+    let counter = &*counter.cast::<AtomicU32>();
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+#[kernel]
+pub unsafe fn sign_select(x: f32, a: f32, b: f32, out: *mut f32) {
+    *out = if x >= 0.0 { a } else { b };
+}
+
+#[kernel]
+pub unsafe fn float_classes(
+    x: f32,
+    is_nan: *mut bool,
+    is_infinite: *mut bool,
+    is_finite: *mut bool,
+) {
+    *is_nan = x.is_nan();
+    *is_infinite = x.is_infinite();
+    *is_finite = x.is_finite();
+}
+
+/// `#[inline]` functions get a local copy in every CGU using them, the kernels store its address so that
+/// it is not inlined.
+#[inline]
+pub fn dedup_helper<T: core::ops::Mul<Output = T> + Copy>(x: T) -> T {
+    x * x
+}
+
+mod dedup_a {
+    use cuda_std::prelude::*;
+
+    #[kernel]
+    pub unsafe fn dedup_helper_a(out: *mut fn(f32) -> f32) {
+        *out = super::dedup_helper::<f32>;
+    }
+}
+
+mod dedup_b {
+    use cuda_std::prelude::*;
+
+    #[kernel]
+    pub unsafe fn dedup_helper_b(out: *mut fn(f32) -> f32) {
+        *out = super::dedup_helper::<f32>;
+    }
+}
+
+// the empty arrays are mutable allocations, which are only shared because they are zero-sized.
+#[no_mangle]
+static mut EMPTY_A: &mut [u32] = &mut [];
+#[no_mangle]
+static mut EMPTY_B: &mut [u32] = &mut [];
+
+#[kernel]
+pub unsafe fn empty_statics(out: *mut *const u32) {
+    *out = EMPTY_A.as_ptr();
+    *out.add(1) = EMPTY_B.as_ptr();
+}
@@ -0,0 +1,20 @@
+//! A kernel which `tests/codegen.rs` builds with and without optimizations to compare the passes which run.
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+#![allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+
+use cuda_std::prelude::*;
+
+#[kernel]
+pub unsafe fn sum(values: *const u32, len: usize, out: *mut u32) {
+    let mut sum = 0u32;
+    for i in 0..len {
+        sum = sum.wrapping_add(*values.add(i));
+    }
+    *out = sum;
+}
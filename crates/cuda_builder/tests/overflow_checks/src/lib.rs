@@ -0,0 +1,16 @@
+//! A kernel whose addition may overflow, which `tests/codegen.rs` builds with overflow checks.
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+#![allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+
+use cuda_std::prelude::*;
+
+#[kernel]
+pub unsafe fn checked_add(a: u32, b: u32, out: *mut u32) {
+    *out = a + b;
+}
@@ -0,0 +1,65 @@
+//! Information about the architecture the code is being compiled for.
+//!
+//! The codegen exposes the target architecture as `target_feature`s, every architecture up to and including
+//! the target architecture is enabled. For example, when compiling for `compute_75`, `compute_70` is enabled but
+//! `compute_80` is not. Code can therefore query the architecture with `#[cfg(target_feature = "compute_70")]`,
+//! or use [`CUDA_ARCH`] in regular code, which is a constant and therefore lets unused branches be removed entirely.
+//!
+//! The architecture is set with `-arch=compute_XX` in the llvm-args (which `cuda_builder` does), or with
+//! `-Ctarget-cpu=sm_XX` if there is no `-arch`.
+//!
+//! Architecture-specific variants such as `compute_90a` also enable their generic architecture, so
+//! `compute_90` is enabled when compiling for `compute_90a`. Their own feature (`compute_90a`) is only enabled
+//! when compiling for exactly that architecture, because newer architectures do not have its features.
+
+/// The compute capability being compiled for, in the same format as CUDA C's `__CUDA_ARCH__`, for example `750`
+/// for `compute_75`. This is `0` when not compiling for the GPU.
+///
+/// ```ignore
+/// if CUDA_ARCH >= 800 {
+///     // use sm_80+ instructions, this branch is not codegenned when compiling for older architectures.
+/// }
+/// ```
+///
+/// Because it is a constant it can also be used in const contexts, for example to size arrays per architecture:
+///
+/// ```ignore
+/// const TILE: usize = if CUDA_ARCH >= 800 { 128 } else { 64 };
+///
+/// let tile_data = shared_array!([f32; TILE * TILE]);
+/// ```
+pub const CUDA_ARCH: u32 = compute_capability() * 10;
+
+/// Returns the compute capability being compiled for as `major * 10 + minor`, for example `61` for `compute_61`.
+/// This is `0` when not compiling for the GPU.
+pub const fn compute_capability() -> u32 {
+    if cfg!(target_feature = "compute_90") {
+        90
+    } else if cfg!(target_feature = "compute_80") {
+        80
+    } else if cfg!(target_feature = "compute_75") {
+        75
+    } else if cfg!(target_feature = "compute_72") {
+        72
+    } else if cfg!(target_feature = "compute_70") {
+        70
+    } else if cfg!(target_feature = "compute_62") {
+        62
+    } else if cfg!(target_feature = "compute_61") {
+        61
+    } else if cfg!(target_feature = "compute_60") {
+        60
+    } else if cfg!(target_feature = "compute_53") {
+        53
+    } else if cfg!(target_feature = "compute_52") {
+        52
+    } else if cfg!(target_feature = "compute_50") {
+        50
+    } else if cfg!(target_feature = "compute_37") {
+        37
+    } else if cfg!(target_feature = "compute_35") {
+        35
+    } else {
+        0
+    }
+}
@@ -0,0 +1,172 @@
+//! Atomic operations which have no equivalent in [`core::sync::atomic`].
+//!
+//! Atomics such as `fetch_add` on [`AtomicU32`](core::sync::atomic::AtomicU32) are codegenned to the matching
+//! PTX `atom` instructions, this module exposes the ones which are specific to CUDA.
+
+use crate::gpu_only;
+use half::f16;
+
+macro_rules! wrapping_atomic {
+    ($($(#[$attr:meta])* $name:ident, $op:literal);* $(;)?) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(ptr: *mut u32, val: u32) -> u32 {
+                let old: u32;
+                asm!(
+                    concat!($op, " {}, [{}], {};"),
+                    out(reg32) old,
+                    in(reg64) ptr,
+                    in(reg32) val,
+                    options(nostack)
+                );
+                old
+            }
+        )*
+    };
+}
+
+wrapping_atomic! {
+    /// Atomically increments the value at `ptr`, wrapping around to `0` once it reaches `val`, and returns
+    /// the old value (`atom.inc.u32`). That is, it stores `if old >= val { 0 } else { old + 1 }`.
+    /// This is CUDA C's `atomicInc`.
+    ///
+    /// This is useful for indices into ring buffers of `val + 1` elements, which would otherwise need a
+    /// compare and swap loop.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and writes and aligned to 4 bytes, and every other access to it at the
+    /// same time must be atomic.
+    atomic_inc, "atom.inc.u32";
+    /// Atomically decrements the value at `ptr`, wrapping around to `val` once it reaches `0` or if it is greater
+    /// than `val`, and returns the old value (`atom.dec.u32`). That is, it stores
+    /// `if old == 0 || old > val { val } else { old - 1 }`. This is CUDA C's `atomicDec`.
+    ///
+    /// # Safety
+    ///
+    /// The same rules as [`atomic_inc`] apply.
+    atomic_dec, "atom.dec.u32";
+    /// Atomically increments a value in global memory with wraparound (`atom.global.inc.u32`), see [`atomic_inc`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory, and the same rules as [`atomic_inc`] apply.
+    atomic_global_inc, "atom.global.inc.u32";
+    /// Atomically decrements a value in global memory with wraparound (`atom.global.dec.u32`), see [`atomic_dec`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory, and the same rules as [`atomic_inc`] apply.
+    atomic_global_dec, "atom.global.dec.u32";
+}
+
+/// Atomically compares and swaps the `u32` at `ptr` in global memory, returning the old value.
+#[gpu_only]
+#[inline(always)]
+unsafe fn cas_global_u32(ptr: *mut u32, expected: u32, new: u32) -> u32 {
+    let old: u32;
+    asm!(
+        "atom.global.cas.b32 {}, [{}], {}, {};",
+        out(reg32) old,
+        in(reg64) ptr,
+        in(reg32) expected,
+        in(reg32) new,
+        options(nostack)
+    );
+    old
+}
+
+/// Atomically adds `f(old)` to the `u32` at `ptr` in global memory using a compare and swap loop,
+/// returning the old value. Only used for half atomics before `compute_70`.
+#[gpu_only]
+#[inline(always)]
+unsafe fn cas_loop_global_u32(ptr: *mut u32, f: impl Fn(u32) -> u32) -> u32 {
+    let mut old = ptr.read_volatile();
+    loop {
+        let prev = cas_global_u32(ptr, old, f(old));
+        if prev == old {
+            return old;
+        }
+        old = prev;
+    }
+}
+
+/// Adds two halves in `f32` and rounds the sum to a half. The result is correctly rounded even though the sum in
+/// `f32` may be rounded too, because `f32` has at least `2 * 11 + 2` significand bits (24), which makes the double
+/// rounding innocuous.
+#[inline(always)]
+fn add_f16(a: f16, b: f16) -> f16 {
+    f16::from_f32(a.to_f32() + b.to_f32())
+}
+
+/// Atomically adds `val` to the half in global memory at `ptr` and returns the old value
+/// (`atom.global.add.noftz.f16`). This is CUDA C's `atomicAdd` on `__half`.
+///
+/// This is mostly useful for mixed precision accumulation. Before `compute_70` there is no half atomic,
+/// so this is done with a compare and swap loop on the 32 bit word containing the half, which is a lot
+/// slower when many threads add to the same location.
+///
+/// # Safety
+///
+/// `ptr` must point to global memory, be valid for reads and writes and aligned to 2 bytes, and every
+/// other access to it at the same time must be atomic. Pointing to global memory is checked in debug builds.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn atomic_global_add_f16(ptr: *mut f16, val: f16) -> f16 {
+    crate::debug_assert!(crate::ptr::is_global(ptr));
+    if crate::arch::CUDA_ARCH < 700 {
+        let word = (ptr as usize & !3) as *mut u32;
+        let shift = (ptr as usize & 2) as u32 * 8;
+        let old = cas_loop_global_u32(word, |old| {
+            let sum = add_f16(f16::from_bits((old >> shift) as u16), val);
+            (old & !(0xffff << shift)) | ((sum.to_bits() as u32) << shift)
+        });
+        return f16::from_bits((old >> shift) as u16);
+    }
+    let old: u16;
+    asm!(
+        "atom.global.add.noftz.f16 {}, [{}], {};",
+        out(reg16) old,
+        in(reg64) ptr,
+        in(reg16) val.to_bits(),
+        options(nostack)
+    );
+    f16::from_bits(old)
+}
+
+/// Atomically adds both halves of `val` to the pair of halves in global memory at `ptr` and returns the
+/// old values (`atom.global.add.noftz.f16x2`). This is CUDA C's `atomicAdd` on `__half2`.
+///
+/// Each half is added on its own, but both additions happen as one atomic operation. Before `compute_70`
+/// this is done with a compare and swap loop.
+///
+/// # Safety
+///
+/// `ptr` must point to global memory, be valid for reads and writes and aligned to 4 bytes, and every
+/// other access to it at the same time must be atomic. Pointing to global memory is checked in debug builds.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn atomic_global_add_f16x2(ptr: *mut [f16; 2], val: [f16; 2]) -> [f16; 2] {
+    crate::debug_assert!(crate::ptr::is_global(ptr));
+    // the first half is in the low bits of the pair.
+    let pack = |[lo, hi]: [f16; 2]| lo.to_bits() as u32 | (hi.to_bits() as u32) << 16;
+    let unpack = |x: u32| [f16::from_bits(x as u16), f16::from_bits((x >> 16) as u16)];
+    if crate::arch::CUDA_ARCH < 700 {
+        let old = cas_loop_global_u32(ptr.cast(), |old| {
+            let [lo, hi] = unpack(old);
+            pack([add_f16(lo, val[0]), add_f16(hi, val[1])])
+        });
+        return unpack(old);
+    }
+    let old: u32;
+    asm!(
+        "atom.global.add.noftz.f16x2 {}, [{}], {};",
+        out(reg32) old,
+        in(reg64) ptr,
+        in(reg32) pack(val),
+        options(nostack)
+    );
+    unpack(old)
+}
@@ -0,0 +1,165 @@
+//! Bit manipulation functions corresponding to the CUDA C integer intrinsics.
+//!
+//! Unlike the raw libdevice functions in [`intrinsics`](crate::intrinsics), these are safe, take unsigned
+//! integers, and are lowered by the codegen directly to the matching PTX instructions. Those which are
+//! `const` also work on the CPU and in const contexts, constant inputs are folded at compile time.
+
+use crate::gpu_only;
+
+/// Reverses the bit order of a 32-bit integer, bit `N` of the result is bit `31 - N` of `x` (`brev.b32`).
+#[inline(always)]
+pub const fn brev(x: u32) -> u32 {
+    x.reverse_bits()
+}
+
+/// Reverses the bit order of a 64-bit integer, bit `N` of the result is bit `63 - N` of `x` (`brev.b64`).
+#[inline(always)]
+pub const fn brevll(x: u64) -> u64 {
+    x.reverse_bits()
+}
+
+/// Reverses the bit order of a `usize`, this is [`brevll`] on 64-bit targets and [`brev`] on 32-bit targets.
+#[inline(always)]
+pub const fn brev_usize(x: usize) -> usize {
+    x.reverse_bits()
+}
+
+/// Finds the position of the least significant set bit of a 32-bit integer, starting at `1` for the
+/// least significant bit. Returns `0` if no bit is set. This is CUDA C's `__ffs`.
+#[inline(always)]
+pub const fn ffs(x: u32) -> u32 {
+    if x == 0 {
+        0
+    } else {
+        x.trailing_zeros() + 1
+    }
+}
+
+/// Finds the position of the least significant set bit of a 64-bit integer, starting at `1` for the
+/// least significant bit. Returns `0` if no bit is set. This is CUDA C's `__ffsll`.
+#[inline(always)]
+pub const fn ffsll(x: u64) -> u32 {
+    if x == 0 {
+        0
+    } else {
+        x.trailing_zeros() + 1
+    }
+}
+
+/// Finds the position of the `offset`-th set bit of `mask`, counting from bit `base` (`fns.b32`).
+/// This is CUDA C's `__fns`.
+///
+/// A positive `offset` searches towards the most significant bit and a negative one towards the least
+/// significant bit. An `offset` of `1` or `-1` finds the closest set bit, including bit `base` itself, while
+/// an `offset` of `0` returns `base` if it is set. Returns `None` if there is no such bit.
+///
+/// This is handy for iterating over set bits, for example `fns(mask, 0, n + 1)` is the position of the
+/// `n`-th set bit of `mask`.
+///
+/// # Panics
+///
+/// Panics if `base` is not in the range of `0..=31`.
+#[gpu_only]
+#[inline(always)]
+pub fn fns(mask: u32, base: u32, offset: i32) -> Option<u32> {
+    assert!(base < 32, "fns base must be in the range of 0..=31");
+    let pos: u32;
+    unsafe {
+        asm!(
+            "fns.b32 {}, {}, {}, {};",
+            out(reg32) pos,
+            in(reg32) mask,
+            in(reg32) base,
+            in(reg32) offset
+        )
+    }
+    if pos == u32::MAX {
+        None
+    } else {
+        Some(pos)
+    }
+}
+
+/// A selector for [`byte_perm`], which picks 4 bytes out of the 8 bytes of two `u32`s.
+///
+/// The bytes of the inputs `a` and `b` are numbered `0` to `7`, `0` being the least significant byte
+/// of `a` and `7` the most significant byte of `b`. Byte `i` of the result is the input byte at the `i`-th
+/// index of the permutation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BytePermutation(u32);
+
+impl BytePermutation {
+    /// Creates a permutation from the input byte indices of each byte of the result, from the least
+    /// significant byte to the most significant byte.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any index is not in the range of `0..=7`.
+    pub const fn new(bytes: [u8; 4]) -> Self {
+        assert!(
+            bytes[0] < 8 && bytes[1] < 8 && bytes[2] < 8 && bytes[3] < 8,
+            "byte indices must be in the range of 0..=7"
+        );
+        Self(
+            bytes[0] as u32
+                | (bytes[1] as u32) << 4
+                | (bytes[2] as u32) << 8
+                | (bytes[3] as u32) << 12,
+        )
+    }
+
+    /// Returns the bytes of `a` unchanged.
+    pub const fn identity() -> Self {
+        Self::new([0, 1, 2, 3])
+    }
+
+    /// Reverses the bytes of `a`, which swaps its endianness.
+    pub const fn reverse() -> Self {
+        Self::new([3, 2, 1, 0])
+    }
+
+    /// Repeats byte `byte` of the inputs in every byte of the result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `byte` is not in the range of `0..=7`.
+    pub const fn broadcast(byte: u8) -> Self {
+        Self::new([byte; 4])
+    }
+
+    /// Takes the two low bytes of `a` followed by the two low bytes of `b`, interleaving the low halves.
+    pub const fn low_halves() -> Self {
+        Self::new([0, 1, 4, 5])
+    }
+
+    /// Takes the two high bytes of `a` followed by the two high bytes of `b`, interleaving the high halves.
+    pub const fn high_halves() -> Self {
+        Self::new([2, 3, 6, 7])
+    }
+
+    /// The raw selector given to `prmt.b32`.
+    pub const fn selector(self) -> u32 {
+        self.0
+    }
+}
+
+/// Picks 4 bytes out of the 8 bytes of `a` and `b` according to `permutation` (`prmt.b32`).
+/// This is CUDA C's `__byte_perm`.
+///
+/// For example, `byte_perm(x, 0, BytePermutation::reverse())` reverses the bytes of `x`.
+#[gpu_only]
+#[inline(always)]
+pub fn byte_perm(a: u32, b: u32, permutation: BytePermutation) -> u32 {
+    let out: u32;
+    unsafe {
+        asm!(
+            "prmt.b32 {}, {}, {}, {};",
+            out(reg32) out,
+            in(reg32) a,
+            in(reg32) b,
+            in(reg32) permutation.selector(),
+            options(pure, nomem, nostack)
+        );
+    }
+    out
+}
@@ -0,0 +1,74 @@
+//! Functions that work over whole thread blocks.
+//!
+//! These combine the warp-level functions of [`warp`](crate::warp) with shared memory and
+//! [`sync_threads`] to work across every thread of a block. Thread blocks of any size and
+//! shape are supported, threads are grouped into warps by their linear index in the block,
+//! like CUDA does.
+
+use crate::thread::{
+    block_dim_x, block_dim_y, block_dim_z, sync_threads, thread_idx_x, thread_idx_y, thread_idx_z,
+};
+
+/// Combines `value` of every thread of the block, returning the result on every thread. This
+/// is the equivalent of CUB's `BlockReduce`.
+///
+/// `warp_reduce` reduces the values of the threads of one warp given its mask, as
+/// [`warp_reduce`](crate::warp::warp_reduce) and the `warp_reduce_*` functions do. Each warp is
+/// reduced with it, then the results of every warp are reduced again by the first warp. The
+/// last warp of a block whose size is not a multiple of 32 is reduced with a mask of only the
+/// threads which exist.
+///
+/// `scratch` is used to exchange the results of the warps, it must point to shared memory with room
+/// for one `T` per warp (32 is enough for any block). This synchronizes the block three times, so
+/// the scratch memory can be reused right after this returns.
+///
+/// ```ignore
+/// let scratch = shared_array!([u32; 32]).cast::<u32>();
+/// let scratch_f32 = shared_array!([f32; 32]).cast::<f32>();
+///
+/// // uses `redux.sync` on compute_80 and above.
+/// let count = block_reduce(value, scratch, warp::warp_reduce_add_u32);
+/// let sum = block_reduce(x, scratch_f32, |mask, v| {
+///     warp::warp_reduce(mask, v, |a, b| a + b)
+/// });
+/// ```
+///
+/// # Safety
+///
+/// Every thread of the block must call this with the same `scratch`, just like [`sync_threads`].
+/// `scratch` must be valid for reading and writing one `T` per warp of the block, and must not be
+/// accessed by anything else while this runs.
+#[inline(always)]
+pub unsafe fn block_reduce<T: Copy>(
+    value: T,
+    scratch: *mut T,
+    warp_reduce: impl Fn(u32, T) -> T,
+) -> T {
+    let (dim_x, dim_y) = (block_dim_x(), block_dim_y());
+    let threads = dim_x * dim_y * block_dim_z();
+    let tid = thread_idx_x() + dim_x * (thread_idx_y() + dim_y * thread_idx_z());
+    let (warp, lane) = (tid / 32, tid % 32);
+    let warps = (threads + 31) / 32;
+
+    // the mask of the first `n` lanes of a warp.
+    let lanes = |n: u32| if n >= 32 { u32::MAX } else { (1 << n) - 1 };
+
+    let partial = warp_reduce(lanes(threads - warp * 32), value);
+    if lane == 0 {
+        scratch.add(warp as usize).write(partial);
+    }
+    sync_threads();
+
+    if warp == 0 && lane < warps {
+        let total = warp_reduce(lanes(warps), scratch.add(lane as usize).read());
+        if lane == 0 {
+            scratch.write(total);
+        }
+    }
+    sync_threads();
+
+    let total = scratch.read();
+    // make sure every thread read the total before the scratch memory is reused.
+    sync_threads();
+    total
+}
@@ -0,0 +1,80 @@
+//! Thread block clusters, groups of thread blocks which are guaranteed to run at the same time on
+//! a group of multiprocessors, and can synchronize with each other. Clusters require `sm_90` or above.
+//!
+//! The size of the clusters of a kernel is set with `#[kernel(cluster_dims(x, y, z))]`, or when launching
+//! the kernel. On older architectures every block is its own cluster of a single block, so code using these
+//! functions works on every architecture.
+
+use crate::arch::CUDA_ARCH;
+use crate::gpu_only;
+
+macro_rules! cluster_sreg {
+    ($($(#[$attr:meta])* $name:ident, $reg:literal, $fallback:expr);* $(;)?) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name() -> u32 {
+                if CUDA_ARCH < 900 {
+                    return $fallback;
+                }
+                let out: u32;
+                unsafe {
+                    asm!(
+                        concat!("mov.u32 {}, ", $reg, ";"),
+                        out(reg32) out,
+                        options(pure, nomem, nostack)
+                    );
+                }
+                out
+            }
+        )*
+    };
+}
+
+cluster_sreg! {
+    /// The index of the block within its cluster, from `0` to [`cluster_size`] - 1 (`%cluster_ctarank`).
+    cluster_rank, "%cluster_ctarank", 0;
+    /// The amount of blocks in the cluster (`%cluster_nctarank`).
+    cluster_size, "%cluster_nctarank", 1;
+    /// The x index of the block within its cluster (`%cluster_ctaid.x`).
+    cluster_block_idx_x, "%cluster_ctaid.x", 0;
+    /// The y index of the block within its cluster (`%cluster_ctaid.y`).
+    cluster_block_idx_y, "%cluster_ctaid.y", 0;
+    /// The z index of the block within its cluster (`%cluster_ctaid.z`).
+    cluster_block_idx_z, "%cluster_ctaid.z", 0;
+    /// The x dimension of the cluster in blocks (`%cluster_nctaid.x`).
+    cluster_dim_x, "%cluster_nctaid.x", 1;
+    /// The y dimension of the cluster in blocks (`%cluster_nctaid.y`).
+    cluster_dim_y, "%cluster_nctaid.y", 1;
+    /// The z dimension of the cluster in blocks (`%cluster_nctaid.z`).
+    cluster_dim_z, "%cluster_nctaid.z", 1;
+    /// The x index of the cluster within the grid (`%clusterid.x`).
+    cluster_idx_x, "%clusterid.x", crate::thread::block_idx_x();
+    /// The y index of the cluster within the grid (`%clusterid.y`).
+    cluster_idx_y, "%clusterid.y", crate::thread::block_idx_y();
+    /// The z index of the cluster within the grid (`%clusterid.z`).
+    cluster_idx_z, "%clusterid.z", crate::thread::block_idx_z();
+}
+
+/// Waits until every thread of every block in the cluster has reached this point, and makes the memory
+/// accesses done before visible to the whole cluster (`barrier.cluster.arrive` followed by `barrier.cluster.wait`).
+/// This is CUDA C's `cluster.sync()`.
+///
+/// On architectures below `sm_90` this is the same as [`sync_threads`](crate::thread::sync_threads) because
+/// every cluster is a single block.
+#[gpu_only]
+#[inline(always)]
+pub fn cluster_sync() {
+    if CUDA_ARCH < 900 {
+        crate::thread::sync_threads();
+        return;
+    }
+    unsafe {
+        asm!(
+            "barrier.cluster.arrive;",
+            "barrier.cluster.wait;",
+            options(nostack)
+        );
+    }
+}
@@ -1,13 +1,31 @@
 //! Extension trait for [`f32`] and [`f64`], providing high level wrappers on top of
 //! raw libdevice intrinsics from [`intrinsics`](crate::intrinsics).
 
+use crate::gpu_only;
 use crate::intrinsics as raw;
+use half::{bf16, f16};
 
 // allows us to add new functions to the trait at any time without needing a new major version.
 mod private {
     pub trait Sealed {}
     impl Sealed for f32 {}
     impl Sealed for f64 {}
+    impl Sealed for half::f16 {}
+    impl Sealed for half::bf16 {}
+}
+
+/// The rounding mode of a floating point operation which is explicitly rounded, such as
+/// [`FloatExt::mul_add_rounded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RoundingMode {
+    /// Round to the nearest value, ties to even (`.rn`). This is the default for all operations.
+    NearestEven,
+    /// Round toward zero (`.rz`).
+    TowardZero,
+    /// Round toward positive infinity (`.rp`).
+    Up,
+    /// Round toward negative infinity (`.rm`).
+    Down,
 }
 
 /// Extension trait for [`f32`] and [`f64`] which provides high level functions for
@@ -68,6 +86,38 @@ pub trait FloatExt: Sized + private::Sealed {
     fn y1(self) -> Self;
     /// The value of the bessel function of the second kind of order n for self. `Y_n(self)`.
     fn yn(self, order: i32) -> Self;
+    /// Fused multiply-add, `(self * a) + b` with a single rounding using the given rounding mode
+    /// (`fma.rn`, `fma.rz`, `fma.rp`, or `fma.rm`).
+    fn mul_add_rounded(self, a: Self, b: Self, rounding: RoundingMode) -> Self;
+    /// The value of `self / rhs`, rounded with `rounding`.
+    ///
+    /// Plain `/` always rounds to the nearest value (`div.rn`) for `f64`. For `f32` it does too,
+    /// unless fast division is enabled with `-prec-div=0`, in which case this can be used to get
+    /// IEEE division for specific operations.
+    fn div_rounded(self, rhs: Self, rounding: RoundingMode) -> Self;
+    /// A fast approximation of `self / rhs`.
+    ///
+    /// For `f32` this is `div.approx.f32`, which has a maximum error of 2 ulp. For `f64` this multiplies
+    /// by a reciprocal approximated with `rcp.approx.ftz.f64`, which is only accurate to about 20 bits and
+    /// flushes denormals to zero. Plain `/` is never approximated for `f64`, this must be used explicitly.
+    fn div_approx(self, rhs: Self) -> Self;
+    /// The minimum of `self` and `other`, which is NaN if either of them is NaN.
+    ///
+    /// [`f32::min`] and [`f64::min`] return the other value if one of them is NaN, which is what `min.f32`
+    /// and `min.f64` do. This is instead `min.NaN.f32` on compute_80 and above, `f64` and older
+    /// architectures check for NaN explicitly. Which zero is returned for `-0.0` and `+0.0` is unspecified.
+    fn min_nan(self, other: Self) -> Self;
+    /// The maximum of `self` and `other`, which is NaN if either of them is NaN, see
+    /// [`min_nan`](Self::min_nan).
+    fn max_nan(self, other: Self) -> Self;
+    /// The value of `self` as an `f32`, rounded with `rounding` if it cannot be represented exactly
+    /// (`cvt.rn.f32.f64`, `cvt.rz.f32.f64`, etc.). `as` casts always round to the nearest value.
+    ///
+    /// For `f32` this is `self`, widening to `f64` is always exact so it needs no rounding mode.
+    fn to_f32_rounded(self, rounding: RoundingMode) -> f32;
+    /// The value of `self` as an `f16`, rounded with `rounding` if it cannot be represented exactly
+    /// (`cvt.rn.f16.f32`, `cvt.rz.f16.f32`, etc.).
+    fn to_f16_rounded(self, rounding: RoundingMode) -> f16;
 }
 
 impl FloatExt for f64 {
@@ -176,6 +226,63 @@ impl FloatExt for f64 {
     fn yn(self, order: i32) -> Self {
         unsafe { raw::yn(order, self) }
     }
+
+    fn mul_add_rounded(self, a: Self, b: Self, rounding: RoundingMode) -> Self {
+        unsafe {
+            match rounding {
+                RoundingMode::NearestEven => raw::fma_rn(self, a, b),
+                RoundingMode::TowardZero => raw::fma_rz(self, a, b),
+                RoundingMode::Up => raw::fma_ru(self, a, b),
+                RoundingMode::Down => raw::fma_rd(self, a, b),
+            }
+        }
+    }
+
+    fn div_rounded(self, rhs: Self, rounding: RoundingMode) -> Self {
+        unsafe {
+            match rounding {
+                RoundingMode::NearestEven => raw::ddiv_rn(self, rhs),
+                RoundingMode::TowardZero => raw::ddiv_rz(self, rhs),
+                RoundingMode::Up => raw::ddiv_ru(self, rhs),
+                RoundingMode::Down => raw::ddiv_rd(self, rhs),
+            }
+        }
+    }
+
+    fn div_approx(self, rhs: Self) -> Self {
+        self * rcp_approx_f64(rhs)
+    }
+
+    fn min_nan(self, other: Self) -> Self {
+        if self.is_nan() || other.is_nan() {
+            Self::NAN
+        } else {
+            self.min(other)
+        }
+    }
+
+    fn max_nan(self, other: Self) -> Self {
+        if self.is_nan() || other.is_nan() {
+            Self::NAN
+        } else {
+            self.max(other)
+        }
+    }
+
+    fn to_f32_rounded(self, rounding: RoundingMode) -> f32 {
+        unsafe {
+            match rounding {
+                RoundingMode::NearestEven => raw::double2float_rn(self),
+                RoundingMode::TowardZero => raw::double2float_rz(self),
+                RoundingMode::Up => raw::double2float_ru(self),
+                RoundingMode::Down => raw::double2float_rd(self),
+            }
+        }
+    }
+
+    fn to_f16_rounded(self, rounding: RoundingMode) -> f16 {
+        f16::from_bits(cvt_f16_f64(self, rounding))
+    }
 }
 
 impl FloatExt for f32 {
@@ -283,4 +390,260 @@ impl FloatExt for f32 {
     fn yn(self, order: i32) -> Self {
         unsafe { raw::ynf(order, self) }
     }
+
+    fn mul_add_rounded(self, a: Self, b: Self, rounding: RoundingMode) -> Self {
+        unsafe {
+            match rounding {
+                RoundingMode::NearestEven => raw::fmaf_rn(self, a, b),
+                RoundingMode::TowardZero => raw::fmaf_rz(self, a, b),
+                RoundingMode::Up => raw::fmaf_ru(self, a, b),
+                RoundingMode::Down => raw::fmaf_rd(self, a, b),
+            }
+        }
+    }
+
+    fn div_rounded(self, rhs: Self, rounding: RoundingMode) -> Self {
+        unsafe {
+            match rounding {
+                RoundingMode::NearestEven => raw::fdiv_rn(self, rhs),
+                RoundingMode::TowardZero => raw::fdiv_rz(self, rhs),
+                RoundingMode::Up => raw::fdiv_ru(self, rhs),
+                RoundingMode::Down => raw::fdiv_rd(self, rhs),
+            }
+        }
+    }
+
+    fn div_approx(self, rhs: Self) -> Self {
+        unsafe { raw::fast_fdividef(self, rhs) }
+    }
+
+    fn min_nan(self, other: Self) -> Self {
+        if crate::arch::CUDA_ARCH < 800 {
+            return if self.is_nan() || other.is_nan() {
+                Self::NAN
+            } else {
+                self.min(other)
+            };
+        }
+        min_nan_f32(self, other)
+    }
+
+    fn max_nan(self, other: Self) -> Self {
+        if crate::arch::CUDA_ARCH < 800 {
+            return if self.is_nan() || other.is_nan() {
+                Self::NAN
+            } else {
+                self.max(other)
+            };
+        }
+        max_nan_f32(self, other)
+    }
+
+    fn to_f32_rounded(self, _rounding: RoundingMode) -> f32 {
+        self
+    }
+
+    fn to_f16_rounded(self, rounding: RoundingMode) -> f16 {
+        f16::from_bits(cvt_f16_f32(self, rounding))
+    }
+}
+
+// libdevice does not have an approximate f64 reciprocal.
+#[gpu_only]
+#[inline(always)]
+fn rcp_approx_f64(x: f64) -> f64 {
+    let out: f64;
+    unsafe {
+        asm!(
+            "rcp.approx.ftz.f64 {}, {};",
+            out(reg64) out,
+            in(reg64) x,
+            options(pure, nomem, nostack)
+        );
+    }
+    out
+}
+
+// `min.NaN` and `max.NaN` only exist for f32 and f16, and libdevice does not use them.
+macro_rules! nan_min_max {
+    ($($name:ident, $op:literal);* $(;)?) => {
+        $(
+            #[gpu_only]
+            #[inline(always)]
+            fn $name(x: f32, y: f32) -> f32 {
+                let out: f32;
+                unsafe {
+                    asm!(
+                        concat!($op, ".NaN.f32 {}, {}, {};"),
+                        out(reg32) out,
+                        in(reg32) x,
+                        in(reg32) y,
+                        options(pure, nomem, nostack)
+                    );
+                }
+                out
+            }
+        )*
+    };
+}
+
+nan_min_max! {
+    min_nan_f32, "min";
+    max_nan_f32, "max";
+}
+
+// libdevice only has round to nearest conversions to f16.
+macro_rules! cvt_f16 {
+    ($($name:ident, $ty:ty, $reg:ident, $src:literal);* $(;)?) => {
+        $(
+            #[gpu_only]
+            #[inline(always)]
+            fn $name(x: $ty, rounding: RoundingMode) -> u16 {
+                let out: u16;
+                macro_rules! cvt {
+                    ($mode:literal) => {
+                        asm!(
+                            concat!("cvt.", $mode, ".f16.", $src, " {}, {};"),
+                            out(reg16) out,
+                            in($reg) x,
+                            options(pure, nomem, nostack)
+                        )
+                    };
+                }
+                unsafe {
+                    match rounding {
+                        RoundingMode::NearestEven => cvt!("rn"),
+                        RoundingMode::TowardZero => cvt!("rz"),
+                        RoundingMode::Up => cvt!("rp"),
+                        RoundingMode::Down => cvt!("rm"),
+                    }
+                }
+                out
+            }
+        )*
+    };
+}
+
+cvt_f16! {
+    cvt_f16_f32, f32, reg32, "f32";
+    cvt_f16_f64, f64, reg64, "f64";
+}
+
+/// Extension trait for [`f32`] which provides fast approximations of transcendental functions, the same
+/// as CUDA's `__expf`, `__powf`, etc. These are built on top of the `ex2.approx` and `lg2.approx`
+/// instructions, which are much faster than the precise functions like [`f32::powf`], but have a larger
+/// error, especially for large inputs. Most denormal inputs and outputs are flushed to zero.
+///
+/// The precise std functions are never replaced with these, they must be used explicitly.
+pub trait FastMathExt: Sized + private::Sealed {
+    /// A fast approximation of `e^self`, `__expf`.
+    fn exp_approx(self) -> Self;
+    /// A fast approximation of `10^self`, `__exp10f`.
+    fn exp10_approx(self) -> Self;
+    /// A fast approximation of the natural logarithm of self, `__logf`.
+    fn ln_approx(self) -> Self;
+    /// A fast approximation of the base 2 logarithm of self, `__log2f`.
+    fn log2_approx(self) -> Self;
+    /// A fast approximation of the base 10 logarithm of self, `__log10f`.
+    fn log10_approx(self) -> Self;
+    /// A fast approximation of `self^n`, computed as `2^(n * log2(self))`, `__powf`.
+    fn powf_approx(self, n: Self) -> Self;
+}
+
+impl FastMathExt for f32 {
+    fn exp_approx(self) -> Self {
+        unsafe { raw::fast_expf(self) }
+    }
+
+    fn exp10_approx(self) -> Self {
+        unsafe { raw::fast_exp10f(self) }
+    }
+
+    fn ln_approx(self) -> Self {
+        unsafe { raw::fast_logf(self) }
+    }
+
+    fn log2_approx(self) -> Self {
+        unsafe { raw::fast_log2f(self) }
+    }
+
+    fn log10_approx(self) -> Self {
+        unsafe { raw::fast_log10f(self) }
+    }
+
+    fn powf_approx(self, n: Self) -> Self {
+        unsafe { raw::fast_powf(self, n) }
+    }
+}
+
+/// Extension trait for [`f16`] and [`bf16`] which provides the sign manipulation functions of
+/// [`f32`] without converting to `f32` and back, so mixed precision code stays in half precision.
+///
+/// [`abs`](Self::abs) and [`neg`](Self::neg) are `abs.f16` and `neg.f16` on compute_53 and above, and
+/// `abs.bf16` and `neg.bf16` on compute_80 and above, older architectures flip the sign bit directly.
+/// The sign of a NaN returned by the instructions is unspecified.
+pub trait HalfExt: Sized + private::Sealed {
+    /// The absolute value of `self`.
+    fn abs(self) -> Self;
+    /// The value of `-self`.
+    fn neg(self) -> Self;
+    /// The magnitude of `self` with the sign of `sign`. There is no PTX instruction for this, so it only
+    /// takes the bits of the sign.
+    fn copysign(self, sign: Self) -> Self;
+}
+
+macro_rules! half_ext {
+    ($($ty:ident, $ptx:literal, $arch:literal, $abs:ident, $neg:ident);* $(;)?) => {
+        $(
+            impl HalfExt for $ty {
+                #[inline(always)]
+                fn abs(self) -> Self {
+                    if crate::arch::CUDA_ARCH < $arch {
+                        return $ty::from_bits(self.to_bits() & 0x7fff);
+                    }
+                    $ty::from_bits($abs(self.to_bits()))
+                }
+
+                #[inline(always)]
+                fn neg(self) -> Self {
+                    if crate::arch::CUDA_ARCH < $arch {
+                        return $ty::from_bits(self.to_bits() ^ 0x8000);
+                    }
+                    $ty::from_bits($neg(self.to_bits()))
+                }
+
+                #[inline(always)]
+                fn copysign(self, sign: Self) -> Self {
+                    $ty::from_bits((self.to_bits() & 0x7fff) | (sign.to_bits() & 0x8000))
+                }
+            }
+
+            half_op!($abs, "abs", $ptx);
+            half_op!($neg, "neg", $ptx);
+        )*
+    };
+}
+
+macro_rules! half_op {
+    ($name:ident, $op:literal, $ptx:literal) => {
+        #[gpu_only]
+        #[inline(always)]
+        fn $name(x: u16) -> u16 {
+            let out: u16;
+            unsafe {
+                asm!(
+                    concat!($op, ".", $ptx, " {}, {};"),
+                    out(reg16) out,
+                    in(reg16) x,
+                    options(pure, nomem, nostack)
+                );
+            }
+            out
+        }
+    };
+}
+
+half_ext! {
+    f16, "f16", 530, abs_f16, neg_f16;
+    bf16, "bf16", 800, abs_bf16, neg_bf16;
 }
@@ -104,3 +104,46 @@ macro_rules! assert_ne {
         }
     };
 }
+
+/// Asserts that an expression is `true` in debug builds, returning an `AssertionFailed` error to the application
+/// that launched the kernel if it is not. Like [`debug_assert!`](core::debug_assert) this checks nothing if
+/// `debug_assertions` are disabled (release builds), the expression is not even evaluated.
+///
+/// Unlike `core`'s version this does not go through the panic machinery, a failure directly calls
+/// `__assertfail`, which reports the expression, file and line and stops the kernel.
+#[macro_export]
+macro_rules! debug_assert {
+    ($cond:expr $(,)?) => {
+        if cfg!(debug_assertions) && !$cond {
+            unsafe {
+                $crate::io::__assertfail(
+                    concat!("assertion failed: ", stringify!($cond), "\0").as_ptr(),
+                    concat!(file!(), "\0").as_ptr(),
+                    line!(),
+                    "\0".as_ptr(),
+                    1,
+                )
+            };
+        }
+    };
+}
+
+/// Asserts that two expressions are equal in debug builds, see [`assert_eq!`] and [`debug_assert!`].
+#[macro_export]
+macro_rules! debug_assert_eq {
+    ($a:expr, $b:expr) => {
+        if cfg!(debug_assertions) {
+            $crate::assert_eq!($a, $b);
+        }
+    };
+}
+
+/// Asserts that two expressions are not equal in debug builds, see [`assert_ne!`] and [`debug_assert!`].
+#[macro_export]
+macro_rules! debug_assert_ne {
+    ($a:expr, $b:expr) => {
+        if cfg!(debug_assertions) {
+            $crate::assert_ne!($a, $b);
+        }
+    };
+}
@@ -24,22 +24,59 @@
 #![cfg_attr(
     any(target_arch = "nvptx", target_arch = "nvptx64"),
     no_std,
-    feature(register_attr, alloc_error_handler, asm, link_llvm_intrinsics),
+    feature(
+        register_attr,
+        alloc_error_handler,
+        asm,
+        link_llvm_intrinsics,
+        core_intrinsics
+    ),
     register_attr(nvvm_internal)
 )]
 
 extern crate alloc;
 
+/// Runs the `wait_group` instruction `$instr` with `$n` as the amount of groups which may stay pending, which
+/// must be an immediate from `0` to `7`. `$n` above `7` runs it with `0`, which waits for every group.
+#[allow(unused_macros)]
+macro_rules! wait_group {
+    ($instr:literal, $n:expr) => {
+        match $n {
+            1 => asm!(concat!($instr, " 1;"), options(nostack)),
+            2 => asm!(concat!($instr, " 2;"), options(nostack)),
+            3 => asm!(concat!($instr, " 3;"), options(nostack)),
+            4 => asm!(concat!($instr, " 4;"), options(nostack)),
+            5 => asm!(concat!($instr, " 5;"), options(nostack)),
+            6 => asm!(concat!($instr, " 6;"), options(nostack)),
+            7 => asm!(concat!($instr, " 7;"), options(nostack)),
+            _ => asm!(concat!($instr, " 0;"), options(nostack)),
+        }
+    };
+}
+
+pub mod arch;
+pub mod atomic;
+pub mod bits;
+pub mod block;
+pub mod cluster;
 pub mod float;
 #[allow(warnings)]
 pub mod intrinsics;
 pub mod io;
 pub mod mem;
 pub mod misc;
+pub mod pipeline;
+pub mod ptr;
 // WIP
 // pub mod rt;
+pub mod shared;
 pub mod thread;
+#[cfg(any(target_feature = "compute_90", doc))]
+pub mod tma;
+pub mod video;
 pub mod warp;
+#[cfg(any(target_feature = "compute_90a", doc))]
+pub mod wgmma;
 
 mod float_ext;
 
@@ -55,7 +92,9 @@ pub mod prelude {
     pub use crate::f16;
     pub use crate::kernel;
     pub use crate::thread;
-    pub use crate::{assert_eq, assert_ne, print, println};
+    pub use crate::{
+        assert_eq, assert_ne, debug_assert, debug_assert_eq, debug_assert_ne, print, println,
+    };
     pub use alloc::{
         borrow::ToOwned,
         boxed::Box,
@@ -76,7 +115,7 @@ fn alloc_handler(layout: core::alloc::Layout) -> ! {
 // despite it having no reason for doing that. It needs more debugging to see what is causing it exactly. For now we just trap.
 #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
 #[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
+fn panic(info: &core::panic::PanicInfo) -> ! {
     // use crate::prelude::*;
     // let block = thread::block_idx();
     // let thread = thread::thread_idx();
@@ -116,6 +155,34 @@ fn panic(_info: &core::panic::PanicInfo) -> ! {
 
     // // crate::println!("{}", msg);
 
+    // printing the location does not need any allocation or formatting machinery, so it does not run into the
+    // issue above. `#[track_caller]` is respected by rustc when making the location, so the location is the
+    // logical caller even if it was inlined.
+    if let Some(loc) = info.location() {
+        // the file name is not null terminated, so we pass its length with `%.*s`.
+        #[repr(C)]
+        struct PanicLocation {
+            file_len: u32,
+            file: *const u8,
+            line: u32,
+            col: u32,
+        }
+
+        let args = PanicLocation {
+            file_len: loc.file().len() as u32,
+            file: loc.file().as_ptr(),
+            line: loc.line(),
+            col: loc.column(),
+        };
+
+        unsafe {
+            io::vprintf(
+                "thread panicked at %.*s:%u:%u\n\0".as_ptr(),
+                &args as *const PanicLocation as *const core::ffi::c_void,
+            );
+        }
+    }
+
     extern "C" {
         fn __nvvm_trap() -> !;
     }
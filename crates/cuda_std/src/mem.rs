@@ -32,14 +32,18 @@ pub static GLOBAL_ALLOCATOR: CUDAAllocator = CUDAAllocator;
 
 /// Returns the amount of shared memory that has been dynamically allocated
 /// by the caller of the kernel for every thread block (CTA).
+///
+/// This reads the `%dynamic_smem_size` special register, LLVM and libnvvm have no intrinsic for it.
+/// The value is the same for the whole launch, so it can be read as often as needed.
 #[gpu_only]
 #[inline(always)]
 pub fn dynamic_smem_size() -> u32 {
     let mut out;
     unsafe {
         asm!(
-            "mov.u32 {}, %dynamic_smem_size",
-            out(reg32) out
+            "mov.u32 {}, %dynamic_smem_size;",
+            out(reg32) out,
+            options(pure, nomem, nostack)
         )
     }
     out
@@ -56,8 +60,9 @@ pub fn total_smem_size() -> u32 {
     let mut out;
     unsafe {
         asm!(
-            "mov.u32 {}, %total_smem_size",
-            out(reg32) out
+            "mov.u32 {}, %total_smem_size;",
+            out(reg32) out,
+            options(pure, nomem, nostack)
         )
     }
     out
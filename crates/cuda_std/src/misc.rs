@@ -3,12 +3,13 @@
 use crate::gpu_only;
 
 /// Suspends execution of the kernel, usually to pause at a specific point when debugging in a debugger.
+///
+/// This is `brkpt`. When compiling with debug info it has a source location like any other instruction
+/// (`.loc`), so `cuda-gdb` shows where the kernel stopped, with the caller of this inlined function as its frame.
 #[gpu_only]
 #[inline(always)]
 pub fn breakpoint() {
-    unsafe {
-        asm!("brkpt");
-    }
+    unsafe { core::intrinsics::breakpoint() }
 }
 
 /// Increments a hardware counter between `0` and `7` (inclusive).
@@ -24,12 +25,31 @@ pub fn profiler_counter(counter: u32) {
         (0..=7).contains(&counter),
         "Profiler counter value must be in the range of 0..=7"
     );
-    unsafe {
-        asm!(
-            "pmevent {}",
-            in(reg32) counter
-        )
+    prof_trigger(counter);
+}
+
+/// Signals the performance monitor event `counter` between `0` and `15` (inclusive), the same as
+/// CUDA's `__prof_trigger`. Profilers expose events `0` to `7` as `prof_trigger_00` to `prof_trigger_07`,
+/// each event is counted once per warp.
+///
+/// `pmevent` only takes an immediate, so `counter` should be a constant, otherwise this is a
+/// branch over every event.
+///
+/// # Panics
+///
+/// Panics if `counter` is not in the range of `0..=15`.
+#[gpu_only]
+#[inline(always)]
+pub fn prof_trigger(counter: u32) {
+    macro_rules! pmevent {
+        ($($n:literal),*) => {
+            match counter {
+                $($n => unsafe { asm!(concat!("pmevent ", $n, ";")) },)*
+                _ => panic!("Performance monitor event must be in the range of 0..=15"),
+            }
+        };
     }
+    pmevent!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
 }
 
 /// Returns the value of a per-multiprocessor counter incremented on every clock cycle.
@@ -45,3 +65,96 @@ pub fn clock() -> u64 {
     }
     clock
 }
+
+/// Returns the value of a global nanosecond timer, which unlike [`clock`] is the same on every multiprocessor,
+/// so it can be used to time across blocks. The timer has a resolution of about a microsecond on most devices.
+///
+/// Every call reads the timer again, reads are never merged or reordered with each other.
+#[gpu_only]
+#[inline(always)]
+pub fn globaltimer() -> u64 {
+    extern "C" {
+        #[link_name = "llvm.nvvm.read.ptx.sreg.globaltimer"]
+        fn __nvvm_read_globaltimer() -> u64;
+    }
+    unsafe { __nvvm_read_globaltimer() }
+}
+
+/// Queries a compile-time setting of the codegen, this is the same mechanism libdevice uses to
+/// pick specialized code paths. The call is resolved to a constant during optimization, so branching
+/// on it has no runtime cost. Known names are:
+///
+/// - `__CUDA_FTZ`: `1` if denormals are flushed to zero (`-ftz=1`), `0` otherwise.
+/// - `__CUDA_PREC_DIV`: `1` if division is IEEE round-to-nearest, `0` if it is approximate (`-prec-div=0`).
+/// - `__CUDA_PREC_SQRT`: `1` if sqrt is IEEE round-to-nearest, `0` if it is approximate (`-prec-sqrt=0`).
+/// - `__CUDA_ARCH`: the compute capability being compiled for, times 10, for example `520` for `compute_52`.
+///
+/// Any other name yields `0`.
+///
+/// # Safety
+///
+/// `name` must be nul-terminated, such as `b"__CUDA_FTZ\0"`, the name is read up to the first nul.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn nvvm_reflect(name: &'static [u8]) -> i32 {
+    extern "C" {
+        fn __nvvm_reflect(name: *const u8) -> i32;
+    }
+    __nvvm_reflect(name.as_ptr())
+}
+
+macro_rules! envreg {
+    ($($name:ident, $n:literal);* $(;)?) => {
+        $(
+            #[doc = concat!("Returns the value of the environment register `%envreg", $n, "`.")]
+            ///
+            /// Environment registers are read-only registers set by the driver when launching the kernel, some
+            /// launch setups use them to pass the launch configuration. They are the same for every thread of the
+            /// launch, what they contain is not defined by CUDA and depends on the driver and the launching code.
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name() -> u32 {
+                extern "C" {
+                    #[link_name = concat!("llvm.nvvm.read.ptx.sreg.envreg", $n)]
+                    fn read_envreg() -> u32;
+                }
+                unsafe { read_envreg() }
+            }
+        )*
+    };
+}
+
+envreg! {
+    envreg0, "0";
+    envreg1, "1";
+    envreg2, "2";
+    envreg3, "3";
+    envreg4, "4";
+    envreg5, "5";
+    envreg6, "6";
+    envreg7, "7";
+    envreg8, "8";
+    envreg9, "9";
+    envreg10, "10";
+    envreg11, "11";
+    envreg12, "12";
+    envreg13, "13";
+    envreg14, "14";
+    envreg15, "15";
+    envreg16, "16";
+    envreg17, "17";
+    envreg18, "18";
+    envreg19, "19";
+    envreg20, "20";
+    envreg21, "21";
+    envreg22, "22";
+    envreg23, "23";
+    envreg24, "24";
+    envreg25, "25";
+    envreg26, "26";
+    envreg27, "27";
+    envreg28, "28";
+    envreg29, "29";
+    envreg30, "30";
+    envreg31, "31";
+}
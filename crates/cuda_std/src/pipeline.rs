@@ -0,0 +1,138 @@
+//! Asynchronous copies from global to shared memory (`cp.async`), which are hardware accelerated on `sm_80`
+//! and above.
+//!
+//! This mirrors the shape of the CUDA C++ pipeline primitives: every thread issues copies with [`memcpy_async`],
+//! batches the copies issued so far into a group with [`pipeline_commit`], then waits for groups to complete with
+//! [`pipeline_wait_prior`]. The copies are done in the background without going through registers, so other work
+//! can be done while they complete. On older architectures the copies are simply done synchronously and
+//! committing/waiting does nothing, so code using these functions works on every architecture.
+//!
+//! ```ignore
+//! // every thread copies its part of the tile.
+//! let idx = thread::thread_idx_x() as usize * PER_THREAD;
+//! memcpy_async(tile.add(idx), input.add(offset + idx), PER_THREAD);
+//! pipeline_commit();
+//! // ... do other work while the copy is in flight
+//! pipeline_wait_prior(0);
+//! thread::sync_threads();
+//! ```
+
+use crate::arch::CUDA_ARCH;
+use crate::gpu_only;
+use core::mem::size_of;
+
+macro_rules! cp_async {
+    ($($(#[$attr:meta])* $name:ident, $size:literal),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            ///
+            /// # Safety
+            ///
+            #[doc = concat!("`dst` must point to shared memory and `src` to global memory, both must be valid for ", stringify!($size))]
+            #[doc = concat!("bytes and be aligned to ", stringify!($size), " bytes. This requires `sm_80` or above.")]
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(dst: *mut u8, src: *const u8) {
+                // the codegen converts `dst` to a shared address and `src` to a global address.
+                asm!(
+                    concat!("cp.async.ca.shared.global [{}], [{}], ", stringify!($size), ";"),
+                    in(reg64) dst,
+                    in(reg64) src,
+                    options(nostack)
+                );
+            }
+        )*
+    };
+}
+
+cp_async! {
+    /// Starts an asynchronous copy of 4 bytes from global memory to shared memory (`cp.async.ca`).
+    cp_async_4, 4,
+    /// Starts an asynchronous copy of 8 bytes from global memory to shared memory (`cp.async.ca`).
+    cp_async_8, 8,
+    /// Starts an asynchronous copy of 16 bytes from global memory to shared memory (`cp.async.ca`).
+    cp_async_16, 16,
+}
+
+/// Starts copying `count` values from global memory `src` to shared memory `dst` in the background.
+/// The copy is complete once the group it is committed in (see [`pipeline_commit`]) has been waited on
+/// with [`pipeline_wait_prior`].
+///
+/// The copy is split into the largest chunks the alignment of `dst` and `src` allows, so aligning both
+/// pointers to 16 bytes yields the fastest copies. Leftover bytes which cannot be copied asynchronously
+/// (less than 4 bytes, or bytes where the pointers are not aligned to 4 bytes) are copied synchronously.
+///
+/// # Safety
+///
+/// `dst` must point to shared memory and be valid for writing `count` values, `src` must point to global
+/// memory and be valid for reading `count` values. `dst` must not be read or written until the copy is complete.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn memcpy_async<T: Copy>(dst: *mut T, src: *const T, count: usize) {
+    let dst = dst.cast::<u8>();
+    let src = src.cast::<u8>();
+    let len = count * size_of::<T>();
+
+    if CUDA_ARCH < 800 {
+        core::ptr::copy_nonoverlapping(src, dst, len);
+        return;
+    }
+
+    let mut offset = 0;
+    while offset < len {
+        let (dst, src) = (dst.add(offset), src.add(offset));
+        let align = dst as usize | src as usize;
+        let remaining = len - offset;
+
+        if remaining >= 16 && align % 16 == 0 {
+            cp_async_16(dst, src);
+            offset += 16;
+        } else if remaining >= 8 && align % 8 == 0 {
+            cp_async_8(dst, src);
+            offset += 8;
+        } else if remaining >= 4 && align % 4 == 0 {
+            cp_async_4(dst, src);
+            offset += 4;
+        } else {
+            dst.write(src.read());
+            offset += 1;
+        }
+    }
+}
+
+/// Commits all the asynchronous copies issued by this thread since the last commit into a group,
+/// which can then be waited on with [`pipeline_wait_prior`] (`cp.async.commit_group`).
+#[gpu_only]
+#[inline(always)]
+pub fn pipeline_commit() {
+    if CUDA_ARCH >= 800 {
+        unsafe { asm!("cp.async.commit_group;", options(nostack)) }
+    }
+}
+
+/// Waits until at most `n` of the most recently committed groups of this thread are still pending
+/// (`cp.async.wait_group`). `pipeline_wait_prior(0)` therefore waits for every committed copy.
+///
+/// Only the calling thread is waited on, use [`sync_threads`](crate::thread::sync_threads) afterwards
+/// if other threads need to see the copied data. `n` above `7` waits for every copy.
+#[gpu_only]
+#[inline(always)]
+pub fn pipeline_wait_prior(n: u32) {
+    if CUDA_ARCH < 800 {
+        return;
+    }
+    // the amount of groups must be an immediate.
+    unsafe {
+        match n {
+            0 => asm!("cp.async.wait_group 0;", options(nostack)),
+            1 => asm!("cp.async.wait_group 1;", options(nostack)),
+            2 => asm!("cp.async.wait_group 2;", options(nostack)),
+            3 => asm!("cp.async.wait_group 3;", options(nostack)),
+            4 => asm!("cp.async.wait_group 4;", options(nostack)),
+            5 => asm!("cp.async.wait_group 5;", options(nostack)),
+            6 => asm!("cp.async.wait_group 6;", options(nostack)),
+            7 => asm!("cp.async.wait_group 7;", options(nostack)),
+            _ => asm!("cp.async.wait_all;", options(nostack)),
+        }
+    }
+}
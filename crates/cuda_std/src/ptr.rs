@@ -0,0 +1,824 @@
+//! Loads and stores which map to specific PTX memory instructions.
+//!
+//! The codegen will usually pick the right instructions on its own, but in performance sensitive code
+//! it is sometimes useful to explicitly ask for a certain kind of load or store, such as 128-bit vector loads
+//! which make coalesced global memory accesses a lot cheaper.
+//!
+//! [`GlobalPtr`] and [`SharedPtr`] are pointers which carry their address space in their type, the codegen emits them
+//! as pointers in that address space so they cannot be mixed up with pointers into other memory. Reading and writing
+//! through them converts them to a generic pointer first, so those are generic accesses (`ld`, not `ld.shared`).
+
+use crate::{address_space, gpu_only};
+use core::mem::{size_of, MaybeUninit};
+use vek::Vec4;
+
+/// Loads 4 consecutive `f32`s from global memory. If `ptr` is aligned to 16 bytes, this
+/// is done in a single 128-bit `ld.global.v4.f32` vector load, otherwise it falls back to
+/// 4 scalar loads.
+///
+/// # Safety
+///
+/// `ptr` must point to global memory and must be valid for reading 4 `f32`s.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn load_global_v4_f32(ptr: *const f32) -> Vec4<f32> {
+    if ptr as usize % 16 != 0 {
+        return Vec4::new(
+            ptr.read(),
+            ptr.add(1).read(),
+            ptr.add(2).read(),
+            ptr.add(3).read(),
+        );
+    }
+
+    let (x, y, z, w);
+    asm!(
+        "ld.global.v4.f32 {{{}, {}, {}, {}}}, [{}];",
+        out(reg32) x,
+        out(reg32) y,
+        out(reg32) z,
+        out(reg32) w,
+        in(reg64) ptr,
+        options(readonly, nostack)
+    );
+    Vec4::new(x, y, z, w)
+}
+
+/// Stores 4 consecutive `f32`s to global memory. If `ptr` is aligned to 16 bytes, this
+/// is done in a single 128-bit `st.global.v4.f32` vector store, otherwise it falls back to
+/// 4 scalar stores.
+///
+/// # Safety
+///
+/// `ptr` must point to global memory and must be valid for writing 4 `f32`s.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn store_global_v4_f32(ptr: *mut f32, val: Vec4<f32>) {
+    if ptr as usize % 16 != 0 {
+        ptr.write(val.x);
+        ptr.add(1).write(val.y);
+        ptr.add(2).write(val.z);
+        ptr.add(3).write(val.w);
+        return;
+    }
+
+    asm!(
+        "st.global.v4.f32 [{}], {{{}, {}, {}, {}}};",
+        in(reg64) ptr,
+        in(reg32) val.x,
+        in(reg32) val.y,
+        in(reg32) val.z,
+        in(reg32) val.w,
+        options(nostack)
+    );
+}
+
+/// Loads a value from global memory through the non-coherent (read-only) data cache (`ld.global.nc`).
+///
+/// This is useful for data which the kernel only reads, the read-only cache is separate from the L1 cache
+/// so it does not compete with other loads. LLVM only uses it on its own when it can prove that the memory
+/// is never written, which it rarely can. If `ptr` is aligned to 8 or 4 bytes and the size of `T` is a multiple of
+/// that, the value is loaded in 64 or 32-bit chunks, otherwise it falls back to a normal load.
+///
+/// # Safety
+///
+/// `ptr` must point to global memory and must be valid for reading a `T`. The memory must not be written
+/// by any thread (or the host) while the kernel is running, otherwise the cache may return stale data.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn load_global_nc<T: Copy>(ptr: *const T) -> T {
+    let size = size_of::<T>();
+    let mut val = MaybeUninit::<T>::uninit();
+    let dst = val.as_mut_ptr();
+
+    if size % 8 == 0 && ptr as usize % 8 == 0 {
+        for i in 0..size / 8 {
+            let chunk: u64;
+            asm!(
+                "ld.global.nc.u64 {}, [{}];",
+                out(reg64) chunk,
+                in(reg64) ptr.cast::<u64>().add(i),
+                options(readonly, nostack)
+            );
+            dst.cast::<u64>().add(i).write_unaligned(chunk);
+        }
+    } else if size % 4 == 0 && ptr as usize % 4 == 0 {
+        for i in 0..size / 4 {
+            let chunk: u32;
+            asm!(
+                "ld.global.nc.u32 {}, [{}];",
+                out(reg32) chunk,
+                in(reg64) ptr.cast::<u32>().add(i),
+                options(readonly, nostack)
+            );
+            dst.cast::<u32>().add(i).write_unaligned(chunk);
+        }
+    } else {
+        return ptr.read();
+    }
+    val.assume_init()
+}
+
+// loads and stores `T` in the biggest chunks its size and the alignment of the pointer allow, so that
+// every part of it goes through the cache operator.
+macro_rules! cache_op_access {
+    ($(
+        $(#[$load_attr:meta])* load $load:ident, $load_op:literal;
+        $(#[$store_attr:meta])* store $store:ident, $store_op:literal;
+    )*) => {
+        $(
+            $(#[$load_attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $load<T: Copy>(ptr: *const T) -> T {
+                let mut val = MaybeUninit::<T>::uninit();
+                let dst = val.as_mut_ptr();
+                macro_rules! chunks {
+                    ($ty:ty, $reg:ident, $suffix:literal) => {
+                        for i in 0..size_of::<T>() / size_of::<$ty>() {
+                            let chunk: $ty;
+                            asm!(
+                                concat!($load_op, ".", $suffix, " {}, [{}];"),
+                                out($reg) chunk,
+                                in(reg64) ptr.cast::<$ty>().add(i),
+                                options(nostack)
+                            );
+                            dst.cast::<$ty>().add(i).write_unaligned(chunk);
+                        }
+                    };
+                }
+                match chunk_size::<T>(ptr as usize) {
+                    8 => chunks!(u64, reg64, "u64"),
+                    4 => chunks!(u32, reg32, "u32"),
+                    2 => chunks!(u16, reg16, "u16"),
+                    _ => {
+                        for i in 0..size_of::<T>() {
+                            let chunk: u16;
+                            asm!(
+                                concat!($load_op, ".u8 {}, [{}];"),
+                                out(reg16) chunk,
+                                in(reg64) ptr.cast::<u8>().add(i),
+                                options(nostack)
+                            );
+                            dst.cast::<u8>().add(i).write(chunk as u8);
+                        }
+                    }
+                }
+                val.assume_init()
+            }
+
+            $(#[$store_attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $store<T: Copy>(ptr: *mut T, val: T) {
+                let src = &val as *const T;
+                macro_rules! chunks {
+                    ($ty:ty, $reg:ident, $suffix:literal) => {
+                        for i in 0..size_of::<T>() / size_of::<$ty>() {
+                            asm!(
+                                concat!($store_op, ".", $suffix, " [{}], {};"),
+                                in(reg64) ptr.cast::<$ty>().add(i),
+                                in($reg) src.cast::<$ty>().add(i).read_unaligned(),
+                                options(nostack)
+                            );
+                        }
+                    };
+                }
+                match chunk_size::<T>(ptr as usize) {
+                    8 => chunks!(u64, reg64, "u64"),
+                    4 => chunks!(u32, reg32, "u32"),
+                    2 => chunks!(u16, reg16, "u16"),
+                    _ => {
+                        for i in 0..size_of::<T>() {
+                            asm!(
+                                concat!($store_op, ".u8 [{}], {};"),
+                                in(reg64) ptr.cast::<u8>().add(i),
+                                in(reg16) src.cast::<u8>().add(i).read() as u16,
+                                options(nostack)
+                            );
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+/// The biggest chunk size which both the size of `T` and `addr` are a multiple of.
+#[inline(always)]
+fn chunk_size<T>(addr: usize) -> usize {
+    [8, 4, 2]
+        .iter()
+        .copied()
+        .find(|n| size_of::<T>() % n == 0 && addr % n == 0)
+        .unwrap_or(1)
+}
+
+cache_op_access! {
+    /// Loads a value bypassing the caches (`ld.cv`), the cached copy is invalidated and the value is
+    /// fetched again, so it sees the writes of threads on other multiprocessors. Together with
+    /// [`store_wt`] this is useful for producer-consumer communication across blocks.
+    ///
+    /// The value is loaded in chunks of 8, 4, 2 or 1 bytes depending on the size of `T` and the alignment
+    /// of `ptr`. Each chunk is loaded once, but there is no ordering between chunks or with other memory
+    /// accesses, use a fence for that.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reading a `T`.
+    load load_cv, "ld.cv";
+    /// Stores a value writing it through to system memory (`st.wt`) instead of keeping it in the L2
+    /// cache, see [`load_cv`] for how the value is split into chunks.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writing a `T`.
+    store store_wt, "st.wt";
+
+    /// Loads a value from global memory bypassing the caches (`ld.global.cv`), see [`load_cv`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory and must be valid for reading a `T`.
+    load load_global_cv, "ld.global.cv";
+    /// Stores a value to global memory writing it through to system memory (`st.global.wt`), see [`store_wt`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory and must be valid for writing a `T`.
+    store store_global_wt, "st.global.wt";
+}
+
+/// How likely data accessed with a [`CachePolicy`] is to be evicted from the L2 cache, relative to other data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum L2Eviction {
+    /// The data is evicted first, for data which is only accessed once (`L2::evict_first`).
+    First,
+    /// The default priority (`L2::evict_normal`).
+    Normal,
+    /// The data is evicted last, for data which should persist in the L2 cache because it is
+    /// accessed again soon, such as a lookup table or a tile reused by other blocks (`L2::evict_last`).
+    Last,
+    /// The priority of data which is already cached is not changed (`L2::evict_unchanged`).
+    Unchanged,
+}
+
+/// A 64-bit L2 cache policy created with [`create_cache_policy`], used by [`load_global_with_policy`]
+/// and [`store_global_with_policy`].
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CachePolicy(pub u64);
+
+/// Creates a cache policy which gives all the data accessed with it the eviction priority `eviction`
+/// in the L2 cache (`createpolicy.fractional`).
+///
+/// This requires `compute_80` or above, on older architectures the policy is `0` and ignored by the
+/// accesses using it.
+#[gpu_only]
+#[inline(always)]
+pub fn create_cache_policy(eviction: L2Eviction) -> CachePolicy {
+    if crate::arch::CUDA_ARCH < 800 {
+        return CachePolicy(0);
+    }
+    let policy: u64;
+    // the eviction priority is part of the instruction.
+    unsafe {
+        match eviction {
+            L2Eviction::First => asm!(
+                "createpolicy.fractional.L2::evict_first.b64 {}, 1.0;",
+                out(reg64) policy,
+                options(pure, nomem, nostack)
+            ),
+            L2Eviction::Normal => asm!(
+                "createpolicy.fractional.L2::evict_normal.b64 {}, 1.0;",
+                out(reg64) policy,
+                options(pure, nomem, nostack)
+            ),
+            L2Eviction::Last => asm!(
+                "createpolicy.fractional.L2::evict_last.b64 {}, 1.0;",
+                out(reg64) policy,
+                options(pure, nomem, nostack)
+            ),
+            L2Eviction::Unchanged => asm!(
+                "createpolicy.fractional.L2::evict_unchanged.b64 {}, 1.0;",
+                out(reg64) policy,
+                options(pure, nomem, nostack)
+            ),
+        }
+    }
+    CachePolicy(policy)
+}
+
+/// Loads a value from global memory with the L2 cache eviction priority of `policy`
+/// (`ld.global.L2::cache_hint`), see [`load_cv`] for how the value is split into chunks.
+///
+/// This requires `compute_80` or above, on older architectures this is a normal load.
+///
+/// # Safety
+///
+/// `ptr` must point to global memory and must be valid for reading a `T`.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn load_global_with_policy<T: Copy>(ptr: *const T, policy: CachePolicy) -> T {
+    if crate::arch::CUDA_ARCH < 800 {
+        return ptr.read();
+    }
+    let mut val = MaybeUninit::<T>::uninit();
+    let dst = val.as_mut_ptr();
+    macro_rules! chunks {
+        ($ty:ty, $reg:ident, $suffix:literal) => {
+            for i in 0..size_of::<T>() / size_of::<$ty>() {
+                let chunk: $ty;
+                asm!(
+                    concat!("ld.global.L2::cache_hint.", $suffix, " {}, [{}], {};"),
+                    out($reg) chunk,
+                    in(reg64) ptr.cast::<$ty>().add(i),
+                    in(reg64) policy.0,
+                    options(readonly, nostack)
+                );
+                dst.cast::<$ty>().add(i).write_unaligned(chunk);
+            }
+        };
+    }
+    match chunk_size::<T>(ptr as usize) {
+        8 => chunks!(u64, reg64, "u64"),
+        4 => chunks!(u32, reg32, "u32"),
+        2 => chunks!(u16, reg16, "u16"),
+        _ => {
+            for i in 0..size_of::<T>() {
+                let chunk: u16;
+                asm!(
+                    "ld.global.L2::cache_hint.u8 {}, [{}], {};",
+                    out(reg16) chunk,
+                    in(reg64) ptr.cast::<u8>().add(i),
+                    in(reg64) policy.0,
+                    options(readonly, nostack)
+                );
+                dst.cast::<u8>().add(i).write(chunk as u8);
+            }
+        }
+    }
+    val.assume_init()
+}
+
+/// Stores a value to global memory with the L2 cache eviction priority of `policy`
+/// (`st.global.L2::cache_hint`), see [`load_cv`] for how the value is split into chunks.
+///
+/// This requires `compute_80` or above, on older architectures this is a normal store.
+///
+/// # Safety
+///
+/// `ptr` must point to global memory and must be valid for writing a `T`.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn store_global_with_policy<T: Copy>(ptr: *mut T, val: T, policy: CachePolicy) {
+    if crate::arch::CUDA_ARCH < 800 {
+        ptr.write(val);
+        return;
+    }
+    let src = &val as *const T;
+    macro_rules! chunks {
+        ($ty:ty, $reg:ident, $suffix:literal) => {
+            for i in 0..size_of::<T>() / size_of::<$ty>() {
+                asm!(
+                    concat!("st.global.L2::cache_hint.", $suffix, " [{}], {}, {};"),
+                    in(reg64) ptr.cast::<$ty>().add(i),
+                    in($reg) src.cast::<$ty>().add(i).read_unaligned(),
+                    in(reg64) policy.0,
+                    options(nostack)
+                );
+            }
+        };
+    }
+    match chunk_size::<T>(ptr as usize) {
+        8 => chunks!(u64, reg64, "u64"),
+        4 => chunks!(u32, reg32, "u32"),
+        2 => chunks!(u16, reg16, "u16"),
+        _ => {
+            for i in 0..size_of::<T>() {
+                asm!(
+                    "st.global.L2::cache_hint.u8 [{}], {}, {};",
+                    in(reg64) ptr.cast::<u8>().add(i),
+                    in(reg16) src.cast::<u8>().add(i).read() as u16,
+                    in(reg64) policy.0,
+                    options(nostack)
+                );
+            }
+        }
+    }
+}
+
+// stores `T` in chunks like `cache_op_access`, every chunk is guarded by the predicate instead of branching
+// around all of them.
+macro_rules! predicated_store {
+    ($($(#[$attr:meta])* $name:ident, $op:literal;)*) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name<T: Copy>(predicate: bool, ptr: *mut T, val: T) {
+                let src = &val as *const T;
+                macro_rules! chunks {
+                    ($ty:ty, $reg:ident, $suffix:literal, $chunk:ty) => {
+                        for i in 0..size_of::<T>() / size_of::<$ty>() {
+                            asm!(
+                                "{{",
+                                ".reg .pred %p;",
+                                "setp.ne.u32 %p, {}, 0;",
+                                concat!("@%p ", $op, ".", $suffix, " [{}], {};"),
+                                "}}",
+                                in(reg32) predicate as u32,
+                                // `ptr` may be dangling if the predicate is false.
+                                in(reg64) ptr.cast::<$ty>().wrapping_add(i),
+                                in($reg) src.cast::<$ty>().add(i).read_unaligned() as $chunk,
+                                options(nostack)
+                            );
+                        }
+                    };
+                }
+                match chunk_size::<T>(ptr as usize) {
+                    8 => chunks!(u64, reg64, "u64", u64),
+                    4 => chunks!(u32, reg32, "u32", u32),
+                    2 => chunks!(u16, reg16, "u16", u16),
+                    _ => chunks!(u8, reg16, "u8", u16),
+                }
+            }
+        )*
+    };
+}
+
+predicated_store! {
+    /// Stores a value only if `predicate` is `true`, with predicated stores (`@%p st`) instead of a branch.
+    /// For short conditional writes such as the bounds check at the end of a kernel, this avoids the divergent
+    /// branch and the reconvergence around the store. See [`load_cv`] for how the value is split into chunks.
+    ///
+    /// ```ignore
+    /// // `@%p st.u32` instead of `if idx < len { out.add(idx).write(val) }`
+    /// store_if(idx < len, out.add(idx), val);
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writing a `T` if `predicate` is `true`.
+    store_if, "st";
+    /// Stores a value to global memory only if `predicate` is `true` (`@%p st.global`), see [`store_if`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory and be valid for writing a `T` if `predicate` is `true`.
+    store_global_if, "st.global";
+    /// Stores a value to shared memory only if `predicate` is `true` (`@%p st.shared`), see [`store_if`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to shared memory and be valid for writing a `T` if `predicate` is `true`.
+    store_shared_if, "st.shared";
+}
+
+/// The set of threads which a scoped load or store is coherent with, such as [`store_release`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// The threads of the same thread block (`.cta`).
+    Block,
+    /// The threads of every block on the same GPU (`.gpu`).
+    Device,
+    /// Every thread in the system, including threads on peer GPUs and the host (`.sys`).
+    /// This is needed for memory which is shared with other devices, such as peer or host mapped memory.
+    System,
+}
+
+/// The fence which orders memory accesses at `scope`. This is used to emulate acquire
+/// and release accesses before compute_70.
+#[gpu_only]
+#[inline(always)]
+fn scope_fence(scope: Scope) {
+    unsafe {
+        match scope {
+            Scope::Block => asm!("membar.cta;", options(nostack)),
+            Scope::Device => asm!("membar.gl;", options(nostack)),
+            Scope::System => asm!("membar.sys;", options(nostack)),
+        }
+    }
+}
+
+// scoped accesses are a single memory operation, so unlike `cache_op_access` values cannot be split
+// into chunks and only 1, 2, 4 and 8 byte values are supported.
+macro_rules! scoped_access {
+    ($(
+        $(#[$load_attr:meta])* load $load:ident, $load_sem:literal, $load_space:literal;
+        $(#[$store_attr:meta])* store $store:ident, $store_sem:literal, $store_space:literal;
+    )*) => {
+        $(
+            $(#[$load_attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $load<T: Copy>(ptr: *const T, scope: Scope) -> T {
+                // before compute_70 there are no scoped accesses, volatile accesses are always visible
+                // to the whole system, and the ordering is done with a fence.
+                if crate::arch::CUDA_ARCH < 700 {
+                    let val = ptr.read_volatile();
+                    if $load_sem == "acquire" {
+                        scope_fence(scope);
+                    }
+                    return val;
+                }
+                let mut val = MaybeUninit::<T>::uninit();
+                let dst = val.as_mut_ptr();
+                macro_rules! ld {
+                    ($ty:ty, $reg:ident, $suffix:literal) => {{
+                        let out: $ty;
+                        macro_rules! scoped {
+                            ($scope:literal) => {
+                                asm!(
+                                    concat!("ld.", $load_sem, ".", $scope, $load_space, ".", $suffix, " {}, [{}];"),
+                                    out($reg) out,
+                                    in(reg64) ptr,
+                                    options(nostack)
+                                )
+                            };
+                        }
+                        match scope {
+                            Scope::Block => scoped!("cta"),
+                            Scope::Device => scoped!("gpu"),
+                            Scope::System => scoped!("sys"),
+                        }
+                        out
+                    }};
+                }
+                match size_of::<T>() {
+                    8 => dst.cast::<u64>().write(ld!(u64, reg64, "b64")),
+                    4 => dst.cast::<u32>().write(ld!(u32, reg32, "b32")),
+                    2 => dst.cast::<u16>().write(ld!(u16, reg16, "b16")),
+                    1 => dst.cast::<u8>().write(ld!(u16, reg16, "u8") as u8),
+                    _ => panic!("scoped loads only support values of 1, 2, 4 or 8 bytes"),
+                }
+                val.assume_init()
+            }
+
+            $(#[$store_attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $store<T: Copy>(ptr: *mut T, val: T, scope: Scope) {
+                if crate::arch::CUDA_ARCH < 700 {
+                    if $store_sem == "release" {
+                        scope_fence(scope);
+                    }
+                    ptr.write_volatile(val);
+                    return;
+                }
+                let src = &val as *const T;
+                macro_rules! st {
+                    ($ty:ty, $reg:ident, $suffix:literal, $val:expr) => {{
+                        let val: $ty = $val;
+                        macro_rules! scoped {
+                            ($scope:literal) => {
+                                asm!(
+                                    concat!("st.", $store_sem, ".", $scope, $store_space, ".", $suffix, " [{}], {};"),
+                                    in(reg64) ptr,
+                                    in($reg) val,
+                                    options(nostack)
+                                )
+                            };
+                        }
+                        match scope {
+                            Scope::Block => scoped!("cta"),
+                            Scope::Device => scoped!("gpu"),
+                            Scope::System => scoped!("sys"),
+                        }
+                    }};
+                }
+                match size_of::<T>() {
+                    8 => st!(u64, reg64, "b64", src.cast::<u64>().read()),
+                    4 => st!(u32, reg32, "b32", src.cast::<u32>().read()),
+                    2 => st!(u16, reg16, "b16", src.cast::<u16>().read()),
+                    1 => st!(u16, reg16, "u8", src.cast::<u8>().read() as u16),
+                    _ => panic!("scoped stores only support values of 1, 2, 4 or 8 bytes"),
+                }
+            }
+        )*
+    };
+}
+
+scoped_access! {
+    /// Loads a value with relaxed ordering at `scope` (`ld.relaxed.{cta,gpu,sys}`). The load is coherent
+    /// with the stores of every thread in `scope`, so it sees their latest values, but it does not order
+    /// any other memory accesses.
+    ///
+    /// This requires compute_70 or above, older architectures use a volatile load, which is coherent with
+    /// the whole system.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reading a `T` and aligned to the size of `T`, which must be 1, 2, 4 or 8 bytes.
+    load load_relaxed, "relaxed", "";
+    /// Stores a value with relaxed ordering at `scope` (`st.relaxed.{cta,gpu,sys}`), see [`load_relaxed`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writing a `T` and aligned to the size of `T`, which must be 1, 2, 4 or 8 bytes.
+    store store_relaxed, "relaxed", "";
+
+    /// Loads a value with acquire ordering at `scope` (`ld.acquire.{cta,gpu,sys}`). Memory accesses after the
+    /// load cannot happen before it, so if it reads the value of a [`store_release`] by a thread in `scope`,
+    /// everything that thread wrote before the store is visible after the load.
+    ///
+    /// This requires compute_70 or above, older architectures use a volatile load followed by a fence at `scope`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reading a `T` and aligned to the size of `T`, which must be 1, 2, 4 or 8 bytes.
+    load load_acquire, "acquire", "";
+    /// Stores a value with release ordering at `scope` (`st.release.{cta,gpu,sys}`). Memory accesses before
+    /// the store cannot happen after it, see [`load_acquire`].
+    ///
+    /// This requires compute_70 or above, older architectures use a fence at `scope` followed by a volatile store.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for writing a `T` and aligned to the size of `T`, which must be 1, 2, 4 or 8 bytes.
+    store store_release, "release", "";
+
+    /// Loads a value from global memory with relaxed ordering at `scope` (`ld.relaxed.{cta,gpu,sys}.global`),
+    /// see [`load_relaxed`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory and must be valid for reading a `T` and aligned to the size of
+    /// `T`, which must be 1, 2, 4 or 8 bytes.
+    load load_global_relaxed, "relaxed", ".global";
+    /// Stores a value to global memory with relaxed ordering at `scope` (`st.relaxed.{cta,gpu,sys}.global`),
+    /// see [`load_relaxed`]. With [`Scope::System`] this is the store to use for peer or host mapped memory.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory and must be valid for writing a `T` and aligned to the size of
+    /// `T`, which must be 1, 2, 4 or 8 bytes.
+    store store_global_relaxed, "relaxed", ".global";
+
+    /// Loads a value from global memory with acquire ordering at `scope` (`ld.acquire.{cta,gpu,sys}.global`),
+    /// see [`load_acquire`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory and must be valid for reading a `T` and aligned to the size of
+    /// `T`, which must be 1, 2, 4 or 8 bytes.
+    load load_global_acquire, "acquire", ".global";
+    /// Stores a value to global memory with release ordering at `scope` (`st.release.{cta,gpu,sys}.global`),
+    /// see [`store_release`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to global memory and must be valid for writing a `T` and aligned to the size of
+    /// `T`, which must be 1, 2, 4 or 8 bytes.
+    store store_global_release, "release", ".global";
+}
+
+macro_rules! is_space {
+    ($($(#[$attr:meta])* $name:ident, $space:literal);* $(;)?) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name<T: ?Sized>(ptr: *const T) -> bool {
+                let out: u32;
+                unsafe {
+                    asm!(
+                        "{{",
+                        ".reg .pred %p;",
+                        concat!("isspacep.", $space, " %p, {};"),
+                        "selp.u32 {}, 1, 0, %p;",
+                        "}}",
+                        in(reg64) ptr as *const u8,
+                        out(reg32) out,
+                        options(pure, nomem, nostack)
+                    );
+                }
+                out != 0
+            }
+        )*
+    };
+}
+
+is_space! {
+    /// Returns whether a generic pointer points to global memory (`isspacep.global`).
+    /// This is CUDA C's `__isGlobal`.
+    is_global, "global";
+    /// Returns whether a generic pointer points to shared memory (`isspacep.shared`).
+    /// This is CUDA C's `__isShared`.
+    is_shared, "shared";
+    /// Returns whether a generic pointer points to constant memory (`isspacep.const`).
+    /// This is CUDA C's `__isConstant`.
+    is_constant, "const";
+    /// Returns whether a generic pointer points to local memory, such as the stack (`isspacep.local`).
+    /// This is CUDA C's `__isLocal`.
+    is_local, "local";
+}
+
+macro_rules! address_space_ptr {
+    ($($(#[$attr:meta])* $name:ident, $space:ident, $to_gen:literal, $from_gen:literal);* $(;)?) => {
+        $(
+            $(#[$attr])*
+            // the pointer is only ever created by and given to the conversion intrinsics. It must never be
+            // read as a field, that would reinterpret the address as a generic address.
+            #[address_space($space)]
+            #[repr(transparent)]
+            pub struct $name<T>(*mut T);
+
+            impl<T> Clone for $name<T> {
+                fn clone(&self) -> Self {
+                    *self
+                }
+            }
+
+            impl<T> Copy for $name<T> {}
+
+            impl<T> $name<T> {
+                #[doc = concat!("Converts a generic pointer into a pointer in the ", stringify!($space), " address space.")]
+                ///
+                /// # Safety
+                ///
+                #[doc = concat!("`ptr` must point into ", stringify!($space), " memory.")]
+                #[gpu_only]
+                #[inline(always)]
+                pub unsafe fn from_generic(ptr: *mut T) -> Self {
+                    extern "C" {
+                        #[link_name = $from_gen]
+                        fn from_gen(ptr: *mut u8) -> $name<u8>;
+                    }
+                    core::mem::transmute(from_gen(ptr.cast()))
+                }
+
+                /// Converts this pointer into a generic pointer, which can be used like any other pointer.
+                #[gpu_only]
+                #[inline(always)]
+                pub fn as_generic(self) -> *mut T {
+                    extern "C" {
+                        #[link_name = $to_gen]
+                        fn to_gen(ptr: $name<u8>) -> *mut u8;
+                    }
+                    unsafe { to_gen(core::mem::transmute(self)).cast() }
+                }
+
+                /// Offsets the pointer by `count` values of `T`, see [`pointer::add`].
+                ///
+                /// # Safety
+                ///
+                /// The same rules as [`pointer::add`] apply.
+                #[inline(always)]
+                pub unsafe fn add(self, count: usize) -> Self {
+                    Self::from_generic(self.as_generic().add(count))
+                }
+
+                /// Reads the value pointed to through the generic pointer, see [`pointer::read`].
+                ///
+                /// # Safety
+                ///
+                /// The same rules as [`pointer::read`] apply.
+                #[inline(always)]
+                pub unsafe fn read(self) -> T {
+                    self.as_generic().read()
+                }
+
+                /// Overwrites the value pointed to through the generic pointer without dropping the old value, see
+                /// [`pointer::write`].
+                ///
+                /// # Safety
+                ///
+                /// The same rules as [`pointer::write`] apply.
+                #[inline(always)]
+                pub unsafe fn write(self, val: T) {
+                    self.as_generic().write(val)
+                }
+            }
+        )*
+    };
+}
+
+address_space_ptr! {
+    /// A pointer into global memory (`.global`).
+    GlobalPtr, global, "llvm.nvvm.ptr.global.to.gen.p0i8.p1i8", "llvm.nvvm.ptr.gen.to.global.p1i8.p0i8";
+    /// A pointer into the shared memory of the thread block (`.shared`).
+    SharedPtr, shared, "llvm.nvvm.ptr.shared.to.gen.p0i8.p3i8", "llvm.nvvm.ptr.gen.to.shared.p3i8.p0i8";
+}
+
+impl<T: Copy> GlobalPtr<T> {
+    /// Reads the value pointed to bypassing the caches (`ld.global.cv`), see [`load_cv`].
+    ///
+    /// # Safety
+    ///
+    /// The same rules as [`pointer::read`] apply.
+    #[inline(always)]
+    pub unsafe fn read_cv(self) -> T {
+        load_global_cv(self.as_generic())
+    }
+
+    /// Writes the value pointed to through to system memory (`st.global.wt`), see [`store_wt`].
+    ///
+    /// # Safety
+    ///
+    /// The same rules as [`pointer::write`] apply.
+    #[inline(always)]
+    pub unsafe fn write_wt(self, val: T) {
+        store_global_wt(self.as_generic(), val)
+    }
+}
@@ -0,0 +1,52 @@
+//! Shared memory arrays, including ones whose size depends on generic params.
+//!
+//! Statics cannot be placed in shared memory, [`shared_array!`] gives a pointer to shared memory of any type
+//! instead. The type can use the generic params of the function the macro is used in, so a tiled helper which is
+//! generic over its tile size gets tiles of the right size for every tile size it is instantiated with:
+//!
+//! ```ignore
+//! use cuda_std::prelude::*;
+//!
+//! unsafe fn tiled_sum<const TILE: usize>(input: &[f32], out: *mut f32) {
+//!     let tile = cuda_std::shared_array!([f32; TILE]);
+//!     // ...
+//! }
+//!
+//! #[kernel]
+//! pub unsafe fn sum_256(input: &[f32], out: *mut f32) {
+//!     tiled_sum::<256>(input, out);
+//! }
+//! ```
+//!
+//! Kernels themselves cannot be generic, so the generic code goes in a function called by the kernels.
+//!
+//! The memory is not initialized and the whole block uses the same memory. Every use of the macro gets its own
+//! memory for every type it is used with, but the memory only depends on the use and the type, so instantiations
+//! of a generic function which use the macro with the same type (such as
+//! `shared_array!([f32; 32])` in a `foo<T>`, for every `T`) share the same memory. Static shared memory is
+//! limited to 48 KB per block, the codegen errors about kernels that use more than that.
+
+/// Returns a pointer to shared memory for an `A`, this is defined by the codegen and every pair of `Marker` and
+/// `A` gets its own memory. `Marker` is a type which is unique to the call site, use [`shared_array!`] instead of
+/// calling this directly.
+#[doc(hidden)]
+#[cfg_attr(
+    any(target_arch = "nvptx", target_arch = "nvptx64"),
+    nvvm_internal(shared_array)
+)]
+#[inline(never)]
+pub fn shared_array<Marker, A>() -> *mut A {
+    unimplemented!("shared_array is only available when compiling with the NVVM codegen")
+}
+
+/// Returns a `*mut $ty` pointing to shared memory which is uninitialized and used by the whole thread block.
+/// `$ty` can use the generic params of the surrounding function. Every use of the macro gets its own memory for
+/// every `$ty`, instantiations of the surrounding function with the same `$ty` share it. See
+/// [`shared`](crate::shared) for more info.
+#[macro_export]
+macro_rules! shared_array {
+    ($ty:ty) => {{
+        struct SharedArray;
+        $crate::shared::shared_array::<SharedArray, $ty>()
+    }};
+}
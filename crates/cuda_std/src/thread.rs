@@ -247,6 +247,8 @@ pub fn sync_threads() {
 
 /// Identical to [`sync_threads`] but with the additional feature that it evaluates
 /// the predicate for every thread and returns the number of threads in which it evaluated to a non-zero number.
+///
+/// Every thread of the block must call this, just like [`sync_threads`] (`bar.red.popc.u32`).
 #[gpu_only]
 #[inline(always)]
 pub fn sync_threads_count(predicate: u32) -> u32 {
@@ -260,6 +262,8 @@ pub fn sync_threads_count(predicate: u32) -> u32 {
 
 /// Identical to [`sync_threads`] but with the additional feature that it evaluates
 /// the predicate for every thread and returns a non-zero integer if every predicate evaluates to non-zero for all threads.
+///
+/// Every thread of the block must call this, just like [`sync_threads`] (`bar.red.and.pred`).
 #[gpu_only]
 #[inline(always)]
 pub fn sync_threads_and(predicate: u32) -> u32 {
@@ -274,6 +278,8 @@ pub fn sync_threads_and(predicate: u32) -> u32 {
 /// Identical to [`sync_threads`] but with the additional feature that it evaluates
 /// the predicate for every thread and returns a non-zero integer if at least one predicate in a thread evaluates
 /// to non-zero.
+///
+/// Every thread of the block must call this, just like [`sync_threads`] (`bar.red.or.pred`).
 #[gpu_only]
 #[inline(always)]
 pub fn sync_threads_or(predicate: u32) -> u32 {
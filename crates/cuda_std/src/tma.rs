@@ -0,0 +1,376 @@
+//! Bulk tensor copies between global and shared memory with the Tensor Memory Accelerator (TMA) of `sm_90`.
+//!
+//! A tensor copy moves a whole tile of a (up to 5 dimensional) tensor at once, as described by a [`TensorMap`].
+//! Tensor maps are created on the host with `cuTensorMapEncodeTiled` and usually passed to the kernel by value.
+//! Loads complete asynchronously on an mbarrier in shared memory, which the threads then wait on:
+//!
+//! ```ignore
+//! if thread::thread_idx_x() == 0 {
+//!     mbarrier_init(bar, 1);
+//!     mbarrier_arrive_expect_tx(bar, TILE_BYTES);
+//!     cp_async_bulk_tensor_2d_load(tile, &map, [x, y], bar);
+//! }
+//! thread::sync_threads();
+//! while !mbarrier_try_wait_parity(bar, 0) {}
+//! ```
+//!
+//! The asynchronous stores and reductions into shared memory (`st.async` and `red.async`) complete on an
+//! mbarrier of the block they write to the same way, the mbarrier is told to expect the bytes written with
+//! [`mbarrier_arrive_expect_tx`].
+//!
+//! This module only exists when compiling for `compute_90` or above, there is no fallback for older architectures.
+
+use crate::gpu_only;
+
+/// An opaque description of the layout of a tensor in global memory and of the tiles copied out of it,
+/// the same as the driver API's `CUtensorMap`.
+#[repr(C, align(64))]
+#[derive(Clone, Copy)]
+pub struct TensorMap(pub [u8; 128]);
+
+macro_rules! cp_async_bulk_tensor {
+    ($($load:ident, $store:ident, $dim:literal, $n:literal, [$($coord:ident),*], $coords:literal);* $(;)?) => {
+        $(
+            #[doc = concat!("Starts copying the tile at `coords` of the ", $dim, " tensor described by `tensor_map` to shared")]
+            /// memory `dst`. The copy completes on `mbarrier`, which must have been told to expect the bytes of the tile
+            /// with [`mbarrier_arrive_expect_tx`].
+            ///
+            #[doc = concat!("This is `cp.async.bulk.tensor.", $dim, ".shared::cluster.global.tile.mbarrier::complete_tx::bytes`.")]
+            ///
+            /// # Safety
+            ///
+            /// `dst` and `mbarrier` must point to shared memory, `dst` must be aligned to 128 bytes and be valid for writing
+            /// the whole tile. `tensor_map` must point to a valid tensor map in global, constant or param memory.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $load(dst: *mut u8, tensor_map: *const TensorMap, coords: [i32; $n], mbarrier: *mut u64) {
+                let [$($coord),*] = coords;
+                asm!(
+                    "{{",
+                    ".reg .u64 %dst, %bar;",
+                    "cvta.to.shared.u64 %dst, {dst};",
+                    "cvta.to.shared.u64 %bar, {bar};",
+                    concat!(
+                        "cp.async.bulk.tensor.", $dim, ".shared::cluster.global.tile.mbarrier::complete_tx::bytes ",
+                        "[%dst], [{map}, {{", $coords, "}}], [%bar];"
+                    ),
+                    "}}",
+                    dst = in(reg64) dst,
+                    bar = in(reg64) mbarrier,
+                    map = in(reg64) tensor_map,
+                    $($coord = in(reg32) $coord,)*
+                    options(nostack)
+                );
+            }
+
+            #[doc = concat!("Starts copying shared memory `src` to the tile at `coords` of the ", $dim, " tensor described by")]
+            /// `tensor_map`. The copy is part of the current bulk group, see [`cp_async_bulk_commit_group`].
+            ///
+            #[doc = concat!("This is `cp.async.bulk.tensor.", $dim, ".global.shared::cta.tile.bulk_group`.")]
+            ///
+            /// # Safety
+            ///
+            /// `src` must point to shared memory, be aligned to 128 bytes and be valid for reading the whole tile.
+            /// `tensor_map` must point to a valid tensor map in global, constant or param memory. `src` must not be
+            /// written until the copy has read it.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $store(tensor_map: *const TensorMap, coords: [i32; $n], src: *const u8) {
+                let [$($coord),*] = coords;
+                asm!(
+                    "{{",
+                    ".reg .u64 %src;",
+                    "cvta.to.shared.u64 %src, {src};",
+                    concat!(
+                        "cp.async.bulk.tensor.", $dim, ".global.shared::cta.tile.bulk_group ",
+                        "[{map}, {{", $coords, "}}], [%src];"
+                    ),
+                    "}}",
+                    src = in(reg64) src,
+                    map = in(reg64) tensor_map,
+                    $($coord = in(reg32) $coord,)*
+                    options(nostack)
+                );
+            }
+        )*
+    };
+}
+
+cp_async_bulk_tensor! {
+    cp_async_bulk_tensor_1d_load, cp_async_bulk_tensor_1d_store, "1d", 1, [c0], "{c0}";
+    cp_async_bulk_tensor_2d_load, cp_async_bulk_tensor_2d_store, "2d", 2, [c0, c1], "{c0}, {c1}";
+    cp_async_bulk_tensor_3d_load, cp_async_bulk_tensor_3d_store, "3d", 3, [c0, c1, c2], "{c0}, {c1}, {c2}";
+    cp_async_bulk_tensor_4d_load, cp_async_bulk_tensor_4d_store, "4d", 4, [c0, c1, c2, c3], "{c0}, {c1}, {c2}, {c3}";
+    cp_async_bulk_tensor_5d_load, cp_async_bulk_tensor_5d_store, "5d", 5, [c0, c1, c2, c3, c4], "{c0}, {c1}, {c2}, {c3}, {c4}";
+}
+
+/// Commits all the bulk stores issued by this thread since the last commit into a bulk group, which can
+/// then be waited on with [`cp_async_bulk_wait_group_read`] (`cp.async.bulk.commit_group`).
+#[gpu_only]
+#[inline(always)]
+pub fn cp_async_bulk_commit_group() {
+    unsafe { asm!("cp.async.bulk.commit_group;", options(nostack)) }
+}
+
+/// Waits until at most `n` of the most recently committed bulk groups of this thread are still reading their
+/// source (`cp.async.bulk.wait_group.read`), after which their shared memory can be reused. `n` above `7` waits
+/// for every group.
+#[gpu_only]
+#[inline(always)]
+pub fn cp_async_bulk_wait_group_read(n: u32) {
+    unsafe { wait_group!("cp.async.bulk.wait_group.read", n) }
+}
+
+/// Initializes the mbarrier `mbarrier` in shared memory to expect `count` arrivals (`mbarrier.init.shared.b64`).
+/// The mbarrier must be made visible to the other threads, such as with [`sync_threads`](crate::thread::sync_threads),
+/// before they use it.
+///
+/// # Safety
+///
+/// `mbarrier` must point to shared memory and be aligned to 8 bytes.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn mbarrier_init(mbarrier: *mut u64, count: u32) {
+    asm!(
+        "{{",
+        ".reg .u64 %bar;",
+        "cvta.to.shared.u64 %bar, {};",
+        "mbarrier.init.shared.b64 [%bar], {};",
+        "}}",
+        in(reg64) mbarrier,
+        in(reg32) count,
+        options(nostack)
+    );
+}
+
+/// Arrives on `mbarrier` and makes it also wait for `bytes` bytes of asynchronous copies, such as tensor loads,
+/// to complete (`mbarrier.arrive.expect_tx.shared.b64`). Returns the state of the mbarrier before arriving.
+///
+/// # Safety
+///
+/// `mbarrier` must point to an initialized mbarrier in shared memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn mbarrier_arrive_expect_tx(mbarrier: *mut u64, bytes: u32) -> u64 {
+    let state: u64;
+    asm!(
+        "{{",
+        ".reg .u64 %bar;",
+        "cvta.to.shared.u64 %bar, {};",
+        "mbarrier.arrive.expect_tx.shared.b64 {}, [%bar], {};",
+        "}}",
+        in(reg64) mbarrier,
+        out(reg64) state,
+        in(reg32) bytes,
+        options(nostack)
+    );
+    state
+}
+
+/// Checks whether the phase of `mbarrier` with the parity `parity` (`0` for even phases, `1` for odd phases)
+/// has completed, potentially suspending the thread for a short time (`mbarrier.try_wait.parity.shared.b64`).
+/// This is usually called in a loop until it returns `true`.
+///
+/// # Safety
+///
+/// `mbarrier` must point to an initialized mbarrier in shared memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn mbarrier_try_wait_parity(mbarrier: *mut u64, parity: u32) -> bool {
+    let done: u32;
+    asm!(
+        "{{",
+        ".reg .u64 %bar;",
+        ".reg .pred %p;",
+        "cvta.to.shared.u64 %bar, {};",
+        "mbarrier.try_wait.parity.shared.b64 %p, [%bar], {};",
+        "selp.u32 {}, 1, 0, %p;",
+        "}}",
+        in(reg64) mbarrier,
+        in(reg32) parity,
+        out(reg32) done,
+        options(nostack)
+    );
+    done != 0
+}
+
+macro_rules! st_async {
+    ($($name:ident, $ty:ty, $ptx_ty:literal, $reg:ident);* $(;)?) => {
+        $(
+            #[doc = concat!("Asynchronously stores `value` to the shared memory `dst`, the store completes on `mbarrier` with the")]
+            #[doc = concat!("size of a `", stringify!($ty), "`. This is `st.async.shared::cluster.mbarrier::complete_tx::bytes.", $ptx_ty, "`.")]
+            ///
+            /// `dst` and `mbarrier` can be in the shared memory of another block of the cluster (a generic pointer to
+            /// it, such as from `mapa`), but they must be in the same block.
+            ///
+            /// # Safety
+            ///
+            /// `dst` must point to the shared memory of a block of the cluster and be valid for writing, `mbarrier`
+            /// must point to an initialized mbarrier in the shared memory of the same block.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(dst: *mut $ty, value: $ty, mbarrier: *mut u64) {
+                asm!(
+                    "{{",
+                    ".reg .u64 %dst, %bar;",
+                    "cvta.to.shared::cluster.u64 %dst, {dst};",
+                    "cvta.to.shared::cluster.u64 %bar, {bar};",
+                    concat!("st.async.shared::cluster.mbarrier::complete_tx::bytes.", $ptx_ty, " [%dst], {value}, [%bar];"),
+                    "}}",
+                    dst = in(reg64) dst,
+                    value = in($reg) value,
+                    bar = in(reg64) mbarrier,
+                    options(nostack)
+                );
+            }
+        )*
+    };
+}
+
+st_async! {
+    st_async_u32, u32, "b32", reg32;
+    st_async_i32, i32, "b32", reg32;
+    st_async_f32, f32, "b32", reg32;
+    st_async_u64, u64, "b64", reg64;
+    st_async_i64, i64, "b64", reg64;
+    st_async_f64, f64, "b64", reg64;
+}
+
+macro_rules! red_async {
+    ($($name:ident, $ty:ty, $op:literal, $ptx_ty:literal, $reg:ident);* $(;)?) => {
+        $(
+            #[doc = concat!("Asynchronously combines the `", stringify!($ty), "` in the shared memory `dst` with `value` using `", $op, "`, the")]
+            /// reduction completes on `mbarrier` with the size of the value. This is
+            #[doc = concat!("`red.async.relaxed.cluster.shared::cluster.mbarrier::complete_tx::bytes.", $op, ".", $ptx_ty, "`.")]
+            ///
+            /// `dst` and `mbarrier` can be in the shared memory of another block of the cluster (a generic pointer to
+            /// it, such as from `mapa`), but they must be in the same block.
+            ///
+            /// # Safety
+            ///
+            /// `dst` must point to the shared memory of a block of the cluster and be valid for reading and writing,
+            /// `mbarrier` must point to an initialized mbarrier in the shared memory of the same block.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(dst: *mut $ty, value: $ty, mbarrier: *mut u64) {
+                asm!(
+                    "{{",
+                    ".reg .u64 %dst, %bar;",
+                    "cvta.to.shared::cluster.u64 %dst, {dst};",
+                    "cvta.to.shared::cluster.u64 %bar, {bar};",
+                    concat!(
+                        "red.async.relaxed.cluster.shared::cluster.mbarrier::complete_tx::bytes.", $op, ".", $ptx_ty,
+                        " [%dst], {value}, [%bar];"
+                    ),
+                    "}}",
+                    dst = in(reg64) dst,
+                    value = in($reg) value,
+                    bar = in(reg64) mbarrier,
+                    options(nostack)
+                );
+            }
+        )*
+    };
+}
+
+red_async! {
+    red_async_add_u32, u32, "add", "u32", reg32;
+    red_async_add_i32, i32, "add", "s32", reg32;
+    red_async_add_u64, u64, "add", "u64", reg64;
+    red_async_min_u32, u32, "min", "u32", reg32;
+    red_async_min_i32, i32, "min", "s32", reg32;
+    red_async_max_u32, u32, "max", "u32", reg32;
+    red_async_max_i32, i32, "max", "s32", reg32;
+    red_async_inc_u32, u32, "inc", "u32", reg32;
+    red_async_dec_u32, u32, "dec", "u32", reg32;
+    red_async_and_u32, u32, "and", "b32", reg32;
+    red_async_or_u32, u32, "or", "b32", reg32;
+    red_async_xor_u32, u32, "xor", "b32", reg32;
+}
+
+/// Replaces the global address of the tensor described by the tensor map in global memory `tensor_map`
+/// (`tensormap.replace.tile.global_address.global.b1024.b64`). This requires `sm_90a`.
+///
+/// The modified tensor map must be made visible to tensor copies with [`fence_proxy_tensormap_release`]
+/// and [`fence_proxy_tensormap_acquire`] before it is used.
+///
+/// # Safety
+///
+/// `tensor_map` must point to a valid tensor map in global memory, which is not used by any copies in flight.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn tensormap_replace_global_address(tensor_map: *mut TensorMap, address: *const u8) {
+    asm!(
+        "tensormap.replace.tile.global_address.global.b1024.b64 [{}], {};",
+        in(reg64) tensor_map,
+        in(reg64) address,
+        options(nostack)
+    );
+}
+
+/// Replaces the size of dimension `dim` of the tensor described by the tensor map in global memory `tensor_map`
+/// (`tensormap.replace.tile.global_dim.global.b1024.b32`). This requires `sm_90a`.
+///
+/// The modified tensor map must be made visible to tensor copies with [`fence_proxy_tensormap_release`]
+/// and [`fence_proxy_tensormap_acquire`] before it is used.
+///
+/// # Panics
+///
+/// Panics if `dim` is not in the range of `0..=4`.
+///
+/// # Safety
+///
+/// `tensor_map` must point to a valid tensor map in global memory, which is not used by any copies in flight.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn tensormap_replace_global_dim(tensor_map: *mut TensorMap, dim: u32, size: u32) {
+    macro_rules! replace {
+        ($dim:literal) => {
+            asm!(
+                concat!("tensormap.replace.tile.global_dim.global.b1024.b32 [{}], ", $dim, ", {};"),
+                in(reg64) tensor_map,
+                in(reg32) size,
+                options(nostack)
+            )
+        };
+    }
+
+    // the dimension must be an immediate.
+    match dim {
+        0 => replace!(0),
+        1 => replace!(1),
+        2 => replace!(2),
+        3 => replace!(3),
+        4 => replace!(4),
+        _ => panic!("tensor maps only have 5 dimensions"),
+    }
+}
+
+/// Makes the writes of this thread to tensor maps visible to tensor copies issued by other threads of the
+/// GPU after they call [`fence_proxy_tensormap_acquire`] (`fence.proxy.tensormap::generic.release.gpu`).
+#[gpu_only]
+#[inline(always)]
+pub fn fence_proxy_tensormap_release() {
+    unsafe {
+        asm!(
+            "fence.proxy.tensormap::generic.release.gpu;",
+            options(nostack)
+        )
+    }
+}
+
+/// Makes the tensor map at `tensor_map`, modified and released by another thread with [`fence_proxy_tensormap_release`],
+/// visible to the tensor copies issued by this thread (`fence.proxy.tensormap::generic.acquire.gpu`).
+///
+/// # Safety
+///
+/// `tensor_map` must point to a valid tensor map in global memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn fence_proxy_tensormap_acquire(tensor_map: *const TensorMap) {
+    asm!(
+        "fence.proxy.tensormap::generic.acquire.gpu [{}], 128;",
+        in(reg64) tensor_map,
+        options(nostack)
+    );
+}
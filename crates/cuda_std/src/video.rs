@@ -0,0 +1,113 @@
+//! SIMD video instructions, which operate on every byte or halfword packed in a `u32` at once.
+//!
+//! These are the equivalents of CUDA C's `__vadd4`, `__vabsdiffu4`, etc. Functions ending in `4` treat
+//! their operands as four 8-bit lanes, functions ending in `2` as two 16-bit lanes. The lanes are unsigned
+//! unless the function name says otherwise:
+//!
+//! - `ss` functions treat the lanes as signed and saturate the result to the range of the lane.
+//! - `us` functions treat the lanes as unsigned and saturate the result to the range of the lane.
+//! - `s` and `u` functions treat the lanes as signed and unsigned respectively.
+//!
+//! They are useful in image processing kernels, for example [`vabsdiffu4`] computes the difference of
+//! four 8-bit pixels at once.
+
+use crate::gpu_only;
+
+macro_rules! video_simd {
+    ($($(#[$attr:meta])* $name:ident => $inst:literal),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            ///
+            #[doc = concat!("This is `", $inst, "`.")]
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name(a: u32, b: u32) -> u32 {
+                let out: u32;
+                unsafe {
+                    asm!(
+                        concat!($inst, " {}, {}, {}, {};"),
+                        out(reg32) out,
+                        in(reg32) a,
+                        in(reg32) b,
+                        // the value used for lanes which are masked out, every lane is written.
+                        in(reg32) 0u32,
+                        options(pure, nomem, nostack)
+                    );
+                }
+                out
+            }
+        )*
+    };
+}
+
+video_simd! {
+    /// Adds every byte of `a` to the corresponding byte of `b`, wrapping on overflow.
+    vadd4 => "vadd4.u32.u32.u32",
+    /// Adds every signed byte of `a` to the corresponding byte of `b`, saturating on overflow.
+    vaddss4 => "vadd4.s32.s32.s32.sat",
+    /// Adds every unsigned byte of `a` to the corresponding byte of `b`, saturating on overflow.
+    vaddus4 => "vadd4.u32.u32.u32.sat",
+    /// Subtracts every byte of `b` from the corresponding byte of `a`, wrapping on overflow.
+    vsub4 => "vsub4.u32.u32.u32",
+    /// Subtracts every signed byte of `b` from the corresponding byte of `a`, saturating on overflow.
+    vsubss4 => "vsub4.s32.s32.s32.sat",
+    /// Subtracts every unsigned byte of `b` from the corresponding byte of `a`, saturating on overflow.
+    vsubus4 => "vsub4.u32.u32.u32.sat",
+    /// The absolute difference of every signed byte of `a` and the corresponding byte of `b`.
+    vabsdiffs4 => "vabsdiff4.s32.s32.s32",
+    /// The absolute difference of every unsigned byte of `a` and the corresponding byte of `b`.
+    vabsdiffu4 => "vabsdiff4.u32.u32.u32",
+    /// The minimum of every signed byte of `a` and the corresponding byte of `b`.
+    vmins4 => "vmin4.s32.s32.s32",
+    /// The minimum of every unsigned byte of `a` and the corresponding byte of `b`.
+    vminu4 => "vmin4.u32.u32.u32",
+    /// The maximum of every signed byte of `a` and the corresponding byte of `b`.
+    vmaxs4 => "vmax4.s32.s32.s32",
+    /// The maximum of every unsigned byte of `a` and the corresponding byte of `b`.
+    vmaxu4 => "vmax4.u32.u32.u32",
+    /// Adds every halfword of `a` to the corresponding halfword of `b`, wrapping on overflow.
+    vadd2 => "vadd2.u32.u32.u32",
+    /// Adds every signed halfword of `a` to the corresponding halfword of `b`, saturating on overflow.
+    vaddss2 => "vadd2.s32.s32.s32.sat",
+    /// Adds every unsigned halfword of `a` to the corresponding halfword of `b`, saturating on overflow.
+    vaddus2 => "vadd2.u32.u32.u32.sat",
+    /// Subtracts every halfword of `b` from the corresponding halfword of `a`, wrapping on overflow.
+    vsub2 => "vsub2.u32.u32.u32",
+    /// Subtracts every signed halfword of `b` from the corresponding halfword of `a`, saturating on overflow.
+    vsubss2 => "vsub2.s32.s32.s32.sat",
+    /// Subtracts every unsigned halfword of `b` from the corresponding halfword of `a`, saturating on overflow.
+    vsubus2 => "vsub2.u32.u32.u32.sat",
+    /// The absolute difference of every signed halfword of `a` and the corresponding halfword of `b`.
+    vabsdiffs2 => "vabsdiff2.s32.s32.s32",
+    /// The absolute difference of every unsigned halfword of `a` and the corresponding halfword of `b`.
+    vabsdiffu2 => "vabsdiff2.u32.u32.u32",
+    /// The minimum of every signed halfword of `a` and the corresponding halfword of `b`.
+    vmins2 => "vmin2.s32.s32.s32",
+    /// The minimum of every unsigned halfword of `a` and the corresponding halfword of `b`.
+    vminu2 => "vmin2.u32.u32.u32",
+    /// The maximum of every signed halfword of `a` and the corresponding halfword of `b`.
+    vmaxs2 => "vmax2.s32.s32.s32",
+    /// The maximum of every unsigned halfword of `a` and the corresponding halfword of `b`.
+    vmaxu2 => "vmax2.u32.u32.u32",
+}
+
+/// The sum of the absolute differences of every unsigned byte of `a` and the corresponding byte of `b`, plus `c`.
+/// This is the core of block matching algorithms such as motion estimation.
+///
+/// This is `vabsdiff4.u32.u32.u32.add`.
+#[gpu_only]
+#[inline(always)]
+pub fn sad4(a: u32, b: u32, c: u32) -> u32 {
+    let out: u32;
+    unsafe {
+        asm!(
+            "vabsdiff4.u32.u32.u32.add {}, {}, {}, {};",
+            out(reg32) out,
+            in(reg32) a,
+            in(reg32) b,
+            in(reg32) c,
+            options(pure, nomem, nostack)
+        );
+    }
+    out
+}
@@ -5,7 +5,13 @@
 
 use crate::gpu_only;
 
-/// Synchronizes all of the threads inside of this warp according to `mask`.
+/// Synchronizes all of the threads inside of this warp according to `mask`. This is the equivalent
+/// of CUDA C's `__syncwarp(mask)` and lowers to a single `bar.warp.sync` instruction.
+///
+/// On compute_70 and above, threads inside of a warp are scheduled independently and are not guaranteed
+/// to reconverge after divergent code, this function must be used to explicitly reconverge them before
+/// doing warp-wide operations. `bar.warp.sync` is accepted by every architecture NVVM can target, so no
+/// architecture check is needed to call it.
 ///
 /// # Safety
 ///
@@ -40,3 +46,518 @@ pub fn lane_id() -> u32 {
     }
     out
 }
+
+/// Returns a 32-bit mask with a bit set in the position of the thread's lane within
+/// the warp. That is, `1 << lane_id()`.
+#[gpu_only]
+#[inline(always)]
+pub fn lanemask_eq() -> u32 {
+    extern "C" {
+        #[link_name = "llvm.nvvm.read.ptx.sreg.lanemask.eq"]
+        fn lanemask_eq() -> u32;
+    }
+
+    unsafe { lanemask_eq() }
+}
+
+/// Returns a 32-bit mask with bits set in positions less than or equal to the thread's
+/// lane within the warp.
+#[gpu_only]
+#[inline(always)]
+pub fn lanemask_le() -> u32 {
+    extern "C" {
+        #[link_name = "llvm.nvvm.read.ptx.sreg.lanemask.le"]
+        fn lanemask_le() -> u32;
+    }
+
+    unsafe { lanemask_le() }
+}
+
+/// Returns a 32-bit mask with bits set in positions less than the thread's lane within
+/// the warp. This is useful for warp-wide prefix sums, `(ballot & lanemask_lt()).count_ones()`
+/// is the number of preceding lanes in the warp that voted `true`.
+#[gpu_only]
+#[inline(always)]
+pub fn lanemask_lt() -> u32 {
+    extern "C" {
+        #[link_name = "llvm.nvvm.read.ptx.sreg.lanemask.lt"]
+        fn lanemask_lt() -> u32;
+    }
+
+    unsafe { lanemask_lt() }
+}
+
+/// Returns a 32-bit mask with bits set in positions greater than or equal to the thread's
+/// lane within the warp.
+#[gpu_only]
+#[inline(always)]
+pub fn lanemask_ge() -> u32 {
+    extern "C" {
+        #[link_name = "llvm.nvvm.read.ptx.sreg.lanemask.ge"]
+        fn lanemask_ge() -> u32;
+    }
+
+    unsafe { lanemask_ge() }
+}
+
+/// Returns a 32-bit mask with bits set in positions greater than the thread's lane within
+/// the warp.
+#[gpu_only]
+#[inline(always)]
+pub fn lanemask_gt() -> u32 {
+    extern "C" {
+        #[link_name = "llvm.nvvm.read.ptx.sreg.lanemask.gt"]
+        fn lanemask_gt() -> u32;
+    }
+
+    unsafe { lanemask_gt() }
+}
+
+/// Reads a 32-bit value from the lane `src_lane` of every thread inside of `mask`. This is the
+/// `shfl.sync.idx.b32` instruction, [`warp_broadcast`] is a higher level version of this for other types.
+///
+/// # Safety
+///
+/// The behavior of this function is undefined if:
+/// - Any thread inside `mask` has exited.
+/// - The executing thread is not inside of `mask`.
+/// - `src_lane` is not inside of `mask`, the returned value is undefined in this case.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn shuffle_idx_u32(mask: u32, value: u32, src_lane: u32) -> u32 {
+    shuffle_idx_width_u32(mask, value, src_lane, 32)
+}
+
+macro_rules! shuffle_width {
+    ($($(#[$attr:meta])* $name:ident, $lane:ident, $intrinsic:literal, $clamp:literal);* $(;)?) => {
+        $(
+            $(#[$attr])*
+            ///
+            /// The warp is split into segments of `width` lanes (CUDA C's `width` argument), lanes are relative to
+            /// the start of the segment of the thread and never cross into another segment.
+            ///
+            /// # Safety
+            ///
+            /// The behavior of this function is undefined if:
+            /// - Any thread inside `mask` has exited.
+            /// - The executing thread is not inside of `mask`.
+            /// - The lane which is read from is not inside of `mask`, the returned value is undefined in this case.
+            /// - `width` is not a power of two between `1` and `32`.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(mask: u32, value: u32, $lane: u32, width: u32) -> u32 {
+                extern "C" {
+                    #[link_name = $intrinsic]
+                    fn shfl_sync(mask: u32, value: u32, lane: u32, c: u32) -> u32;
+                }
+
+                // the `c` operand packs the segment mask in bits 8-12 and the clamp value in bits 0-4.
+                let c = ((32 - width) << 8) | $clamp;
+                shfl_sync(mask, value, $lane, c)
+            }
+        )*
+    };
+}
+
+shuffle_width! {
+    /// Reads a 32-bit value from the lane `src_lane` of the segment of every thread inside of `mask`
+    /// (`shfl.sync.idx.b32`). This is CUDA C's `__shfl_sync`.
+    shuffle_idx_width_u32, src_lane, "llvm.nvvm.shfl.sync.idx.i32", 0x1f;
+    /// Reads a 32-bit value from the lane `delta` lanes below the current one (`shfl.sync.up.b32`), threads
+    /// of the first `delta` lanes of a segment get their own value. This is CUDA C's `__shfl_up_sync`.
+    shuffle_up_width_u32, delta, "llvm.nvvm.shfl.sync.up.i32", 0x0;
+    /// Reads a 32-bit value from the lane `delta` lanes above the current one (`shfl.sync.down.b32`), threads
+    /// of the last `delta` lanes of a segment get their own value. This is CUDA C's `__shfl_down_sync`.
+    shuffle_down_width_u32, delta, "llvm.nvvm.shfl.sync.down.i32", 0x1f;
+    /// Reads a 32-bit value from the lane which is the current one xor `lane_mask` (`shfl.sync.bfly.b32`).
+    /// This is CUDA C's `__shfl_xor_sync`.
+    shuffle_xor_width_u32, lane_mask, "llvm.nvvm.shfl.sync.bfly.i32", 0x1f;
+}
+
+macro_rules! match_sync {
+    ($($any:ident, $all:ident, $ty:ty, $reg:ident, $bits:literal);* $(;)?) => {
+        $(
+            #[doc = concat!("Returns a mask of the threads inside of `mask` which have the same ", stringify!($bits), "-bit `value`")]
+            #[doc = concat!("as the executing thread (`match.any.sync.b", stringify!($bits), "`). This is CUDA C's `__match_any_sync`")]
+            /// and requires compute_70 or above.
+            ///
+            /// This is useful for partitioning a warp by key, every thread gets the group of threads
+            /// with the same key, and the lowest lane of each group can act as the leader of the group.
+            ///
+            /// # Safety
+            ///
+            /// The behavior of this function is undefined if:
+            /// - Any thread inside `mask` has exited.
+            /// - The executing thread is not inside of `mask`.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $any(mask: u32, value: $ty) -> u32 {
+                let out: u32;
+                asm!(
+                    concat!("match.any.sync.b", stringify!($bits), " {}, {}, {};"),
+                    out(reg32) out,
+                    in($reg) value,
+                    in(reg32) mask,
+                    options(nostack)
+                );
+                out
+            }
+
+            #[doc = concat!("Checks whether every thread inside of `mask` has the same ", stringify!($bits), "-bit `value`")]
+            #[doc = concat!("(`match.all.sync.b", stringify!($bits), "`). This is CUDA C's `__match_all_sync` and requires compute_70 or above.")]
+            ///
+            /// Returns the mask of threads which have the same value and whether all of them do. If not
+            /// every thread has the same value, the returned mask is `0` and the predicate is `false`,
+            /// otherwise the mask is `mask` and the predicate is `true`.
+            ///
+            /// # Safety
+            ///
+            /// The behavior of this function is undefined if:
+            /// - Any thread inside `mask` has exited.
+            /// - The executing thread is not inside of `mask`.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $all(mask: u32, value: $ty) -> (u32, bool) {
+                let out: u32;
+                let pred: u32;
+                asm!(
+                    "{{",
+                    ".reg .pred %p;",
+                    concat!("match.all.sync.b", stringify!($bits), " {}|%p, {}, {};"),
+                    "selp.u32 {}, 1, 0, %p;",
+                    "}}",
+                    out(reg32) out,
+                    in($reg) value,
+                    in(reg32) mask,
+                    out(reg32) pred,
+                    options(nostack)
+                );
+                (out, pred != 0)
+            }
+        )*
+    };
+}
+
+match_sync! {
+    match_any_sync_u32, match_all_sync_u32, u32, reg32, 32;
+    match_any_sync_u64, match_all_sync_u64, u64, reg64, 64;
+}
+
+macro_rules! vote_sync {
+    ($($name:ident, $mode:literal, $desc:literal);* $(;)?) => {
+        $(
+            #[doc = concat!("Returns whether `predicate` is `true` for ", $desc, " of the threads inside of `mask`")]
+            #[doc = concat!("(`vote.sync.", $mode, ".pred`). This is CUDA C's `__", $mode, "_sync`.")]
+            ///
+            /// The predicate can come from any comparison, such as one of 64-bit values.
+            ///
+            /// # Safety
+            ///
+            /// The behavior of this function is undefined if:
+            /// - Any thread inside `mask` has exited.
+            /// - The executing thread is not inside of `mask`.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(mask: u32, predicate: bool) -> bool {
+                let out: u32;
+                asm!(
+                    "{{",
+                    ".reg .pred %p<2>;",
+                    "setp.ne.u32 %p0, {}, 0;",
+                    concat!("vote.sync.", $mode, ".pred %p1, %p0, {};"),
+                    "selp.u32 {}, 1, 0, %p1;",
+                    "}}",
+                    in(reg32) predicate as u32,
+                    in(reg32) mask,
+                    out(reg32) out,
+                    options(nostack)
+                );
+                out != 0
+            }
+        )*
+    };
+}
+
+vote_sync! {
+    vote_all_sync, "all", "every one";
+    vote_any_sync, "any", "at least one";
+}
+
+/// Returns a mask with bit `n` set if `predicate` is `true` for lane `n`, for every thread inside of `mask`
+/// (`vote.sync.ballot.b32`). This is CUDA C's `__ballot_sync`.
+///
+/// # Safety
+///
+/// The behavior of this function is undefined if:
+/// - Any thread inside `mask` has exited.
+/// - The executing thread is not inside of `mask`.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn vote_ballot_sync(mask: u32, predicate: bool) -> u32 {
+    let out: u32;
+    asm!(
+        "{{",
+        ".reg .pred %p;",
+        "setp.ne.u32 %p, {}, 0;",
+        "vote.sync.ballot.b32 {}, %p, {};",
+        "}}",
+        in(reg32) predicate as u32,
+        out(reg32) out,
+        in(reg32) mask,
+        options(nostack)
+    );
+    out
+}
+
+/// Returns a mask of the threads of the warp which are currently executing this together with the calling
+/// thread (`activemask.b32`). This is CUDA C's `__activemask`.
+///
+/// This does not synchronize anything, threads which diverged are not waited for, so the mask is only the threads
+/// which happen to be converged right now.
+#[gpu_only]
+#[inline(always)]
+pub fn active_mask() -> u32 {
+    let out: u32;
+    unsafe {
+        asm!("activemask.b32 {};", out(reg32) out, options(nostack));
+    }
+    out
+}
+
+/// Returns a mask with bit `n` set if `predicate` is `true` for lane `n`, for every thread currently executing this
+/// together with the calling thread. This is [`vote_ballot_sync`] with the mask of [`active_mask`].
+///
+/// Passing `0xffffffff` to [`vote_ballot_sync`] is undefined behavior under divergence, when not every thread of
+/// the warp reaches the ballot, this is always safe to call. Threads which are not active have their bit unset.
+#[gpu_only]
+#[inline(always)]
+pub fn ballot(predicate: bool) -> u32 {
+    // the calling thread is always in its active mask, and none of the threads in it have exited.
+    unsafe { vote_ballot_sync(active_mask(), predicate) }
+}
+
+/// Elects a single leader thread out of the threads inside of `mask`, returning whether the calling
+/// thread is the leader along with the lane of the leader (`elect.sync`). Every thread of `mask`
+/// gets the same leader, which is useful to have only one thread of a warp do something, such as
+/// writing the result of a warp reduction:
+///
+/// ```ignore
+/// let sum = warp::warp_reduce_add_u32(mask, value);
+/// if warp::elect_sync(mask).0 {
+///     *out = sum;
+/// }
+/// ```
+///
+/// On compute_90 and above this is a single `elect.sync` instruction, older architectures fall back
+/// to electing the lowest lane with [`vote_ballot_sync`].
+///
+/// # Safety
+///
+/// The behavior of this function is undefined if:
+/// - Any thread inside `mask` has exited.
+/// - The executing thread is not inside of `mask`.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn elect_sync(mask: u32) -> (bool, u32) {
+    if crate::arch::CUDA_ARCH < 900 {
+        let leader = vote_ballot_sync(mask, true).trailing_zeros();
+        return (leader == lane_id(), leader);
+    }
+    let leader: u32;
+    let is_leader: u32;
+    asm!(
+        "{{",
+        ".reg .pred %p;",
+        "elect.sync {}|%p, {};",
+        "selp.u32 {}, 1, 0, %p;",
+        "}}",
+        out(reg32) leader,
+        in(reg32) mask,
+        out(reg32) is_leader,
+        options(nostack)
+    );
+    (is_leader != 0, leader)
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Types which can be exchanged between the lanes of a warp. Types larger than 32 bits are
+/// decomposed into multiple 32-bit shuffles and reassembled.
+pub trait WarpShuffle: Copy + sealed::Sealed {
+    #[doc(hidden)]
+    unsafe fn shuffle_idx(self, mask: u32, src_lane: u32) -> Self;
+}
+
+macro_rules! impl_warp_shuffle_32 {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $ty {}
+
+            impl WarpShuffle for $ty {
+                #[inline(always)]
+                unsafe fn shuffle_idx(self, mask: u32, src_lane: u32) -> Self {
+                    shuffle_idx_u32(mask, self as u32, src_lane) as $ty
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_warp_shuffle_64 {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $ty {}
+
+            impl WarpShuffle for $ty {
+                #[inline(always)]
+                unsafe fn shuffle_idx(self, mask: u32, src_lane: u32) -> Self {
+                    let bits = self as u64;
+                    let lo = shuffle_idx_u32(mask, bits as u32, src_lane) as u64;
+                    let hi = shuffle_idx_u32(mask, (bits >> 32) as u32, src_lane) as u64;
+                    ((hi << 32) | lo) as $ty
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_warp_shuffle_128 {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $ty {}
+
+            impl WarpShuffle for $ty {
+                #[inline(always)]
+                unsafe fn shuffle_idx(self, mask: u32, src_lane: u32) -> Self {
+                    let lo = (self as u64).shuffle_idx(mask, src_lane) as u128;
+                    let hi = ((self as u128 >> 64) as u64).shuffle_idx(mask, src_lane) as u128;
+                    ((hi << 64) | lo) as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_warp_shuffle_32!(u8, i8, u16, i16, u32, i32);
+impl_warp_shuffle_64!(u64, i64);
+impl_warp_shuffle_128!(u128, i128);
+
+#[cfg(target_pointer_width = "64")]
+impl_warp_shuffle_64!(usize, isize);
+#[cfg(target_pointer_width = "32")]
+impl_warp_shuffle_32!(usize, isize);
+
+impl sealed::Sealed for f32 {}
+
+impl WarpShuffle for f32 {
+    #[inline(always)]
+    unsafe fn shuffle_idx(self, mask: u32, src_lane: u32) -> Self {
+        f32::from_bits(self.to_bits().shuffle_idx(mask, src_lane))
+    }
+}
+
+impl sealed::Sealed for f64 {}
+
+impl WarpShuffle for f64 {
+    #[inline(always)]
+    unsafe fn shuffle_idx(self, mask: u32, src_lane: u32) -> Self {
+        f64::from_bits(self.to_bits().shuffle_idx(mask, src_lane))
+    }
+}
+
+/// Broadcasts `value` from the lane `src_lane` to every thread inside of `mask`, returning
+/// the value of `src_lane` on every thread. 64-bit and 128-bit values are split into 32-bit
+/// shuffles and reassembled.
+///
+/// # Safety
+///
+/// The behavior of this function is undefined if:
+/// - Any thread inside `mask` has exited.
+/// - The executing thread is not inside of `mask`.
+/// - `src_lane` is not inside of `mask`, the returned value is undefined in this case.
+#[inline(always)]
+pub unsafe fn warp_broadcast<T: WarpShuffle>(mask: u32, value: T, src_lane: u32) -> T {
+    value.shuffle_idx(mask, src_lane)
+}
+
+/// Combines `value` of every thread inside of `mask` with `op`, returning the result on every thread.
+///
+/// If `mask` is the full warp (`0xFFFFFFFF`) this is a butterfly of 5 shuffles, otherwise the value
+/// of every lane inside of `mask` is shuffled to every thread one after the other. `op` must be associative
+/// and commutative, for floats the result may differ slightly between lanes because of rounding.
+///
+/// # Safety
+///
+/// The behavior of this function is undefined if:
+/// - Any thread inside `mask` has exited.
+/// - The executing thread is not inside of `mask`.
+#[inline(always)]
+pub unsafe fn warp_reduce<T: WarpShuffle>(mask: u32, value: T, op: impl Fn(T, T) -> T) -> T {
+    if mask == u32::MAX {
+        let lane = lane_id();
+        let mut acc = value;
+        let mut offset = 16;
+        while offset > 0 {
+            acc = op(acc, acc.shuffle_idx(mask, lane ^ offset));
+            offset /= 2;
+        }
+        acc
+    } else {
+        let mut lanes = mask;
+        let mut acc = value.shuffle_idx(mask, lanes.trailing_zeros());
+        lanes &= lanes - 1;
+        while lanes != 0 {
+            acc = op(acc, value.shuffle_idx(mask, lanes.trailing_zeros()));
+            lanes &= lanes - 1;
+        }
+        acc
+    }
+}
+
+macro_rules! warp_reduce_redux {
+    ($($name:ident, $ty:ty, $op:literal, $ptx_ty:literal, $fallback:expr);* $(;)?) => {
+        $(
+            #[doc = concat!("Combines `value` of every thread inside of `mask` with `", $op, "`, returning the result on every thread.")]
+            #[doc = concat!("This is CUDA C's `__reduce_", $op, "_sync`.")]
+            ///
+            #[doc = concat!("On compute_80 and above this is a single `redux.sync.", $op, ".", $ptx_ty, "` instruction, older")]
+            /// architectures fall back to [`warp_reduce`].
+            ///
+            /// # Safety
+            ///
+            /// The behavior of this function is undefined if:
+            /// - Any thread inside `mask` has exited.
+            /// - The executing thread is not inside of `mask`.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(mask: u32, value: $ty) -> $ty {
+                if crate::arch::CUDA_ARCH < 800 {
+                    return warp_reduce(mask, value, $fallback);
+                }
+                let out: $ty;
+                asm!(
+                    concat!("redux.sync.", $op, ".", $ptx_ty, " {}, {}, {};"),
+                    out(reg32) out,
+                    in(reg32) value,
+                    in(reg32) mask,
+                    options(nostack)
+                );
+                out
+            }
+        )*
+    };
+}
+
+warp_reduce_redux! {
+    warp_reduce_add_u32, u32, "add", "u32", u32::wrapping_add;
+    warp_reduce_add_i32, i32, "add", "s32", i32::wrapping_add;
+    warp_reduce_min_u32, u32, "min", "u32", u32::min;
+    warp_reduce_min_i32, i32, "min", "s32", i32::min;
+    warp_reduce_max_u32, u32, "max", "u32", u32::max;
+    warp_reduce_max_i32, i32, "max", "s32", i32::max;
+    warp_reduce_and_u32, u32, "and", "b32", |a, b| a & b;
+    warp_reduce_or_u32, u32, "or", "b32", |a, b| a | b;
+    warp_reduce_xor_u32, u32, "xor", "b32", |a, b| a ^ b;
+}
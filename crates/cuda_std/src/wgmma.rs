@@ -0,0 +1,188 @@
+//! Warpgroup matrix multiply-accumulate (`wgmma`) of `sm_90a`.
+//!
+//! A warpgroup is 4 consecutive warps of a block (128 threads, starting at a thread index which is a multiple
+//! of 128), which together compute `D = A * B + D` for a 64xNx16 tile. `A` is 64x16 and `B` is 16xN, both are
+//! read from shared memory through [`MatrixDescriptor`]s, and `D` is spread over the registers of the threads
+//! as an array of `N / 2` floats each. The multiplication is asynchronous, so it is issued in a sequence like:
+//!
+//! ```ignore
+//! wgmma_fence();
+//! wgmma_m64n64k16_f32_f16(&mut acc, a, b, true);
+//! wgmma_commit_group();
+//! wgmma_wait_group(0);
+//! // `acc` can be read now.
+//! ```
+//!
+//! Both matrices are K-major (`A` row major, `B` column major). The layout of `D` and of the matrices in shared
+//! memory is described in the [PTX ISA](https://docs.nvidia.com/cuda/parallel-thread-execution/index.html#asynchronous-warpgroup-level-matrix-instructions).
+//!
+//! `wgmma` is an architecture-specific feature, this module only exists when compiling for `compute_90a`
+//! (`-arch=compute_90a` or `-Ctarget-cpu=sm_90a`), code using it only runs on devices with compute capability 9.0.
+
+use crate::gpu_only;
+
+/// How a matrix in shared memory is swizzled, which must match the swizzling it was written with, such as
+/// by a tensor copy.
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Swizzle {
+    /// The matrix is not swizzled.
+    None = 0,
+    /// 128-byte swizzling, the same as `CU_TENSOR_MAP_SWIZZLE_128B`.
+    Bytes128 = 1,
+    /// 64-byte swizzling, the same as `CU_TENSOR_MAP_SWIZZLE_64B`.
+    Bytes64 = 2,
+    /// 32-byte swizzling, the same as `CU_TENSOR_MAP_SWIZZLE_32B`.
+    Bytes32 = 3,
+}
+
+/// Describes where and how a matrix operand of `wgmma` is laid out in shared memory.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatrixDescriptor(pub u64);
+
+/// Creates the descriptor of a matrix in shared memory starting at `matrix`, with `leading_byte_offset` and
+/// `stride_byte_offset` being the distances in bytes between the core matrices in the leading and strided
+/// dimensions. `matrix` and both offsets must be multiples of 16 bytes.
+///
+/// # Safety
+///
+/// `matrix` must point to shared memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn matrix_descriptor(
+    matrix: *const u8,
+    leading_byte_offset: u32,
+    stride_byte_offset: u32,
+    swizzle: Swizzle,
+) -> MatrixDescriptor {
+    let addr: u64;
+    asm!(
+        "cvta.to.shared.u64 {}, {};",
+        out(reg64) addr,
+        in(reg64) matrix,
+        options(pure, nomem, nostack)
+    );
+    // the fields are in units of 16 bytes.
+    let encode = |bytes: u64| (bytes & 0x3ffff) >> 4;
+    MatrixDescriptor(
+        encode(addr)
+            | encode(leading_byte_offset as u64) << 16
+            | encode(stride_byte_offset as u64) << 32
+            | (swizzle as u64) << 62,
+    )
+}
+
+/// Makes the registers and shared memory written by this thread before it visible to the following `wgmma`s
+/// (`wgmma.fence.sync.aligned`). This is required before the first `wgmma` and whenever the accumulators or the
+/// matrices are written by anything else in between.
+#[gpu_only]
+#[inline(always)]
+pub fn wgmma_fence() {
+    unsafe { asm!("wgmma.fence.sync.aligned;", options(nostack)) }
+}
+
+/// Commits all the `wgmma`s issued by this warpgroup since the last commit into a group, which can then be waited
+/// on with [`wgmma_wait_group`] (`wgmma.commit_group.sync.aligned`).
+#[gpu_only]
+#[inline(always)]
+pub fn wgmma_commit_group() {
+    unsafe { asm!("wgmma.commit_group.sync.aligned;", options(nostack)) }
+}
+
+/// Waits until at most `n` of the most recently committed `wgmma` groups are still pending
+/// (`wgmma.wait_group.sync.aligned`), after which their accumulators can be read. `n` above `7` waits for every group.
+#[gpu_only]
+#[inline(always)]
+pub fn wgmma_wait_group(n: u32) {
+    unsafe { wait_group!("wgmma.wait_group.sync.aligned", n) }
+}
+
+macro_rules! wgmma {
+    ($($name:ident, $ty:literal, $n:literal, $regs:literal, [$first:ident $(, $rest:ident)*];)*) => {
+        $(
+            #[doc = concat!("Starts `acc = a * b + acc` for a 64x", $n, "x16 tile, with `a` and `b` being `", $ty, "` matrices in")]
+            /// shared memory and `acc` the `f32` accumulators of this thread. If `accumulate` is `false`, `acc` is
+            /// overwritten with `a * b` instead. The multiplication is asynchronous, `acc` must not be accessed until
+            /// it was waited on with [`wgmma_wait_group`].
+            ///
+            #[doc = concat!("This is `wgmma.mma_async.sync.aligned.m64n", $n, "k16.f32.", $ty, ".", $ty, "`.")]
+            ///
+            /// # Safety
+            ///
+            /// This must be called by all the threads of the warpgroup with the same `a`, `b` and `accumulate`,
+            /// after a [`wgmma_fence`]. Both descriptors must describe valid matrices in shared memory, which must not be
+            /// written until the multiplication completed.
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $name(acc: &mut [f32; $regs], a: MatrixDescriptor, b: MatrixDescriptor, accumulate: bool) {
+                let [mut $first, $(mut $rest),*] = *acc;
+                asm!(
+                    "{{",
+                    ".reg .pred %p;",
+                    "setp.ne.b32 %p, {scale_d}, 0;",
+                    concat!(
+                        "wgmma.mma_async.sync.aligned.m64n", $n, "k16.f32.", $ty, ".", $ty, " ",
+                        "{{{", stringify!($first), "}", $(", {", stringify!($rest), "}",)* "}}, ",
+                        "{a}, {b}, %p, 1, 1, 0, 0;"
+                    ),
+                    "}}",
+                    $first = inout(reg32) $first,
+                    $($rest = inout(reg32) $rest,)*
+                    a = in(reg64) a.0,
+                    b = in(reg64) b.0,
+                    scale_d = in(reg32) accumulate as u32,
+                    options(nostack)
+                );
+                *acc = [$first, $($rest),*];
+            }
+        )*
+    };
+}
+
+wgmma! {
+    wgmma_m64n8k16_f32_f16, "f16", "8", 4,
+        [d0, d1, d2, d3];
+    wgmma_m64n16k16_f32_f16, "f16", "16", 8,
+        [d0, d1, d2, d3, d4, d5, d6, d7];
+    wgmma_m64n32k16_f32_f16, "f16", "32", 16,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15];
+    wgmma_m64n64k16_f32_f16, "f16", "64", 32,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, d16, d17, d18, d19, d20, d21,
+         d22, d23, d24, d25, d26, d27, d28, d29, d30, d31];
+    wgmma_m64n128k16_f32_f16, "f16", "128", 64,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, d16, d17, d18, d19, d20, d21,
+         d22, d23, d24, d25, d26, d27, d28, d29, d30, d31, d32, d33, d34, d35, d36, d37, d38, d39, d40, d41,
+         d42, d43, d44, d45, d46, d47, d48, d49, d50, d51, d52, d53, d54, d55, d56, d57, d58, d59, d60, d61,
+         d62, d63];
+    wgmma_m64n256k16_f32_f16, "f16", "256", 128,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, d16, d17, d18, d19, d20, d21,
+         d22, d23, d24, d25, d26, d27, d28, d29, d30, d31, d32, d33, d34, d35, d36, d37, d38, d39, d40, d41,
+         d42, d43, d44, d45, d46, d47, d48, d49, d50, d51, d52, d53, d54, d55, d56, d57, d58, d59, d60, d61,
+         d62, d63, d64, d65, d66, d67, d68, d69, d70, d71, d72, d73, d74, d75, d76, d77, d78, d79, d80, d81,
+         d82, d83, d84, d85, d86, d87, d88, d89, d90, d91, d92, d93, d94, d95, d96, d97, d98, d99, d100,
+         d101, d102, d103, d104, d105, d106, d107, d108, d109, d110, d111, d112, d113, d114, d115, d116,
+         d117, d118, d119, d120, d121, d122, d123, d124, d125, d126, d127];
+    wgmma_m64n8k16_f32_bf16, "bf16", "8", 4,
+        [d0, d1, d2, d3];
+    wgmma_m64n16k16_f32_bf16, "bf16", "16", 8,
+        [d0, d1, d2, d3, d4, d5, d6, d7];
+    wgmma_m64n32k16_f32_bf16, "bf16", "32", 16,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15];
+    wgmma_m64n64k16_f32_bf16, "bf16", "64", 32,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, d16, d17, d18, d19, d20, d21,
+         d22, d23, d24, d25, d26, d27, d28, d29, d30, d31];
+    wgmma_m64n128k16_f32_bf16, "bf16", "128", 64,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, d16, d17, d18, d19, d20, d21,
+         d22, d23, d24, d25, d26, d27, d28, d29, d30, d31, d32, d33, d34, d35, d36, d37, d38, d39, d40, d41,
+         d42, d43, d44, d45, d46, d47, d48, d49, d50, d51, d52, d53, d54, d55, d56, d57, d58, d59, d60, d61,
+         d62, d63];
+    wgmma_m64n256k16_f32_bf16, "bf16", "256", 128,
+        [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15, d16, d17, d18, d19, d20, d21,
+         d22, d23, d24, d25, d26, d27, d28, d29, d30, d31, d32, d33, d34, d35, d36, d37, d38, d39, d40, d41,
+         d42, d43, d44, d45, d46, d47, d48, d49, d50, d51, d52, d53, d54, d55, d56, d57, d58, d59, d60, d61,
+         d62, d63, d64, d65, d66, d67, d68, d69, d70, d71, d72, d73, d74, d75, d76, d77, d78, d79, d80, d81,
+         d82, d83, d84, d85, d86, d87, d88, d89, d90, d91, d92, d93, d94, d95, d96, d97, d98, d99, d100,
+         d101, d102, d103, d104, d105, d106, d107, d108, d109, d110, d111, d112, d113, d114, d115, d116,
+         d117, d118, d119, d120, d121, d122, d123, d124, d125, d126, d127];
+}
@@ -3,7 +3,7 @@ use proc_macro2::Span;
 use quote::{quote_spanned, ToTokens};
 use syn::{
     parse::Parse, parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned, Error,
-    FnArg, Ident, ItemFn, ReturnType, Stmt, Token,
+    FnArg, Ident, ItemFn, LitInt, ReturnType, Stmt, Token, Type,
 };
 
 /// Registers a function as a gpu kernel.
@@ -17,12 +17,38 @@ use syn::{
 /// - Makes sure function parameters are all [`Copy`].
 /// - Makes sure the function doesn't return anything.
 ///
+/// The maximum amount of threads per block the kernel will be launched with, and optionally the minimum
+/// amount of blocks which should be able to run at once on a multiprocessor, can be given with
+/// `#[kernel(launch_bounds(max_threads, min_blocks))]`. This is the same as CUDA C's `__launch_bounds__`,
+/// it lets ptxas limit the registers used by the kernel, and the codegen warns if doing so will likely spill registers.
+///
+/// Instead of hand-tuning launch bounds, `#[kernel(occupancy(percent))]` derives them from the percentage of the
+/// maximum threads per multiprocessor of the target arch which should be able to run at once, `occupancy(100)`
+/// asks for the maximum occupancy. The occupancy is lowered if the estimated registers of the kernel do not allow
+/// it, and the kernel must then be launched with at most the derived `maxntidx` threads per block, which is emitted
+/// in the PTX and the kernel manifest. It cannot be combined with `launch_bounds`.
+///
+/// The dimensions of the thread block clusters the kernel is launched with can be given with
+/// `#[kernel(cluster_dims(x, y, z))]`, this is the same as CUDA C's `__cluster_dims__` and requires `compute_90`
+/// or above. Omitted dimensions are `1`.
+///
+/// `#[kernel(weak)]` emits the kernel as a `.weak .entry`, which is useful for libraries which ship default
+/// kernels that applications can override. When linking with `nvlink`, a regular kernel with the same name
+/// replaces the weak one.
+///
+/// Pointer and reference params can be marked with `#[restrict]`, which is the same as CUDA C's `__restrict__`.
+/// It promises that the memory accessed through the param is not accessed through any other param for the duration
+/// of the kernel, which lets the compiler reuse loads and reorder memory accesses more aggressively. `#[readonly]`
+/// promises that the kernel never writes through the param, so it can only be used on `*const T` and `&T`
+/// params where `T` has no interior mutability (`&AtomicU32` is rejected for example). Both are often used together for inputs, such as `#[restrict] #[readonly] input: *const f32`.
+/// Breaking either promise is undefined behavior.
+///
 /// Note that this does not cfg the function for nvptx(64), that is explicit so that rust analyzer is able to
 /// offer intellisense by default.
 #[proc_macro_attribute]
 pub fn kernel(input: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
     let cloned = input.clone();
-    let _ = parse_macro_input!(input as KernelHints);
+    let hints = parse_macro_input!(input as KernelHints);
     let input = parse_macro_input!(cloned as proc_macro2::TokenStream);
     let mut item = parse_macro_input!(item as ItemFn);
     let no_mangle = parse_quote!(#[no_mangle]);
@@ -30,6 +56,76 @@ pub fn kernel(input: proc_macro::TokenStream, item: proc_macro::TokenStream) ->
     let internal = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(kernel(#input)))]);
     item.attrs.push(internal);
 
+    if let Some((max_threads, min_blocks)) = hints.launch_bounds {
+        let bounds = match min_blocks {
+            Some(min_blocks) => quote::quote! { #max_threads, #min_blocks },
+            None => quote::quote! { #max_threads },
+        };
+        let launch_bounds = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(launch_bounds(#bounds)))]);
+        item.attrs.push(launch_bounds);
+    }
+
+    if let Some(percent) = hints.occupancy {
+        let occupancy = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(occupancy(#percent)))]);
+        item.attrs.push(occupancy);
+    }
+
+    if let Some((x, y, z)) = hints.cluster_dims {
+        let cluster_dims = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(cluster_dims(#x, #y, #z)))]);
+        item.attrs.push(cluster_dims);
+    }
+
+    if hints.weak {
+        let weak = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(weak))]);
+        item.attrs.push(weak);
+    }
+
+    // `#[restrict]` and `#[readonly]` are not valid on params outside of this macro, so move them to the function.
+    let mut restrict = vec![];
+    let mut readonly = vec![];
+    let mut param_errors: Vec<Stmt> = vec![];
+    for (idx, param) in item.sig.inputs.iter_mut().enumerate() {
+        let param = match param {
+            FnArg::Typed(param) => param,
+            FnArg::Receiver(_) => continue,
+        };
+        let idx = idx as u32;
+        let mutable = match &*param.ty {
+            Type::Ptr(ptr) => Some(ptr.mutability.is_some()),
+            Type::Reference(reference) => Some(reference.mutability.is_some()),
+            _ => None,
+        };
+        let mut errors = vec![];
+        param.attrs.retain(|attr| {
+            let is_restrict = attr.path.is_ident("restrict");
+            let is_readonly = attr.path.is_ident("readonly");
+            if !is_restrict && !is_readonly {
+                return true;
+            }
+            match mutable {
+                None => errors.push(quote_spanned! {
+                    attr.span() => ::core::compile_error!("`restrict` and `readonly` can only be used on pointer and reference params");
+                }),
+                Some(true) if is_readonly => errors.push(quote_spanned! {
+                    attr.span() => ::core::compile_error!("`readonly` cannot be used on mutable pointers, use `*const T` or `&T` instead");
+                }),
+                _ if is_restrict => restrict.push(idx),
+                _ => readonly.push(idx),
+            }
+            false
+        });
+        param_errors.extend(errors.into_iter().map(|err| parse_quote!(#err;)));
+    }
+    if !restrict.is_empty() {
+        item.attrs.push(parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(restrict(#(#restrict),*)))]));
+    }
+    if !readonly.is_empty() {
+        item.attrs.push(parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(readonly(#(#readonly),*)))]));
+    }
+    for err in param_errors {
+        item.block.stmts.insert(0, err);
+    }
+
     // used to guarantee some things about how params are passed in the codegen.
     item.sig.abi = Some(parse_quote!(extern "C"));
 
@@ -94,12 +190,66 @@ impl Parse for Dimension {
 enum KernelHint {
     GridDim(Dimension),
     BlockDim(Dimension),
+    LaunchBounds(u32, Option<u32>),
+    ClusterDims(u32, u32, u32),
+    Occupancy(u32),
+    Weak,
 }
 
 impl Parse for KernelHint {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let name = Ident::parse(input)?;
         let key = name.to_string();
+        if key == "launch_bounds" {
+            let content;
+            syn::parenthesized!(content in input);
+            let bounds = Punctuated::<LitInt, Token![,]>::parse_terminated(&content)?
+                .iter()
+                .map(|lit| lit.base10_parse::<u32>())
+                .collect::<syn::Result<Vec<_>>>()?;
+            return match *bounds.as_slice() {
+                [max_threads] if max_threads > 0 => Ok(Self::LaunchBounds(max_threads, None)),
+                [max_threads, min_blocks] if max_threads > 0 => {
+                    Ok(Self::LaunchBounds(max_threads, Some(min_blocks)))
+                }
+                _ => Err(Error::new(
+                    name.span(),
+                    "Expected `launch_bounds(max_threads)` or `launch_bounds(max_threads, min_blocks)`",
+                )),
+            };
+        }
+        if key == "cluster_dims" {
+            let content;
+            syn::parenthesized!(content in input);
+            let dims = Punctuated::<LitInt, Token![,]>::parse_terminated(&content)?
+                .iter()
+                .map(|lit| lit.base10_parse::<u32>())
+                .collect::<syn::Result<Vec<_>>>()?;
+            return match *dims.as_slice() {
+                [x] if x > 0 => Ok(Self::ClusterDims(x, 1, 1)),
+                [x, y] if x > 0 && y > 0 => Ok(Self::ClusterDims(x, y, 1)),
+                [x, y, z] if x > 0 && y > 0 && z > 0 => Ok(Self::ClusterDims(x, y, z)),
+                _ => Err(Error::new(
+                    name.span(),
+                    "Expected `cluster_dims(x)`, `cluster_dims(x, y)` or `cluster_dims(x, y, z)` with non-zero dimensions",
+                )),
+            };
+        }
+        if key == "weak" {
+            return Ok(Self::Weak);
+        }
+        if key == "occupancy" {
+            let content;
+            syn::parenthesized!(content in input);
+            let percent = LitInt::parse(&content)?.base10_parse::<u32>()?;
+            return match percent {
+                1..=100 => Ok(Self::Occupancy(percent)),
+                _ => Err(Error::new(
+                    name.span(),
+                    "Expected `occupancy(percent)` with a percentage from 1 to 100",
+                )),
+            };
+        }
         <Token![=]>::parse(input)?;
         match key.as_str() {
             "grid_dim" => {
@@ -119,6 +269,10 @@ impl Parse for KernelHint {
 struct KernelHints {
     grid_dim: Option<Dimension>,
     block_dim: Option<Dimension>,
+    launch_bounds: Option<(u32, Option<u32>)>,
+    cluster_dims: Option<(u32, u32, u32)>,
+    occupancy: Option<u32>,
+    weak: bool,
 }
 
 impl Parse for KernelHints {
@@ -135,9 +289,22 @@ impl Parse for KernelHints {
             match hint {
                 KernelHint::GridDim(dim) => out.grid_dim = Some(dim),
                 KernelHint::BlockDim(dim) => out.block_dim = Some(dim),
+                KernelHint::LaunchBounds(max_threads, min_blocks) => {
+                    out.launch_bounds = Some((max_threads, min_blocks))
+                }
+                KernelHint::ClusterDims(x, y, z) => out.cluster_dims = Some((x, y, z)),
+                KernelHint::Occupancy(percent) => out.occupancy = Some(percent),
+                KernelHint::Weak => out.weak = true,
             }
         }
 
+        if out.launch_bounds.is_some() && out.occupancy.is_some() {
+            return Err(Error::new(
+                input.span(),
+                "`launch_bounds` and `occupancy` cannot be used together",
+            ));
+        }
+
         Ok(out)
     }
 }
@@ -184,3 +351,121 @@ pub fn gpu_only(_attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -
 
     output.into()
 }
+
+/// Marks a `#[repr(transparent)]` struct wrapping a raw pointer as a pointer into a specific address space,
+/// so the codegen emits the pointer as a pointer in that address space (for example `i8 addrspace(3)*` for
+/// shared memory). Casting it to or from a generic pointer is an `addrspacecast`, other casts are an error.
+///
+/// The address space can be `global`, `shared`, `constant`, `local`, or an NVVM address space number.
+///
+/// ```ignore
+/// #[address_space(shared)]
+/// #[repr(transparent)]
+/// pub struct SharedPtr<T>(*mut T);
+/// ```
+///
+/// It can also be used with `constant` on a static to place it in constant memory, which makes reads go through
+/// the constant cache. That is fastest when all the threads of a warp read the same address. Statics in constant
+/// memory must not be mutable or have interior mutability and cannot contain references or pointers. Large read-only
+/// constants, such as lookup tables in a `const`, are placed in constant memory automatically, so a table which
+/// is read at different addresses by the threads of a warp is better off in a regular static.
+///
+/// ```ignore
+/// #[address_space(constant)]
+/// static COEFFICIENTS: [f32; 16] = compute_coefficients();
+/// ```
+#[proc_macro_attribute]
+pub fn address_space(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let space = parse_macro_input!(attr as syn::Expr);
+    let item = parse_macro_input!(item as syn::Item);
+    match &item {
+        syn::Item::Struct(_) => {}
+        syn::Item::Static(_) => {
+            let is_constant =
+                matches!(&space, syn::Expr::Path(path) if path.path.is_ident("constant"));
+            if !is_constant {
+                return Error::new(
+                    space.span(),
+                    "statics can only be placed in the `constant` address space",
+                )
+                .to_compile_error()
+                .into();
+            }
+        }
+        _ => {
+            return Error::new(item.span(), "expected a pointer wrapper struct or a static")
+                .to_compile_error()
+                .into()
+        }
+    }
+
+    let addrspace = match &space {
+        syn::Expr::Path(path) => match path.path.get_ident().map(|x| x.to_string()).as_deref() {
+            Some("global") => 1u32,
+            Some("shared") => 3,
+            Some("constant") => 4,
+            Some("local") => 5,
+            _ => {
+                return Error::new(
+                    space.span(),
+                    "expected `global`, `shared`, `constant`, `local`, or an address space number",
+                )
+                .to_compile_error()
+                .into()
+            }
+        },
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(int),
+            ..
+        }) => match int.base10_parse::<u32>() {
+            Ok(n) => n,
+            Err(e) => return e.to_compile_error().into(),
+        },
+        _ => {
+            return Error::new(space.span(), "expected an address space")
+                .to_compile_error()
+                .into()
+        }
+    };
+    let addrspace = syn::LitInt::new(&addrspace.to_string(), space.span());
+
+    quote::quote! {
+        #[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(addrspace(#addrspace)))]
+        #item
+    }
+    .into()
+}
+
+/// Marks a static as used, the same as `#[used]`, but chooses how long it is kept around.
+///
+/// - `#[used_by(linker)]` is the same as `#[used]`, the static is placed in `llvm.used`, so it is kept in the PTX
+///   even if nothing uses it and can be found from the host or other modules when linking.
+/// - `#[used_by(compiler)]` places the static in `llvm.compiler.used` instead, which only keeps it from being
+///   removed while the crate is optimized. libnvvm may still remove it from the PTX if no device code uses it.
+///
+/// ```ignore
+/// #[used_by(compiler)]
+/// static TABLE: [u32; 4] = [1, 2, 3, 4];
+/// ```
+#[proc_macro_attribute]
+pub fn used_by(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let kind = parse_macro_input!(attr as Ident);
+    let item = parse_macro_input!(item as syn::ItemStatic);
+
+    match kind.to_string().as_str() {
+        "linker" => quote::quote! {
+            #[used]
+            #item
+        }
+        .into(),
+        "compiler" => quote::quote! {
+            #[used]
+            #[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(used(compiler)))]
+            #item
+        }
+        .into(),
+        _ => Error::new(kind.span(), "expected `compiler` or `linker`")
+            .to_compile_error()
+            .into(),
+    }
+}
@@ -254,6 +254,8 @@ impl FromStr for NvvmOption {
                     "72" => NvvmArch::Compute72,
                     "75" => NvvmArch::Compute75,
                     "80" => NvvmArch::Compute80,
+                    "90" => NvvmArch::Compute90,
+                    "90a" => NvvmArch::Compute90a,
                     _ => return Err("unknown arch"),
                 };
                 Self::Arch(arch)
@@ -264,7 +266,7 @@ impl FromStr for NvvmOption {
 }
 
 /// Nvvm architecture, default is `Compute52`
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum NvvmArch {
     Compute35,
     Compute37,
@@ -278,6 +280,10 @@ pub enum NvvmArch {
     Compute72,
     Compute75,
     Compute80,
+    Compute90,
+    /// `compute_90` with the architecture-specific features of Hopper, such as `wgmma`. Code compiled for it
+    /// only runs on devices with exactly compute capability 9.0.
+    Compute90a,
 }
 
 impl Display for NvvmArch {
@@ -294,6 +300,68 @@ impl Default for NvvmArch {
     }
 }
 
+impl NvvmArch {
+    /// Every architecture supported by libnvvm, from oldest to newest.
+    pub const ALL: &'static [NvvmArch] = &[
+        Self::Compute35,
+        Self::Compute37,
+        Self::Compute50,
+        Self::Compute52,
+        Self::Compute53,
+        Self::Compute60,
+        Self::Compute61,
+        Self::Compute62,
+        Self::Compute70,
+        Self::Compute72,
+        Self::Compute75,
+        Self::Compute80,
+        Self::Compute90,
+        Self::Compute90a,
+    ];
+
+    /// The architecture with the compute capability `major * 10 + minor`, `None` if libnvvm does not support it.
+    /// This is never an architecture-specific variant such as `compute_90a`.
+    pub fn from_capability(capability: u32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|arch| arch.capability() == capability)
+    }
+
+    /// The compute capability of this architecture as `major * 10 + minor`, for example `61` for `compute_61`.
+    pub fn capability(&self) -> u32 {
+        match self {
+            Self::Compute35 => 35,
+            Self::Compute37 => 37,
+            Self::Compute50 => 50,
+            Self::Compute52 => 52,
+            Self::Compute53 => 53,
+            Self::Compute60 => 60,
+            Self::Compute61 => 61,
+            Self::Compute62 => 62,
+            Self::Compute70 => 70,
+            Self::Compute72 => 72,
+            Self::Compute75 => 75,
+            Self::Compute80 => 80,
+            Self::Compute90 | Self::Compute90a => 90,
+        }
+    }
+
+    /// Whether this is an architecture-specific variant (the `a` suffix), whose features are not available
+    /// on any other architecture, including newer ones.
+    pub fn is_arch_specific(&self) -> bool {
+        matches!(self, Self::Compute90a)
+    }
+
+    /// The architecture-specific variant of this architecture, `None` if it has none.
+    pub fn arch_specific(&self) -> Option<Self> {
+        match self {
+            Self::Compute90 | Self::Compute90a => Some(Self::Compute90a),
+            _ => None,
+        }
+    }
+}
+
 pub struct NvvmProgram {
     raw: sys::nvvmProgram,
 }
@@ -432,6 +500,8 @@ mod tests {
             "-arch=compute_72",
             "-arch=compute_75",
             "-arch=compute_80",
+            "-arch=compute_90",
+            "-arch=compute_90a",
             "-ftz=1",
             "-prec-sqrt=0",
             "-prec-div=0",
@@ -453,6 +523,8 @@ mod tests {
             Arch(Compute72),
             Arch(Compute75),
             Arch(Compute80),
+            Arch(Compute90),
+            Arch(Compute90a),
             Ftz,
             FastSqrt,
             FastDiv,
@@ -466,4 +538,40 @@ mod tests {
 
         assert_eq!(found, expected);
     }
+
+    #[test]
+    fn arch_capabilities_are_ordered() {
+        use crate::NvvmArch;
+
+        let generic = NvvmArch::ALL
+            .iter()
+            .filter(|arch| !arch.is_arch_specific())
+            .collect::<Vec<_>>();
+        for pair in generic.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert!(pair[0].capability() < pair[1].capability());
+        }
+        for arch in generic {
+            assert_eq!(arch.to_string(), format!("compute_{}", arch.capability()));
+            assert_eq!(NvvmArch::from_capability(arch.capability()), Some(*arch));
+        }
+        assert_eq!(NvvmArch::from_capability(51), None);
+    }
+
+    #[test]
+    fn arch_specific_archs_follow_their_generic_arch() {
+        use crate::NvvmArch;
+
+        for pair in NvvmArch::ALL.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert!(pair[0].capability() <= pair[1].capability());
+        }
+        for arch in NvvmArch::ALL.iter().filter(|arch| arch.is_arch_specific()) {
+            assert_eq!(arch.to_string(), format!("compute_{}a", arch.capability()));
+            let generic = NvvmArch::from_capability(arch.capability()).unwrap();
+            assert!(generic < *arch);
+            assert_eq!(generic.arch_specific(), Some(*arch));
+        }
+        assert_eq!(NvvmArch::Compute80.arch_specific(), None);
+    }
 }
@@ -15,10 +15,12 @@
 
 #include "rustllvm.h"
 
+#include "llvm/Analysis/LoopInfo.h"
 #include "llvm/Analysis/TargetLibraryInfo.h"
 #include "llvm/Analysis/TargetTransformInfo.h"
 #include "llvm/IR/AutoUpgrade.h"
 #include "llvm/IR/AssemblyAnnotationWriter.h"
+#include "llvm/IR/Dominators.h"
 #include "llvm/Support/CBindingWrapping.h"
 #include "llvm/Support/FileSystem.h"
 #include "llvm/Support/Host.h"
@@ -550,6 +552,40 @@ extern "C" void LLVMRustSetLLVMOptions(int Argc, char **Argv)
   cl::ParseCommandLineOptions(Argc, Argv);
 }
 
+// Whether LLVM has an option with this name (without the leading dashes), handing an unknown
+// option to ParseCommandLineOptions exits the process.
+extern "C" bool LLVMRustIsKnownLLVMOption(const char *Name)
+{
+  return cl::getRegisteredOptions().count(Name) != 0;
+}
+
+// Adds `llvm.loop.unroll.disable` to the metadata of every loop in the module, this is
+// respected by the unroller of libnvvm too, unlike disabling it in the pass manager builder.
+extern "C" void LLVMRustDisableLoopUnrolling(LLVMModuleRef M)
+{
+  for (Function &F : *unwrap(M)) {
+    if (F.isDeclaration())
+      continue;
+
+    DominatorTree DT(F);
+    LoopInfo LI(DT);
+    for (Loop *L : LI.getLoopsInPreorder()) {
+      LLVMContext &C = F.getContext();
+      // the first operand of a loop id is the node itself, keep the other hints already on the loop.
+      SmallVector<Metadata *, 4> MDs;
+      MDs.push_back(nullptr);
+      if (MDNode *Old = L->getLoopID()) {
+        for (unsigned I = 1; I < Old->getNumOperands(); ++I)
+          MDs.push_back(Old->getOperand(I));
+      }
+      MDs.push_back(MDNode::get(C, MDString::get(C, "llvm.loop.unroll.disable")));
+      MDNode *ID = MDNode::getDistinct(C, MDs);
+      ID->replaceOperandWith(0, ID);
+      L->setLoopID(ID);
+    }
+  }
+}
+
 enum class LLVMRustFileType
 {
   Other,
@@ -824,6 +860,15 @@ extern "C" void LLVMRustAddAlwaysInlinePass(LLVMPassManagerBuilderRef PMBR,
 #endif
 }
 
+extern "C" LLVMPassRef LLVMRustCreateAlwaysInlinePass(bool AddLifetimes)
+{
+#if LLVM_VERSION_GE(4, 0)
+  return wrap(llvm::createAlwaysInlinerLegacyPass(AddLifetimes));
+#else
+  return wrap(createAlwaysInlinerPass(AddLifetimes));
+#endif
+}
+
 extern "C" void LLVMRustRunRestrictionPass(LLVMModuleRef M, char **Symbols,
                                            size_t Len)
 {
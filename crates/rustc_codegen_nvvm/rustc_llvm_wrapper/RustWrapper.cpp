@@ -178,6 +178,12 @@ extern "C" LLVMValueRef LLVMRustGetNamedValue(LLVMModuleRef M,
   return wrap(unwrap(M)->getNamedValue(Name));
 }
 
+extern "C" void LLVMRustEraseNamedMetadata(LLVMModuleRef M, const char *Name)
+{
+  if (NamedMDNode *Node = unwrap(M)->getNamedMetadata(Name))
+    Node->eraseFromParent();
+}
+
 extern "C" LLVMValueRef LLVMRustGetOrInsertFunction(LLVMModuleRef M,
                                                     const char *Name,
                                                     LLVMTypeRef FunctionTy)
@@ -465,6 +471,12 @@ LLVMRustBuildAtomicCmpXchg(LLVMBuilderRef B, LLVMValueRef Target,
   return wrap(ACXI);
 }
 
+// LLVM 7 has no C API to read the operation of an atomicrmw, our AtomicRmwBinOp has the same values.
+extern "C" LLVMAtomicRMWBinOp LLVMRustGetAtomicRMWBinOp(LLVMValueRef V)
+{
+  return static_cast<LLVMAtomicRMWBinOp>(unwrap<AtomicRMWInst>(V)->getOperation());
+}
+
 enum class LLVMRustSynchronizationScope
 {
   Other,
@@ -547,6 +559,13 @@ extern "C" bool LLVMRustInlineAsmVerify(LLVMTypeRef Ty, char *Constraints,
                            StringRef(Constraints, ConstraintsLen));
 }
 
+extern "C" const char *LLVMRustGetInlineAsmString(LLVMValueRef V, size_t *Len)
+{
+  StringRef Str = unwrap<InlineAsm>(V)->getAsmString();
+  *Len = Str.size();
+  return Str.data();
+}
+
 extern "C" void LLVMRustAppendModuleInlineAsm(LLVMModuleRef M, const char *Asm)
 {
   unwrap(M)->appendModuleInlineAsm(StringRef(Asm));
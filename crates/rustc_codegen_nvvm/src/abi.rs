@@ -11,7 +11,7 @@ use rustc_codegen_ssa::{traits::*, MemFlags};
 use rustc_middle::bug;
 use rustc_middle::ty::layout::LayoutOf;
 pub use rustc_middle::ty::layout::{FAT_PTR_ADDR, FAT_PTR_EXTRA};
-use rustc_middle::ty::{Ty, TyCtxt, TyKind};
+use rustc_middle::ty::{ParamEnv, Ty, TyCtxt, TyKind};
 pub use rustc_target::abi::call::*;
 use rustc_target::abi::call::{CastTarget, Reg, RegKind};
 use rustc_target::abi::{self, HasDataLayout, Int};
@@ -22,7 +22,7 @@ pub(crate) fn readjust_fn_abi<'tcx>(
     tcx: TyCtxt<'tcx>,
     fn_abi: &'tcx FnAbi<'tcx, Ty<'tcx>>,
 ) -> &'tcx FnAbi<'tcx, Ty<'tcx>> {
-    // dont override anything in the rust abi for now
+    // dont override anything in the rust abi for now, rustc already returns scalar pairs as `PassMode::Pair` in it.
     if fn_abi.conv == Conv::Rust {
         return fn_abi;
     }
@@ -33,13 +33,24 @@ pub(crate) fn readjust_fn_abi<'tcx>(
             pad: arg.pad,
         };
 
-        // ignore zsts
+        // ignore zsts, this must not be overridden by the adjustments below, otherwise zst returns (such as
+        // `[T; 0]`) would be passed directly and the signature would have a return type (or a param) with no size.
         if arg.layout.is_zst() {
             arg.mode = PassMode::Ignore;
+            return arg;
         }
 
-        if let TyKind::Ref(_, ty, _) = arg.layout.ty.kind() {
-            if matches!(ty.kind(), TyKind::Slice(_)) {
+        // `#[repr(transparent)]` wrappers must be passed exactly like the type they wrap, so that
+        // wrapping a param does not change the PTX signature.
+        let ty = peel_transparent(tcx, arg.layout.ty);
+
+        // slices are passed as their pointer and length. This is also the case for slices of zsts such as
+        // `&[()]`, only the elements are zero sized, the length is not and is needed to iterate them. `Pair` is
+        // only valid for scalar pair layouts, `llvm_type` and `store_fn_arg` expect exactly two scalars.
+        if let TyKind::Ref(_, ty, _) = ty.kind() {
+            if matches!(ty.kind(), TyKind::Slice(_))
+                && matches!(arg.layout.abi, abi::Abi::ScalarPair(..))
+            {
                 let mut ptr_attrs = ArgAttributes::new();
                 if let PassMode::Indirect { attrs, .. } = arg.mode {
                     ptr_attrs.regular = attrs.regular;
@@ -48,22 +59,51 @@ pub(crate) fn readjust_fn_abi<'tcx>(
             }
         }
 
-        if arg.layout.ty.is_array() && !matches!(arg.mode, PassMode::Direct { .. }) {
+        if ty.is_array() && !matches!(arg.mode, PassMode::Direct { .. }) {
             arg.mode = PassMode::Direct(ArgAttributes::new());
         }
 
-        // pass all aggregates directly as values, ptx wants them to be passed all by value, but rustc's
-        // ptx-kernel abi seems to be wrong, and it's unstable.
-        if matches!(arg.layout.abi, abi::Abi::Aggregate { .. })
-            && matches!(arg.mode, PassMode::Indirect { .. })
-        {
+        // pass all aggregates directly as values, which is what CUDA C does: a struct param is a
+        // `.param .align A .b8 name[S]` holding the bytes of the struct, so a `#[repr(C)]` struct has the same
+        // layout in the param buffer as the CUDA C struct. rustc's own nvptx abi passes aggregates larger
+        // than 8 bytes indirectly, which would make the param a pointer the launcher never passes.
+        // The LLVM struct type has the size of the Rust type, the alignment is fixed up for kernels with the
+        // `align` annotation (see `by_value_alignments` in `mono_item`).
+        // This includes structs made of two scalars such as `struct { x: f32, y: f32 }`, CUDA C passes
+        // those by value as a `.param` struct like any other struct, so `extern "C"` functions
+        // implemented in CUDA C or handwritten PTX must receive them by value too, not as a pointer.
+        // rustc passes the small ones as a pair of params instead, only fat pointers such as slices stay pairs.
+        let by_value = match arg.mode {
+            PassMode::Indirect { .. } => matches!(
+                arg.layout.abi,
+                abi::Abi::Aggregate { .. } | abi::Abi::ScalarPair(..)
+            ),
+            PassMode::Pair(..) => {
+                matches!(arg.layout.abi, abi::Abi::ScalarPair(..))
+                    && !ty.is_any_ptr()
+                    && !ty.is_box()
+            }
+            _ => false,
+        };
+        if by_value {
             arg.mode = PassMode::Direct(ArgAttributes::new());
         }
         arg
     };
+
+    // return pairs of scalars such as `(f32, f32)` as two values instead of through an sret pointer or as a
+    // struct which has to be stored and reloaded to get the fields out. In PTX both are the same by-value
+    // `.param` struct return, so functions implemented in CUDA C returning such a struct are still called correctly.
+    let mut ret = readjust_arg_abi(&fn_abi.ret);
+    if matches!(ret.layout.abi, abi::Abi::ScalarPair(..))
+        && matches!(ret.mode, PassMode::Direct(_) | PassMode::Indirect { .. })
+    {
+        ret.mode = PassMode::Pair(ArgAttributes::new(), ArgAttributes::new());
+    }
+
     tcx.arena.alloc(FnAbi {
         args: fn_abi.args.iter().map(readjust_arg_abi).collect(),
-        ret: readjust_arg_abi(&fn_abi.ret),
+        ret,
         c_variadic: fn_abi.c_variadic,
         fixed_count: fn_abi.fixed_count,
         conv: fn_abi.conv,
@@ -71,6 +111,29 @@ pub(crate) fn readjust_fn_abi<'tcx>(
     })
 }
 
+/// The type of the non-zero-sized field of `ty` if it is a `#[repr(transparent)]` struct, recursively.
+fn peel_transparent<'tcx>(tcx: TyCtxt<'tcx>, mut ty: Ty<'tcx>) -> Ty<'tcx> {
+    while let TyKind::Adt(adt, substs) = ty.kind() {
+        if !adt.is_struct() || !adt.repr.transparent() {
+            break;
+        }
+        let inner = adt
+            .non_enum_variant()
+            .fields
+            .iter()
+            .map(|field| field.ty(tcx, substs))
+            .find(|&field| {
+                tcx.layout_of(ParamEnv::reveal_all().and(field))
+                    .map_or(true, |layout| !layout.is_zst())
+            });
+        match inner {
+            Some(inner) => ty = inner,
+            None => break,
+        }
+    }
+    ty
+}
+
 macro_rules! for_each_kind {
     ($flags: ident, $f: ident, $($kind: ident),+) => ({
         $(if $flags.contains(ArgAttribute::$kind) { $f(llvm::Attribute::$kind) })+
@@ -297,6 +360,8 @@ impl<'ll, 'tcx> FnAbiLlvmExt<'ll, 'tcx> for FnAbi<'tcx, Ty<'tcx>> {
             PassMode::Cast(cast) => cast.llvm_type(cx),
             PassMode::Indirect { .. } => {
                 idx += 1;
+                // the sret pointer is always generic, callers may want the value written into any address
+                // space (a shared static, a `GlobalPtr`, etc.), those are cast to generic pointers in `check_call`.
                 llargument_tys.push(cx.type_ptr_to(self.ret.memory_ty(cx)));
                 cx.type_void()
             }
@@ -478,7 +543,11 @@ impl<'ll, 'tcx> FnAbiLlvmExt<'ll, 'tcx> for FnAbi<'tcx, Ty<'tcx>> {
                 on_stack,
             } => {
                 assert!(!on_stack);
-                apply(bx.cx, attrs);
+                let i = apply(bx.cx, attrs);
+                // the call must agree with the callee on which param is the sret pointer, otherwise
+                // LLVM may treat the store through it as unrelated to the caller's memory.
+                llvm::Attribute::StructRet
+                    .apply_callsite(llvm::AttributePlace::Argument(i), callsite);
             }
             _ => {}
         }
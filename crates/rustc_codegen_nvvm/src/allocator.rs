@@ -94,32 +94,41 @@ pub(crate) unsafe fn codegen(
     // -> ! DIFlagNoReturn
     llvm::Attribute::NoReturn.apply_llfn(llvm::AttributePlace::Function, llfn);
 
-    let kind = if has_alloc_error_handler {
-        AllocatorKind::Global
-    } else {
-        AllocatorKind::Default
-    };
-    let callee = kind.fn_name(sym::oom);
-    let callee = llvm::LLVMRustGetOrInsertFunction(llmod, callee.as_ptr().cast(), callee.len(), ty);
-
-    used.push(callee);
-
-    // -> ! DIFlagNoReturn
-    llvm::Attribute::NoReturn.apply_llfn(llvm::AttributePlace::Function, callee);
-    llvm::LLVMRustSetVisibility(callee, llvm::Visibility::Hidden);
-
     let llbb = llvm::LLVMAppendBasicBlockInContext(llcx, llfn, "entry\0".as_ptr().cast());
 
     let llbuilder = llvm::LLVMCreateBuilderInContext(llcx);
     llvm::LLVMPositionBuilderAtEnd(llbuilder, llbb);
-    let args = args
-        .iter()
-        .enumerate()
-        .map(|(i, _)| llvm::LLVMGetParam(llfn, i as c_uint))
-        .collect::<Vec<_>>();
-    let ret = llvm::LLVMRustBuildCall(llbuilder, callee, args.as_ptr(), args.len() as c_uint, None);
-    llvm::LLVMSetTailCall(ret, True);
-    llvm::LLVMBuildRetVoid(llbuilder);
+    if has_alloc_error_handler {
+        let callee = AllocatorKind::Global.fn_name(sym::oom);
+        let callee =
+            llvm::LLVMRustGetOrInsertFunction(llmod, callee.as_ptr().cast(), callee.len(), ty);
+
+        used.push(callee);
+
+        // -> ! DIFlagNoReturn
+        llvm::Attribute::NoReturn.apply_llfn(llvm::AttributePlace::Function, callee);
+        llvm::LLVMRustSetVisibility(callee, llvm::Visibility::Hidden);
+
+        let args = args
+            .iter()
+            .enumerate()
+            .map(|(i, _)| llvm::LLVMGetParam(llfn, i as c_uint))
+            .collect::<Vec<_>>();
+        let ret =
+            llvm::LLVMRustBuildCall(llbuilder, callee, args.as_ptr(), args.len() as c_uint, None);
+        llvm::LLVMSetTailCall(ret, True);
+        llvm::LLVMBuildRetVoid(llbuilder);
+    } else {
+        // the default handler of std (`__rdl_oom`) formats a message and goes through the panic machinery,
+        // which is not something we can rely on on the device. Just trap so failed allocations always
+        // terminate the kernel.
+        let trap_ty = llvm::LLVMFunctionType(void, [].as_ptr(), 0, False);
+        let trap = "llvm.trap";
+        let trap =
+            llvm::LLVMRustGetOrInsertFunction(llmod, trap.as_ptr().cast(), trap.len(), trap_ty);
+        llvm::LLVMRustBuildCall(llbuilder, trap, [].as_ptr(), 0, None);
+        llvm::LLVMBuildUnreachable(llbuilder);
+    }
     llvm::LLVMDisposeBuilder(llbuilder);
 
     let ptr_ty = llvm::LLVMPointerType(llvm::LLVMInt8TypeInContext(llcx), 0);
@@ -5,6 +5,7 @@ use crate::{
     ty::LayoutLlvmExt,
 };
 use rustc_ast::{InlineAsmOptions, InlineAsmTemplatePiece, LlvmAsmDialect};
+use rustc_codegen_ssa::common::TypeKind;
 use rustc_codegen_ssa::{
     mir::{operand::OperandValue, place::PlaceRef},
     traits::{
@@ -16,6 +17,7 @@ use rustc_hash::FxHashMap;
 use rustc_hir::LlvmInlineAsmInner;
 use rustc_middle::span_bug;
 use rustc_span::{Pos, Span};
+use rustc_target::abi::AddressSpace;
 use rustc_target::asm::{InlineAsmRegClass, InlineAsmRegOrRegClass, NvptxInlineAsmRegClass};
 
 use crate::{builder::Builder, context::CodegenCx};
@@ -171,11 +173,15 @@ impl<'a, 'll, 'tcx> AsmBuilderMethods<'tcx> for Builder<'a, 'll, 'tcx> {
         }
 
         // Collect input operands
+        let memory_spaces = memory_operand_spaces(template, operands);
         let mut inputs = vec![];
         for (idx, op) in operands.iter().enumerate() {
             match *op {
                 InlineAsmOperandRef::In { reg, value } => {
-                    let llval = value.immediate();
+                    let mut llval = value.immediate();
+                    if let Some(&space) = memory_spaces.get(&idx) {
+                        llval = self.memory_operand(llval, space);
+                    }
                     inputs.push(llval);
                     op_idx.insert(idx, constraints.len());
                     constraints.push(reg_to_llvm(reg));
@@ -280,6 +286,11 @@ impl<'a, 'll, 'tcx> AsmBuilderMethods<'tcx> for Builder<'a, 'll, 'tcx> {
                 llvm::Attribute::ReadOnly.apply_callsite(llvm::AttributePlace::Function, result);
             }
         }
+        // assembly which never returns, for example `trap;` or `exit;`, is marked as such. codegen_ssa
+        // emits an `unreachable` after the call.
+        if options.contains(InlineAsmOptions::NORETURN) {
+            llvm::Attribute::NoReturn.apply_callsite(llvm::AttributePlace::Function, result);
+        }
 
         // Write results to outputs
         for (idx, op) in operands.iter().enumerate() {
@@ -302,6 +313,107 @@ impl<'a, 'll, 'tcx> AsmBuilderMethods<'tcx> for Builder<'a, 'll, 'tcx> {
     }
 }
 
+impl<'a, 'll, 'tcx> Builder<'a, 'll, 'tcx> {
+    /// Casts a generic pointer used as the address of a memory operand to the address space of the
+    /// instruction, `cvta.to.shared` for `st.shared [{}], {}`, etc. Other values are left alone.
+    fn memory_operand(&mut self, val: &'ll Value, space: AddressSpace) -> &'ll Value {
+        let ty = self.val_ty(val);
+        if self.type_kind(ty) != TypeKind::Pointer
+            || unsafe { llvm::LLVMGetPointerAddressSpace(ty) } != 0
+        {
+            return val;
+        }
+        let dest_ty = self.type_ptr_to_ext(self.element_type(ty), space);
+        self.addrspace_cast(val, dest_ty).unwrap_or(val)
+    }
+}
+
+/// The state spaces of PTX instructions and the address spaces their memory operands are in.
+/// `.shared::cta` and `.shared::cluster` are both `.shared`, an address in the shared memory of the
+/// thread block is also valid in the shared memory of the cluster.
+const STATE_SPACES: &[(&str, AddressSpace)] = &[
+    ("global", AddressSpace(1)),
+    ("shared", AddressSpace(3)),
+    ("const", AddressSpace(4)),
+    ("local", AddressSpace(5)),
+];
+
+/// Finds the input operands which are only used as the address of memory operands (`[{}]`) of
+/// instructions with a state space, such as `st.shared.u32 [{}], {};`, and the address space they
+/// must be in.
+///
+/// Rust pointers are always generic, but an instruction like `st.shared` needs an address in the
+/// shared state space, which is not the same as the generic address of the shared memory. So a
+/// pointer used like that is cast to the address space of the instruction before being given to the
+/// assembly, which is the same as doing `cvta.to.shared` on it first. If an instruction has multiple
+/// state spaces, such as `cp.async.ca.shared.global [dst], [src], 16;`, the memory operands use them
+/// in order. Memory operands after the last state space, such as the mbarrier of `cp.async.bulk`, operands
+/// which are also used in other ways, and operands used with different state spaces are left generic.
+fn memory_operand_spaces(
+    template: &[InlineAsmTemplatePiece],
+    operands: &[InlineAsmOperandRef<'_, Builder<'_, '_, '_>>],
+) -> FxHashMap<usize, AddressSpace> {
+    // `None` if the operand is used in a way which is not a memory operand of a known state space.
+    let mut spaces = FxHashMap::<usize, Option<AddressSpace>>::default();
+    // the text of the current statement up to the placeholder.
+    let mut statement = String::new();
+    for piece in template {
+        match *piece {
+            InlineAsmTemplatePiece::String(ref s) => {
+                for c in s.chars() {
+                    if matches!(c, ';' | '\n' | '{' | '}') {
+                        statement.clear();
+                    } else {
+                        statement.push(c);
+                    }
+                }
+            }
+            InlineAsmTemplatePiece::Placeholder { operand_idx, .. } => {
+                if !matches!(operands[operand_idx], InlineAsmOperandRef::In { .. }) {
+                    continue;
+                }
+                let space = if statement.trim_end().ends_with('[') {
+                    memory_operand_space(&statement)
+                } else {
+                    None
+                };
+                let entry = spaces.entry(operand_idx).or_insert(space);
+                if *entry != space {
+                    *entry = None;
+                }
+                // the placeholder is part of the statement for the operands after it.
+                statement.push('_');
+            }
+        }
+    }
+    spaces
+        .into_iter()
+        .filter_map(|(idx, space)| Some((idx, space?)))
+        .collect()
+}
+
+/// The address space of the last memory operand of `statement`, which ends at the `[` of it. `None` if the
+/// instruction has fewer state spaces than memory operands up to that one.
+fn memory_operand_space(statement: &str) -> Option<AddressSpace> {
+    let mut words = statement.split_whitespace();
+    let mut instruction = words.next()?;
+    if instruction.starts_with('@') {
+        instruction = words.next()?;
+    }
+    let spaces = instruction
+        .split('.')
+        .filter_map(|part| {
+            let part = part.split("::").next().unwrap_or(part);
+            STATE_SPACES
+                .iter()
+                .find(|(name, _)| *name == part)
+                .map(|(_, space)| *space)
+        })
+        .collect::<Vec<_>>();
+    let operand = statement.matches('[').count() - 1;
+    spaces.get(operand).copied()
+}
+
 impl<'ll, 'tcx> AsmMethods for CodegenCx<'ll, 'tcx> {
     fn codegen_global_asm(
         &self,
@@ -1,9 +1,10 @@
 use crate::llvm::{self, AttributePlace::*, Value};
-use rustc_ast::Attribute;
+use rustc_ast::{Attribute, LitKind, NestedMetaItem};
 use rustc_attr::{InlineAttr, OptimizeAttr};
 use rustc_middle::{middle::codegen_fn_attrs::CodegenFnAttrFlags, ty};
 use rustc_session::{config::OptLevel, Session};
 use rustc_span::Symbol;
+use std::convert::TryFrom;
 
 use crate::context::CodegenCx;
 
@@ -13,7 +14,14 @@ fn inline(val: &'_ Value, inline: InlineAttr) {
     match inline {
         Hint => llvm::Attribute::InlineHint.apply_llfn(Function, val),
         Always => llvm::Attribute::AlwaysInline.apply_llfn(Function, val),
-        Never => llvm::Attribute::NoInline.apply_llfn(Function, val),
+        Never => {
+            // `noinline` together with `alwaysinline` is rejected by the verifier, and a stale hint would make
+            // the inliner reconsider a function the user explicitly wants to keep callable (for example an intrinsic
+            // wrapper they want to step into or break on).
+            llvm::Attribute::AlwaysInline.unapply_llfn(Function, val);
+            llvm::Attribute::InlineHint.unapply_llfn(Function, val);
+            llvm::Attribute::NoInline.apply_llfn(Function, val);
+        }
         None => {}
     }
 }
@@ -68,7 +76,15 @@ pub(crate) fn from_fn_attrs<'ll, 'tcx>(
         InlineAttr::Never
     } else if codegen_fn_attrs.inline == InlineAttr::None && instance.def.requires_inline(cx.tcx) {
         InlineAttr::Hint
+    } else if codegen_fn_attrs.inline == InlineAttr::None
+        && codegen_fn_attrs.flags.contains(CodegenFnAttrFlags::COLD)
+    {
+        // the cold attribute only lowers the inline threshold, which still lets small cold functions
+        // (like most error paths) be inlined into kernels, bloating them and increasing register pressure.
+        InlineAttr::Never
     } else {
+        // an explicit `#[inline(never)]` always ends up here, even on functions which only wrap an intrinsic,
+        // so debug builds with optimizations keep them as real calls with their own line info.
         codegen_fn_attrs.inline
     };
     inline(llfn, inline_attr);
@@ -93,12 +109,45 @@ pub(crate) fn from_fn_attrs<'ll, 'tcx>(
 pub struct Symbols {
     pub nvvm_internal: Symbol,
     pub kernel: Symbol,
+    pub addrspace: Symbol,
+    pub launch_bounds: Symbol,
+    pub restrict: Symbol,
+    pub readonly: Symbol,
+    pub cluster_dims: Symbol,
+    pub occupancy: Symbol,
+    pub weak: Symbol,
+    pub used: Symbol,
+    pub compiler: Symbol,
+    pub shared_array: Symbol,
 }
 
 // inspired by rust-gpu's attribute handling
 #[derive(Default, Clone, PartialEq)]
 pub(crate) struct NvvmAttributes {
     pub kernel: bool,
+    /// The address space of a pointer wrapper struct, set by `#[address_space(N)]`.
+    pub addrspace: Option<u32>,
+    /// The maximum threads per block and optionally the minimum blocks per multiprocessor
+    /// of a kernel, set by `#[kernel(launch_bounds(N, M))]`.
+    pub launch_bounds: Option<(u32, Option<u32>)>,
+    /// The indices of the kernel params which are `noalias`, set by `#[restrict]` on a kernel param.
+    pub restrict: Vec<u32>,
+    /// The indices of the kernel params which are `readonly`, set by `#[readonly]` on a kernel param.
+    pub readonly: Vec<u32>,
+    /// The dimensions of the thread block clusters of a kernel, set by `#[kernel(cluster_dims(x, y, z))]`.
+    pub cluster_dims: Option<(u32, u32, u32)>,
+    /// The percentage of the maximum threads per multiprocessor launch bounds should be derived for,
+    /// set by `#[kernel(occupancy(percent))]`.
+    pub occupancy: Option<u32>,
+    /// Whether a kernel is emitted with weak linkage so another definition can override it when linking,
+    /// set by `#[kernel(weak)]`.
+    pub weak: bool,
+    /// Whether a `#[used]` static goes in `llvm.compiler.used` instead of `llvm.used`, set by
+    /// `#[used_by(compiler)]`.
+    pub compiler_used: bool,
+    /// Whether a function is `cuda_std::shared::shared_array`, which is defined to return a new shared global,
+    /// set by `#[nvvm_internal(shared_array)]`.
+    pub shared_array: bool,
 }
 
 impl NvvmAttributes {
@@ -111,6 +160,81 @@ impl NvvmAttributes {
                 if let Some(arg) = args.first() {
                     if arg.has_name(cx.symbols.kernel) {
                         nvvm_attrs.kernel = true;
+                    } else if arg.has_name(cx.symbols.addrspace) {
+                        let addrspace = arg
+                            .meta_item_list()
+                            .and_then(|list| list.first())
+                            .and_then(|lit| lit.literal())
+                            .and_then(|lit| match lit.kind {
+                                LitKind::Int(n, _) => u32::try_from(n).ok(),
+                                _ => None,
+                            });
+                        match addrspace {
+                            Some(addrspace) => nvvm_attrs.addrspace = Some(addrspace),
+                            None => cx.tcx.sess.span_fatal(
+                                arg.span(),
+                                "expected `addrspace(N)` with an integer N",
+                            ),
+                        }
+                    } else if arg.has_name(cx.symbols.launch_bounds) {
+                        let bounds = int_list(arg);
+                        match bounds.as_deref() {
+                            Some(&[max_threads]) => {
+                                nvvm_attrs.launch_bounds = Some((max_threads, None))
+                            }
+                            Some(&[max_threads, min_blocks]) => {
+                                nvvm_attrs.launch_bounds = Some((max_threads, Some(min_blocks)))
+                            }
+                            _ => cx.tcx.sess.span_fatal(
+                                arg.span(),
+                                "expected `launch_bounds(N)` or `launch_bounds(N, M)` with integers N and M",
+                            ),
+                        }
+                    } else if arg.has_name(cx.symbols.restrict) || arg.has_name(cx.symbols.readonly)
+                    {
+                        let indices = int_list(arg);
+                        match indices {
+                            Some(indices) if arg.has_name(cx.symbols.restrict) => {
+                                nvvm_attrs.restrict.extend(indices)
+                            }
+                            Some(indices) => nvvm_attrs.readonly.extend(indices),
+                            None => cx
+                                .tcx
+                                .sess
+                                .span_fatal(arg.span(), "expected a list of integer param indices"),
+                        }
+                    } else if arg.has_name(cx.symbols.cluster_dims) {
+                        match int_list(arg).as_deref() {
+                            Some(&[x, y, z]) => nvvm_attrs.cluster_dims = Some((x, y, z)),
+                            _ => cx.tcx.sess.span_fatal(
+                                arg.span(),
+                                "expected `cluster_dims(X, Y, Z)` with integers X, Y and Z",
+                            ),
+                        }
+                    } else if arg.has_name(cx.symbols.occupancy) {
+                        match int_list(arg).as_deref() {
+                            Some(&[percent]) if (1..=100).contains(&percent) => {
+                                nvvm_attrs.occupancy = Some(percent)
+                            }
+                            _ => cx.tcx.sess.span_fatal(
+                                arg.span(),
+                                "expected `occupancy(N)` with an integer percentage N from 1 to 100",
+                            ),
+                        }
+                    } else if arg.has_name(cx.symbols.weak) {
+                        nvvm_attrs.weak = true;
+                    } else if arg.has_name(cx.symbols.shared_array) {
+                        nvvm_attrs.shared_array = true;
+                    } else if arg.has_name(cx.symbols.used) {
+                        match arg.meta_item_list().unwrap_or_default() {
+                            [item] if item.has_name(cx.symbols.compiler) => {
+                                nvvm_attrs.compiler_used = true
+                            }
+                            _ => cx
+                                .tcx
+                                .sess
+                                .span_fatal(arg.span(), "expected `used(compiler)`"),
+                        }
                     }
                 }
             }
@@ -119,3 +243,15 @@ impl NvvmAttributes {
         nvvm_attrs
     }
 }
+
+/// Parses the integers of an attribute such as `launch_bounds(N, M)`, `None` if any of them is not an integer.
+fn int_list(arg: &NestedMetaItem) -> Option<Vec<u32>> {
+    arg.meta_item_list()
+        .unwrap_or_default()
+        .iter()
+        .map(|item| match item.literal().map(|lit| &lit.kind) {
+            Some(LitKind::Int(n, _)) => u32::try_from(*n).ok(),
+            _ => None,
+        })
+        .collect()
+}
@@ -1,5 +1,21 @@
+use crate::float_class::lower_float_classification;
+use crate::float_select::lower_float_sign_selects;
+use crate::int_min_max::lower_int_min_max;
+use crate::intrinsic_arch::report_intrinsics;
+use crate::launch_bounds::{check_launch_bounds, derive_launch_bounds};
 use crate::llvm::{self};
-use crate::{builder::Builder, context::CodegenCx, lto::ThinBuffer, LlvmMod, NvvmCodegenBackend};
+use crate::local_memory::check_local_memory;
+use crate::recursion::check_kernel_recursion;
+use crate::reduction::lower_unused_atomics;
+use crate::reflect::resolve_nvvm_reflect;
+use crate::shared_array::{check_shared_memory, define_shared_array, is_shared_array};
+use crate::unsupported_std::check_unsupported_std;
+use crate::{
+    builder::Builder,
+    context::{CodegenArgs, CodegenCx},
+    lto::ThinBuffer,
+    LlvmMod, NvvmCodegenBackend,
+};
 use libc::{c_char, size_t};
 use rustc_codegen_ssa::back::write::{TargetMachineFactoryConfig, TargetMachineFactoryFn};
 use rustc_codegen_ssa::traits::{DebugInfoMethods, MiscMethods};
@@ -51,15 +67,18 @@ pub fn to_llvm_opt_settings(
     }
 }
 
+/// PTX has no relocations, the driver resolves every symbol when it JIT compiles a module, so every PTX file
+/// is already loadable from anywhere with `cuModuleLoadData`. The relocation model therefore does not change
+/// what we emit, but users may still ask for PIE with `-Crelocation-model=pie`. LLVM has no separate mode for
+/// it, so it is treated as PIC like rustc's LLVM backend does.
 fn to_llvm_relocation_model(relocation_model: RelocModel) -> llvm::RelocMode {
     match relocation_model {
         RelocModel::Static => llvm::RelocMode::Static,
-        RelocModel::Pic => llvm::RelocMode::PIC,
         RelocModel::DynamicNoPic => llvm::RelocMode::DynamicNoPic,
         RelocModel::Ropi => llvm::RelocMode::ROPI,
         RelocModel::Rwpi => llvm::RelocMode::RWPI,
         RelocModel::RopiRwpi => llvm::RelocMode::ROPI_RWPI,
-        RelocModel::Pie => panic!(),
+        RelocModel::Pic | RelocModel::Pie => llvm::RelocMode::PIC,
     }
 }
 
@@ -223,12 +242,28 @@ pub(crate) unsafe fn codegen(
         diag_handler.err(&msg);
     }
 
+    // the "object" file is already bitcode, but it is consumed by linking into the final PTX file, so
+    // `--emit=llvm-bc` gets its own copy of the same bitcode. The temp path uses a different extension
+    // than the object file so the two never collide.
+    let bytecode = if config.emit_bc {
+        let bc_out = cgcx
+            .output_filenames
+            .temp_path(OutputType::Bitcode, module_name);
+        if let Err(e) = std::fs::write(&bc_out, data) {
+            let msg = format!("failed to write bytecode to {}: {}", bc_out.display(), e);
+            diag_handler.err(&msg);
+        }
+        Some(bc_out)
+    } else {
+        None
+    };
+
     Ok(CompiledModule {
         name: mod_name,
         kind: module.kind,
         object: Some(out),
         dwarf_object: None,
-        bytecode: None,
+        bytecode,
     })
 }
 
@@ -250,6 +285,7 @@ pub fn compile_codegen_unit(tcx: TyCtxt<'_>, cgu_name: Symbol) -> (ModuleCodegen
 
     fn module_codegen(tcx: TyCtxt<'_>, cgu_name: Symbol) -> ModuleCodegen<LlvmMod> {
         let cgu = tcx.codegen_unit(cgu_name);
+        check_unsupported_std(tcx, cgu);
 
         // Instantiate monomorphizations without filling out definitions yet...
         let llvm_module = LlvmMod::new(&cgu_name.as_str());
@@ -264,6 +300,12 @@ pub fn compile_codegen_unit(tcx: TyCtxt<'_>, cgu_name: Symbol) -> (ModuleCodegen
 
             // ... and now that we have everything pre-defined, fill out those definitions.
             for &(mono_item, _) in &mono_items {
+                if let MonoItem::Fn(inst) = mono_item {
+                    if is_shared_array(&cx, inst) {
+                        define_shared_array(&cx, inst);
+                        continue;
+                    }
+                }
                 mono_item.define::<Builder<'_, '_, '_>>(&cx);
                 if let MonoItem::Fn(inst) = mono_item {
                     let name = tcx.symbol_name(inst).name;
@@ -354,8 +396,29 @@ pub(crate) unsafe fn optimize(
     };
 
     let tm = (cgcx.tm_factory)(tm_factory_config).expect("failed to create target machine");
-
-    if config.opt_level.is_some() {
+    // The args were already validated when the module was codegenned.
+    let args = CodegenArgs::parse(&cgcx.opts.cg.llvm_args, cgcx.opts.cg.target_cpu.as_deref())
+        .unwrap_or_default();
+
+    let opt_level = config
+        .opt_level
+        .map_or(llvm::CodeGenOptLevel::None, |x| to_llvm_opt_settings(x).0);
+    // at -O0 the pass manager builder only adds the always inliner (see `with_llvm_pmb`), but setting up the
+    // function pass manager and the analysis passes is still a noticeable part of dev builds, so run the
+    // inliner on its own if nothing else was asked for. `-Cllvm-args=-debug-pass=Structure` shows the passes.
+    let only_always_inline = opt_level == llvm::CodeGenOptLevel::None
+        && config.opt_size.is_none()
+        && config.inline_threshold.is_none()
+        && !config.no_prepopulate_passes
+        && !config.verify_llvm_ir
+        && config.passes.is_empty();
+
+    if config.opt_level.is_some() && only_always_inline {
+        let mpm = llvm::LLVMCreatePassManager();
+        llvm::LLVMRustAddPass(mpm, llvm::LLVMRustCreateAlwaysInlinePass(false));
+        llvm::LLVMRunPassManager(mpm, llmod);
+        llvm::LLVMDisposePassManager(mpm);
+    } else if config.opt_level.is_some() {
         let fpm = llvm::LLVMCreateFunctionPassManagerForModule(llmod);
         let mpm = llvm::LLVMCreatePassManager();
 
@@ -381,19 +444,22 @@ pub(crate) unsafe fn optimize(
         if !config.no_prepopulate_passes {
             llvm::LLVMRustAddAnalysisPasses(tm, fpm, llmod);
             llvm::LLVMRustAddAnalysisPasses(tm, mpm, llmod);
-            let opt_level = config
-                .opt_level
-                .map_or(llvm::CodeGenOptLevel::None, |x| to_llvm_opt_settings(x).0);
-            with_llvm_pmb(llmod, config, opt_level, &mut |b| {
+            with_llvm_pmb(llmod, config, opt_level, args.no_unroll, &mut |b| {
                 llvm::LLVMPassManagerBuilderPopulateFunctionPassManager(b, fpm);
                 llvm::LLVMPassManagerBuilderPopulateModulePassManager(b, mpm);
             })
         }
 
-        for pass in &config.passes {
-            if !addpass(pass) {
-                diag_handler.warn(&format!("unknown pass `{}`, ignoring", pass));
+        match add_named_passes(config.verify_llvm_ir, &config.passes, addpass) {
+            Ok(unknown) => {
+                for pass in unknown {
+                    diag_handler.warn(&format!("unknown pass `{}`, ignoring", pass));
+                }
             }
+            Err(pass) => diag_handler.err(&format!(
+                "required LLVM pass `{}` could not be found, LLVM may have been built incorrectly",
+                pass
+            )),
         }
 
         diag_handler.abort_if_errors();
@@ -407,13 +473,65 @@ pub(crate) unsafe fn optimize(
         llvm::LLVMDisposePassManager(mpm);
     }
 
+    // reflect calls usually only get a constant argument after inlining, so resolve them after our own
+    // optimizations; libnvvm optimizes the module again and removes the branches which are never taken.
+    resolve_nvvm_reflect(llmod, &args);
+    // integer min/max only turn into selects after optimization.
+    lower_int_min_max(llmod);
+    lower_float_sign_selects(llmod);
+    lower_float_classification(llmod);
+    // whether the result of an atomic is used is only known after optimization.
+    lower_unused_atomics(llmod);
+    // libnvvm unrolls loops on its own, so tell it not to through loop metadata.
+    if args.no_unroll {
+        llvm::LLVMRustDisableLoopUnrolling(llmod);
+    }
+
+    // checked after optimization so that tail recursion LLVM turned into loops is not reported.
+    check_kernel_recursion(diag_handler, llmod, args.deny_recursion);
+    // the register estimate is only meaningful once helpers are inlined into kernels.
+    derive_launch_bounds(diag_handler, llmod, args.arch());
+    check_launch_bounds(diag_handler, llmod, args.arch());
+    // allocas which are left after SROA and inlining are what ends up in local memory.
+    if let Some(limit) = args.local_memory_warning {
+        check_local_memory(diag_handler, llmod, limit);
+    }
+    // shared globals which are never used are removed by optimization.
+    check_shared_memory(diag_handler, llmod);
+    // after optimization so that intrinsics which are never called are not reported.
+    if args.intrinsic_report {
+        report_intrinsics(diag_handler, llmod, args.arch());
+    }
+    diag_handler.abort_if_errors();
+
     Ok(())
 }
 
+/// Adds the passes which are looked up by name with `addpass`, which returns whether LLVM knows the pass. These
+/// are the verifier if `verify` is set, which the backend needs, and the passes given with `-Cpasses`.
+///
+/// Returns the passes given with `-Cpasses` which LLVM does not know, these come from the user so they are only
+/// warned about. Not finding the verifier means that the LLVM we were built against is broken, so that is an error.
+fn add_named_passes<'a>(
+    verify: bool,
+    passes: &'a [String],
+    mut addpass: impl FnMut(&str) -> bool,
+) -> Result<Vec<&'a str>, &'static str> {
+    if verify && !addpass("verify") {
+        return Err("verify");
+    }
+    Ok(passes
+        .iter()
+        .map(|pass| pass.as_str())
+        .filter(|pass| !addpass(pass))
+        .collect())
+}
+
 unsafe fn with_llvm_pmb(
     llmod: &llvm::Module,
     config: &ModuleConfig,
     opt_level: llvm::CodeGenOptLevel,
+    disable_unroll: bool,
     f: &mut impl FnMut(&llvm::PassManagerBuilder),
 ) {
     use std::ptr;
@@ -437,7 +555,8 @@ unsafe fn with_llvm_pmb(
 
     llvm::LLVMPassManagerBuilderSetSizeLevel(builder, opt_size as u32);
 
-    if opt_size != llvm::CodeGenOptSizeNone {
+    // unrolling increases register pressure, which is often worse than the loop overhead on the GPU.
+    if opt_size != llvm::CodeGenOptSizeNone || disable_unroll {
         llvm::LLVMPassManagerBuilderSetDisableUnrollLoops(builder, 1);
     }
 
@@ -446,7 +565,8 @@ unsafe fn with_llvm_pmb(
     // Here we match what clang does (kinda). For O0 we only inline
     // always-inline functions (but don't add lifetime intrinsics), at O1 we
     // inline with lifetime intrinsics, and O2+ we add an inliner with a
-    // thresholds copied from clang.
+    // thresholds copied from clang. None of these inliners touch `noinline` functions, which is what
+    // `#[inline(never)]` lowers to, no matter the threshold or whether debuginfo is enabled.
     match (opt_level, opt_size, inline_threshold) {
         (.., Some(t)) => {
             llvm::LLVMPassManagerBuilderUseInlinerWithThreshold(builder, t as u32);
@@ -461,6 +581,9 @@ unsafe fn with_llvm_pmb(
             llvm::LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 25);
         }
         (llvm::CodeGenOptLevel::None, ..) => {
+            // libnvvm does not inline anything with -opt=0, so this is the only chance for `#[inline(always)]`
+            // functions to be inlined. Functions from other CGUs can only be inlined if rustc made a local copy
+            // of them, which is why cuda_builder passes `-Zinline-in-all-cgus=yes`.
             llvm::LLVMRustAddAlwaysInlinePass(builder, false);
         }
         (llvm::CodeGenOptLevel::Less, ..) => {
@@ -477,3 +600,38 @@ unsafe fn with_llvm_pmb(
     f(builder);
     llvm::LLVMPassManagerBuilderDispose(builder);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_user_passes_are_returned() {
+        let passes = vec!["instcombine".to_string(), "not-a-pass".to_string()];
+        let mut added = vec![];
+        let unknown = add_named_passes(true, &passes, |pass| {
+            added.push(pass.to_string());
+            pass != "not-a-pass"
+        });
+        assert_eq!(unknown, Ok(vec!["not-a-pass"]));
+        assert_eq!(added, ["verify", "instcombine", "not-a-pass"]);
+    }
+
+    #[test]
+    fn missing_required_passes_are_an_error() {
+        let passes = vec!["instcombine".to_string()];
+        let result = add_named_passes(true, &passes, |pass| pass != "verify");
+        assert_eq!(result, Err("verify"));
+    }
+
+    #[test]
+    fn verify_is_only_added_when_asked_for() {
+        let mut added = vec![];
+        let result = add_named_passes(false, &[], |pass| {
+            added.push(pass.to_string());
+            false
+        });
+        assert_eq!(result, Ok(vec![]));
+        assert!(added.is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 #![allow(clippy::unnecessary_mut_passed)]
 
+use crate::asm::inline_asm_call;
 use crate::context::CodegenCx;
 use crate::int_replace::{get_transformed_type, transmute_llval};
 use crate::llvm::{self, BasicBlock, LLVMRustGetValueType, Type, Value};
 use crate::ty::LayoutLlvmExt;
 use libc::{c_char, c_uint};
+use rustc_ast::LlvmAsmDialect;
 use rustc_codegen_ssa::common::{AtomicOrdering, IntPredicate, RealPredicate, TypeKind};
 use rustc_codegen_ssa::mir::operand::{OperandRef, OperandValue};
 use rustc_codegen_ssa::mir::place::PlaceRef;
@@ -14,8 +16,8 @@ use rustc_hir::def_id::DefId;
 use rustc_middle::ty::layout::{
     FnAbiError, FnAbiOfHelpers, FnAbiRequest, LayoutError, LayoutOfHelpers, TyAndLayout,
 };
-use rustc_middle::ty::{self, Ty, TyCtxt};
-use rustc_span::Span;
+use rustc_middle::ty::{self, Instance, Ty, TyCtxt};
+use rustc_span::{Span, Symbol};
 use rustc_target::abi::call::FnAbi;
 use rustc_target::abi::{self, Align, Size, WrappingRange};
 use rustc_target::spec::{HasTargetSpec, Target};
@@ -243,14 +245,15 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
         else_llbb: &'ll BasicBlock,
     ) {
         trace!("Cond br `{:?}`", cond);
-        unsafe {
+        let br = unsafe {
             llvm::LLVMBuildCondBr(
                 &mut self.llbuilder.lock().unwrap(),
                 cond,
                 then_llbb,
                 else_llbb,
-            );
-        }
+            )
+        };
+        self.branch_weights_from_expect(br, cond);
     }
 
     fn switch(
@@ -319,6 +322,7 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
         or(a, b) => LLVMBuildOr,
         xor(a, b) => LLVMBuildXor,
         neg(x) => LLVMBuildNeg,
+        // LLVM 7 builds this as `fsub -0.0, x`, which becomes a single `neg` instruction.
         fneg(x) => LLVMBuildFNeg,
         not(x) => LLVMBuildNot,
         unchecked_sadd(x, y) => LLVMBuildNSWAdd,
@@ -869,6 +873,9 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
 
     fn bitcast(&mut self, val: &'ll Value, dest_ty: &'ll Type) -> &'ll Value {
         trace!("Bitcast `{:?}` to ty `{:?}`", val, dest_ty);
+        if let Some(cast) = self.addrspace_cast(val, dest_ty) {
+            return cast;
+        }
         unsafe {
             llvm::LLVMBuildBitCast(&mut self.llbuilder.lock().unwrap(), val, dest_ty, unnamed())
         }
@@ -883,6 +890,9 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
 
     fn pointercast(&mut self, val: &'ll Value, dest_ty: &'ll Type) -> &'ll Value {
         trace!("Pointercast `{:?}` to ty `{:?}`", val, dest_ty);
+        if let Some(cast) = self.addrspace_cast(val, dest_ty) {
+            return cast;
+        }
         unsafe {
             llvm::LLVMBuildPointerCast(&mut self.llbuilder.lock().unwrap(), val, dest_ty, unnamed())
         }
@@ -1103,34 +1113,115 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
     // Atomic Operations
     fn atomic_cmpxchg(
         &mut self,
-        _dst: &'ll Value,
-        _cmp: &'ll Value,
-        _src: &'ll Value,
-        _order: rustc_codegen_ssa::common::AtomicOrdering,
-        _failure_order: rustc_codegen_ssa::common::AtomicOrdering,
-        _weak: bool,
+        dst: &'ll Value,
+        cmp: &'ll Value,
+        src: &'ll Value,
+        order: rustc_codegen_ssa::common::AtomicOrdering,
+        failure_order: rustc_codegen_ssa::common::AtomicOrdering,
+        weak: bool,
     ) -> &'ll Value {
         // allowed but only for some things and with restrictions
         // https://docs.nvidia.com/cuda/nvvm-ir-spec/index.html#cmpxchg-instruction
-        self.fatal("atomic cmpxchg is not supported")
+        self.check_atomic_operand(src, "cmpxchg");
+        self.fence_before_atomic(order);
+        let res = unsafe {
+            llvm::LLVMRustBuildAtomicCmpXchg(
+                &mut self.llbuilder.lock().unwrap(),
+                dst,
+                cmp,
+                src,
+                llvm::AtomicOrdering::Monotonic,
+                llvm::AtomicOrdering::Monotonic,
+                weak as llvm::Bool,
+            )
+        };
+        // the failure ordering may only be an acquire, which needs the same fence as an acquire on success.
+        if needs_fence_after(order) || needs_fence_after(failure_order) {
+            self.atomic_membar();
+        }
+        res
     }
+
     fn atomic_rmw(
         &mut self,
-        _op: rustc_codegen_ssa::common::AtomicRmwBinOp,
-        _dst: &'ll Value,
-        _src: &'ll Value,
-        _order: rustc_codegen_ssa::common::AtomicOrdering,
+        op: rustc_codegen_ssa::common::AtomicRmwBinOp,
+        dst: &'ll Value,
+        src: &'ll Value,
+        order: rustc_codegen_ssa::common::AtomicOrdering,
     ) -> &'ll Value {
-        // see cmpxchg comment
-        self.fatal("atomic rmw is not supported")
+        // https://docs.nvidia.com/cuda/nvvm-ir-spec/index.html#atomicrmw-instruction
+        if let rustc_codegen_ssa::common::AtomicRmwBinOp::AtomicNand = op {
+            self.fatal("atomic nand is not supported");
+        }
+
+        // atomicrmw only works on integers in LLVM 7, so operations on pointers (such as `AtomicPtr::swap`)
+        // are done on the pointer's address then turned back into a pointer.
+        let src_ty = self.val_ty(src);
+        let ptr_ty = (self.type_kind(src_ty) == TypeKind::Pointer).then(|| src_ty);
+        let (dst, src) = if ptr_ty.is_some() {
+            let isize_ty = self.cx.type_isize();
+            let address_space = unsafe { llvm::LLVMGetPointerAddressSpace(self.val_ty(dst)) };
+            (
+                self.pointercast(
+                    dst,
+                    self.type_ptr_to_ext(isize_ty, abi::AddressSpace(address_space)),
+                ),
+                self.ptrtoint(src, isize_ty),
+            )
+        } else {
+            (dst, src)
+        };
+
+        self.check_atomic_operand(src, "rmw");
+
+        // the pointer's address space is kept, so libnvvm emits `atom.shared` for shared memory pointers
+        // and `atom.global` for global memory pointers.
+        self.fence_before_atomic(order);
+        let res = unsafe {
+            llvm::LLVMBuildAtomicRMW(
+                &mut self.llbuilder.lock().unwrap(),
+                llvm::AtomicRmwBinOp::from_generic(op),
+                dst,
+                src,
+                llvm::AtomicOrdering::Monotonic,
+                llvm::False,
+            )
+        };
+        if needs_fence_after(order) {
+            self.atomic_membar();
+        }
+
+        match ptr_ty {
+            Some(ty) => self.inttoptr(res, ty),
+            None => res,
+        }
     }
 
     fn atomic_fence(
         &mut self,
         _order: rustc_codegen_ssa::common::AtomicOrdering,
-        _scope: rustc_codegen_ssa::common::SynchronizationScope,
+        scope: rustc_codegen_ssa::common::SynchronizationScope,
     ) {
-        self.fatal("atomic fence is not supported, use cuda_std intrinsics instead")
+        match scope {
+            // `compiler_fence`, this only needs to stop LLVM from reordering memory operations across it,
+            // so emit an empty asm block which clobbers memory, which does not generate any PTX instruction.
+            rustc_codegen_ssa::common::SynchronizationScope::SingleThread => {
+                let void = self.type_void();
+                inline_asm_call(
+                    self,
+                    "",
+                    "~{memory}",
+                    &[],
+                    void,
+                    true,
+                    false,
+                    LlvmAsmDialect::Att,
+                    &[],
+                );
+            }
+            // `fence`, this is the equivalent of `__threadfence()`, which is a `membar.gl`.
+            rustc_codegen_ssa::common::SynchronizationScope::CrossThread => self.atomic_membar(),
+        }
     }
 
     fn set_invariant_load(&mut self, load: &'ll Value) {
@@ -1170,6 +1261,20 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
     ) -> &'ll Value {
         trace!("Calling fn {:?} with args {:?}", llfn, args);
         self.cx.last_call_llfn.set(None);
+        if self.is_overflow_panic(llfn, args) {
+            // overflow checks (`-C overflow-checks`) just trap on the device, going through the panic
+            // machinery for every checked operation bloats kernels a lot for something that is a bug anyways.
+            let trap = self.get_intrinsic("llvm.trap");
+            return self.call(self.type_void(), trap, &[], None);
+        }
+        if self.cx.codegen_args.bounds_check_trap
+            && self.calls_lang_item(llfn, self.tcx.lang_items().panic_bounds_check_fn())
+        {
+            // the args are the index, the length and the location.
+            self.print_bounds_check(args[0], args[1]);
+            let trap = self.get_intrinsic("llvm.trap");
+            return self.call(self.type_void(), trap, &[], None);
+        }
         let args = self.check_call("call", llfn, args);
 
         let mut ret = unsafe {
@@ -1210,6 +1315,16 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
     }
 }
 
+/// Whether an atomic with `order` needs a fence after it, see [`Builder::fence_before_atomic`].
+fn needs_fence_after(order: AtomicOrdering) -> bool {
+    matches!(
+        order,
+        AtomicOrdering::Acquire
+            | AtomicOrdering::AcquireRelease
+            | AtomicOrdering::SequentiallyConsistent
+    )
+}
+
 impl<'a, 'll, 'tcx> StaticBuilderMethods for Builder<'a, 'll, 'tcx> {
     fn get_static(&mut self, def_id: DefId) -> &'ll Value {
         // Forward to the `get_static` method of `CodegenCx`
@@ -1243,6 +1358,192 @@ impl<'a, 'll, 'tcx> Builder<'a, 'll, 'tcx> {
         }
     }
 
+    /// Atomics are always emitted as monotonic, which are plain `atom` instructions (or `red` if the result
+    /// is unused), and the orderings stronger than `Relaxed` are done with a `membar.gl` before and/or after
+    /// them. This way relaxed atomics never have fences, and stronger orderings do not depend on how libnvvm
+    /// lowers them.
+    fn fence_before_atomic(&mut self, order: AtomicOrdering) {
+        if matches!(
+            order,
+            AtomicOrdering::Release
+                | AtomicOrdering::AcquireRelease
+                | AtomicOrdering::SequentiallyConsistent
+        ) {
+            self.atomic_membar();
+        }
+    }
+
+    fn atomic_membar(&mut self) {
+        let void = self.type_void();
+        let membar = self.get_intrinsic("llvm.nvvm.membar.gl");
+        self.call(void, membar, &[], None);
+    }
+
+    /// NVVM only supports atomics on 32 and 64 bit values, error out on anything else instead of letting
+    /// libnvvm reject the module.
+    fn check_atomic_operand(&self, val: &'ll Value, op: &str) {
+        let ty = self.val_ty(val);
+        let supported = match self.type_kind(ty) {
+            TypeKind::Integer => matches!(self.int_width(ty), 32 | 64),
+            TypeKind::Pointer => true,
+            _ => false,
+        };
+        if !supported {
+            self.fatal(&format!(
+                "atomic {} is only supported on 32 and 64 bit values, found `{:?}`",
+                op, ty
+            ));
+        }
+    }
+
+    /// Whether `llfn` is the function of the lang item `item`, possibly behind a cast.
+    fn calls_lang_item(&self, llfn: &'ll Value, item: Option<DefId>) -> bool {
+        let item = match item {
+            Some(item) => item,
+            None => return false,
+        };
+        let symbol = self.tcx.symbol_name(Instance::mono(self.tcx, item)).name;
+
+        let mut llfn = llfn;
+        unsafe {
+            while llvm::LLVMIsAConstantExpr(llfn).is_some() {
+                llfn = llvm::LLVMGetOperand(llfn, 0);
+            }
+        }
+        llvm::get_value_name(llfn) == symbol.as_bytes()
+    }
+
+    /// Prints the index and length of a failed bounds check with `vprintf`, for `--bounds-check-trap`.
+    fn print_bounds_check(&mut self, index: &'ll Value, len: &'ll Value) {
+        let i64 = self.type_i64();
+        let align = Align::from_bytes(8).unwrap();
+        // vprintf takes a pointer to its arguments laid out like a struct.
+        let args_ty = self.type_struct(&[i64, i64], false);
+        let args = self.alloca(args_ty, align);
+        for (idx, &val) in [len, index].iter().enumerate() {
+            let val = self.intcast(val, i64, false);
+            let ptr = self.struct_gep(args_ty, args, idx as u64);
+            self.store(val, ptr, align);
+        }
+
+        let format = self.cx.const_cstr(
+            Symbol::intern("index out of bounds: the len is %llu but the index is %llu\n"),
+            true,
+        );
+        let format = self.pointercast(format, self.type_i8p());
+        let args = self.pointercast(args, self.cx.voidp());
+        let vprintf = self.get_intrinsic("vprintf");
+        self.call(self.type_i32(), vprintf, &[format, args], None);
+    }
+
+    /// Whether this is a call to the panic lang item with one of the messages rustc uses for
+    /// overflow checks, such as `attempt to add with overflow`.
+    fn is_overflow_panic(&self, llfn: &'ll Value, args: &[&'ll Value]) -> bool {
+        if !self.calls_lang_item(llfn, self.tcx.lang_items().panic_fn()) {
+            return false;
+        }
+
+        unsafe {
+            let mut msg = match args.first() {
+                Some(msg) => *msg,
+                None => return false,
+            };
+            while llvm::LLVMIsAConstantExpr(msg).is_some() {
+                msg = llvm::LLVMGetOperand(msg, 0);
+            }
+            if llvm::LLVMIsAGlobalVariable(msg).is_none() {
+                return false;
+            }
+            let mut init = match llvm::LLVMGetInitializer(msg) {
+                Some(init) => init,
+                None => return false,
+            };
+            // rustc allocations are emitted as packed structs of their chunks.
+            if llvm::LLVMIsAConstantStruct(init).is_some() {
+                init = llvm::LLVMGetOperand(init, 0);
+            }
+            if llvm::LLVMIsAConstantDataSequential(init).is_none()
+                || llvm::LLVMIsConstantString(init) == llvm::False
+            {
+                return false;
+            }
+            let mut len = 0;
+            let ptr = llvm::LLVMGetAsString(init, &mut len);
+            let msg = std::slice::from_raw_parts(ptr.cast::<u8>(), len);
+            msg.starts_with(b"attempt to ") && msg.ends_with(b"with overflow")
+        }
+    }
+
+    /// Attaches branch weights to a conditional branch on the result of `likely`/`unlikely` (`llvm.expect.i1`).
+    /// LLVM only turns `llvm.expect` into branch weights when optimizing, and libnvvm does not always do it,
+    /// so we do it directly to make sure the block layout favors the expected path.
+    fn branch_weights_from_expect(&mut self, br: &'ll Value, cond: &'ll Value) {
+        unsafe {
+            if llvm::LLVMIsACallInst(cond).is_none()
+                || llvm::get_value_name(llvm::LLVMGetCalledValue(cond)) != b"llvm.expect.i1"
+            {
+                return;
+            }
+            let expected = match llvm::LLVMIsAConstantInt(llvm::LLVMGetOperand(cond, 1)) {
+                Some(expected) => llvm::LLVMConstIntGetZExtValue(expected) != 0,
+                None => return,
+            };
+            // the same weights LLVM uses when lowering `llvm.expect`.
+            let (taken, not_taken) = if expected { (2000, 1) } else { (1, 2000) };
+            let v = [
+                llvm::LLVMMDStringInContext(self.cx.llcx, "branch_weights".as_ptr().cast(), 14),
+                self.cx.const_i32(taken),
+                self.cx.const_i32(not_taken),
+            ];
+            llvm::LLVMSetMetadata(
+                br,
+                llvm::MetadataType::MD_prof as c_uint,
+                llvm::LLVMMDNodeInContext(self.cx.llcx, v.as_ptr(), v.len() as c_uint),
+            );
+        }
+    }
+
+    /// Pointers in different address spaces (from `#[address_space(N)]` pointer wrappers) cannot be bitcast
+    /// into each other, they need an `addrspacecast`. This is only valid between the generic address space
+    /// and a specific one, a pointer in shared memory can never point to global memory, so anything else errors.
+    pub(crate) fn addrspace_cast(
+        &mut self,
+        val: &'ll Value,
+        dest_ty: &'ll Type,
+    ) -> Option<&'ll Value> {
+        let src_ty = self.val_ty(val);
+        if self.type_kind(src_ty) != TypeKind::Pointer
+            || self.type_kind(dest_ty) != TypeKind::Pointer
+        {
+            return None;
+        }
+        let (src, dest) = unsafe {
+            (
+                llvm::LLVMGetPointerAddressSpace(src_ty),
+                llvm::LLVMGetPointerAddressSpace(dest_ty),
+            )
+        };
+        if src == dest {
+            return None;
+        }
+        if src != 0 && dest != 0 {
+            self.fatal(&format!(
+                "cannot cast a pointer in address space {} to address space {}, pointers can only be cast \
+                 to and from generic pointers",
+                src, dest
+            ));
+        }
+        trace!("Addrspacecast `{:?}` to ty `{:?}`", val, dest_ty);
+        Some(unsafe {
+            llvm::LLVMBuildAddrSpaceCast(
+                &mut self.llbuilder.lock().unwrap(),
+                val,
+                dest_ty,
+                unnamed(),
+            )
+        })
+    }
+
     fn check_store(&mut self, val: &'ll Value, ptr: &'ll Value) -> &'ll Value {
         let dest_ptr_ty = self.cx.val_ty(ptr);
         let stored_ty = self.cx.val_ty(val);
@@ -123,6 +123,8 @@ impl<'ll, 'tcx> ConstMethods<'tcx> for CodegenCx<'ll, 'tcx> {
                 self.const_undef(self.type_ix(0))
             }
             Scalar::Int(int) => {
+                // floats are bitcast from an integer of their bits and never go through a host float, so -0.0,
+                // subnormals and NaN payloads are kept exactly. Float data in allocations is emitted as bytes.
                 let data = int.assert_bits(layout.value.size(self));
                 let llval = self.const_uint_big(self.type_ix(bitsize), data);
                 if layout.value == Pointer {
@@ -135,10 +137,17 @@ impl<'ll, 'tcx> ConstMethods<'tcx> for CodegenCx<'ll, 'tcx> {
                 let (alloc_id, offset) = ptr.into_parts();
                 let (base_addr, base_addr_space) = match self.tcx.global_alloc(alloc_id) {
                     GlobalAlloc::Memory(alloc) => {
-                        let init = const_alloc_to_llvm(self, alloc);
+                        // mutable allocations come from `static mut`s or statics with interior mutability and
+                        // can be written to, so each of them needs its own global even if another allocation has
+                        // the same contents. The only ones that are deduplicated are zero-sized ones, which can
+                        // never be written to and therefore share a global with identical constants.
                         let value = match alloc.mutability {
-                            Mutability::Mut => self.static_addr_of_mut(init, alloc.align, None),
-                            _ => self.static_addr_of(init, alloc.align, None),
+                            Mutability::Mut if alloc.len() != 0 => self.static_addr_of_mut(
+                                const_alloc_to_llvm(self, alloc),
+                                alloc.align,
+                                None,
+                            ),
+                            _ => self.const_alloc_addr(alloc),
                         };
                         if !self.sess().fewer_names() {
                             llvm::set_value_name(value, format!("{:?}", alloc_id).as_bytes());
@@ -152,7 +161,7 @@ impl<'ll, 'tcx> ConstMethods<'tcx> for CodegenCx<'ll, 'tcx> {
                     GlobalAlloc::Static(def_id) => {
                         assert!(self.tcx.is_static(def_id));
                         assert!(!self.tcx.is_thread_local_static(def_id));
-                        (self.get_static(def_id), AddressSpace::DATA)
+                        (self.get_static(def_id), self.static_address_space(def_id))
                     }
                 };
                 let llval = unsafe {
@@ -165,7 +174,8 @@ impl<'ll, 'tcx> ConstMethods<'tcx> for CodegenCx<'ll, 'tcx> {
                 if layout.value != Pointer {
                     unsafe { llvm::LLVMConstPtrToInt(llval, llty) }
                 } else {
-                    self.const_bitcast(llval, llty)
+                    // statics in constant memory are cast to generic pointers here.
+                    unsafe { llvm::LLVMConstPointerCast(llval, llty) }
                 }
             }
         };
@@ -187,8 +197,7 @@ impl<'ll, 'tcx> ConstMethods<'tcx> for CodegenCx<'ll, 'tcx> {
             let llval = self.const_usize(alloc.align.bytes());
             unsafe { llvm::LLVMConstIntToPtr(llval, llty) }
         } else {
-            let init = const_alloc_to_llvm(self, alloc);
-            let base_addr = self.static_addr_of(init, alloc.align, None);
+            let base_addr = self.const_alloc_addr(alloc);
 
             let llval = unsafe {
                 llvm::LLVMConstInBoundsGEP(
@@ -1,3 +1,4 @@
+use std::ffi::CString;
 use std::ops::Range;
 
 use crate::debug_info;
@@ -16,15 +17,29 @@ use rustc_middle::{
     mir::interpret::{InitChunk, Scalar as InterpScalar},
     mir::mono::{Linkage, MonoItem},
     span_bug,
-    ty::{self, layout::LayoutOf, Instance, Ty},
+    ty::{
+        self,
+        layout::{LayoutOf, TyAndLayout},
+        Instance, Ty,
+    },
 };
 use rustc_span::Symbol;
 use rustc_target::abi::{
-    AddressSpace, Align, HasDataLayout, Primitive, Scalar, Size, WrappingRange,
+    Abi, AddressSpace, Align, FieldsShape, HasDataLayout, Primitive, Scalar, Size, WrappingRange,
 };
 use tracing::trace;
 
-use crate::{context::CodegenCx, ty::LayoutLlvmExt};
+use crate::{attributes::NvvmAttributes, context::CodegenCx, ty::LayoutLlvmExt};
+
+/// NVVM's constant address space, reads from it go through the constant cache of each multiprocessor.
+pub(crate) const CONSTANT_ADDRESS_SPACE: AddressSpace = AddressSpace(4);
+
+/// Read-only constants of at least this many bytes are placed in constant memory, see [`CodegenCx::const_alloc_addr`].
+const CONST_MEMORY_MIN_SIZE: u64 = 256;
+
+/// The most bytes of constants placed in constant memory by one codegen unit. Kernels can only use
+/// 64KiB of constant memory in total, so this leaves room for other codegen units and for statics.
+const CONST_MEMORY_BUDGET: u64 = 16 * 1024;
 
 pub(crate) fn bytes_in_context<'ll>(llcx: &'ll llvm::Context, bytes: &[u8]) -> &'ll Value {
     unsafe {
@@ -104,12 +119,14 @@ pub(crate) fn const_alloc_to_llvm<'ll>(cx: &CodegenCx<'ll, '_>, alloc: &Allocati
 
         // Generating partially-uninit consts inhibits optimizations, so it is disabled by default.
         // See https://github.com/rust-lang/rust/issues/84565.
-        let allow_partially_uninit = match cx
+        // PTX initializers have no uninit bytes, they are always emitted as zeros, so splitting consts
+        // does not make the PTX any smaller either. Our own option takes precedence over rustc's.
+        let threshold = cx.codegen_args.partially_uninit_const_threshold.or(cx
             .sess()
             .opts
             .debugging_opts
-            .partially_uninit_const_threshold
-        {
+            .partially_uninit_const_threshold);
+        let allow_partially_uninit = match threshold {
             Some(max) => range.len() <= max,
             None => false,
         };
@@ -138,6 +155,14 @@ pub(crate) fn const_alloc_to_llvm<'ll>(cx: &CodegenCx<'ll, '_>, alloc: &Allocati
         let offset = offset.bytes();
         assert_eq!(offset as usize as u64, offset);
         let offset = offset as usize;
+        // relocations are sorted and never overlap, so everything between the end of the last
+        // relocation and this one is plain data, such as the discriminant of a `#[repr(C)]` enum.
+        // That data must be emitted as bytes and never be read as a pointer offset.
+        assert!(
+            offset >= next_offset && offset + pointer_size <= alloc.len(),
+            "const_alloc_to_llvm: relocation at offset {} overlaps data or exceeds the allocation",
+            offset
+        );
         if offset > next_offset {
             // This `inspect` is okay since we have checked that it is not within a relocation, it
             // is within the bounds of the allocation, and it doesn't affect interpreter execution
@@ -183,6 +208,73 @@ pub(crate) fn const_alloc_to_llvm<'ll>(cx: &CodegenCx<'ll, '_>, alloc: &Allocati
     cx.const_struct(&llvals, true)
 }
 
+/// Emits the initializer of a static of a `#[repr(simd)]` type (or an array of them) as LLVM vector
+/// constants instead of a byte array, so that the global keeps its vector type and loads from it
+/// stay vector loads. Returns `None` if the static is not of such a type, or if its bytes cannot be
+/// represented as plain vectors because they contain relocations or uninit bytes.
+fn const_simd_alloc_to_llvm<'ll, 'tcx>(
+    cx: &CodegenCx<'ll, 'tcx>,
+    layout: TyAndLayout<'tcx>,
+    alloc: &Allocation,
+) -> Option<&'ll Value> {
+    if !alloc.relocations().is_empty() || alloc.len() as u64 != layout.size.bytes() {
+        return None;
+    }
+    let mut chunks = alloc
+        .init_mask()
+        .range_as_init_chunks(Size::ZERO, Size::from_bytes(alloc.len()));
+    if !matches!((chunks.next(), chunks.next()), (Some(InitChunk::Init(_)), None)) {
+        return None;
+    }
+
+    let vector_at = |layout: TyAndLayout<'tcx>, offset: usize| -> Option<&'ll Value> {
+        let (element, count) = match layout.abi {
+            Abi::Vector { element, count } => (element, count),
+            _ => return None,
+        };
+        let is_float = match element.value {
+            Primitive::Int(..) => false,
+            Primitive::F32 | Primitive::F64 => true,
+            Primitive::Pointer => return None,
+        };
+        let llty = layout.scalar_llvm_type_at(cx, &element, Size::ZERO);
+        let size = element.value.size(cx).bytes() as usize;
+        let elems = (0..count as usize)
+            .map(|i| {
+                let start = offset + i * size;
+                // This `inspect` is okay since we checked that the allocation is fully init and has
+                // no relocations, and the vector is within the bounds of the allocation.
+                let bits = read_target_uint(
+                    cx.data_layout().endian,
+                    alloc.inspect_with_uninit_and_ptr_outside_interpreter(start..(start + size)),
+                )
+                .ok()?;
+                let int = cx.const_uint_big(cx.type_ix(size as u64 * 8), bits);
+                Some(if is_float {
+                    cx.const_bitcast(int, llty)
+                } else {
+                    int
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(unsafe { llvm::LLVMConstVector(elems.as_ptr(), elems.len() as c_uint) })
+    };
+
+    match layout.fields {
+        FieldsShape::Array { stride, count } if !matches!(layout.abi, Abi::Vector { .. }) => {
+            let elem = layout.field(cx, 0);
+            if stride != elem.size {
+                return None;
+            }
+            let vals = (0..count as usize)
+                .map(|i| vector_at(elem, i * stride.bytes() as usize))
+                .collect::<Option<Vec<_>>>()?;
+            Some(cx.const_array(elem.llvm_type(cx), &vals))
+        }
+        _ => vector_at(layout, 0),
+    }
+}
+
 pub(crate) fn codegen_static_initializer<'ll, 'tcx>(
     cx: &CodegenCx<'ll, 'tcx>,
     def_id: DefId,
@@ -191,6 +283,13 @@ pub(crate) fn codegen_static_initializer<'ll, 'tcx>(
     Ok((const_alloc_to_llvm(cx, alloc), alloc))
 }
 
+/// Maps a rustc linkage to an LLVM linkage. libnvvm derives the PTX linking directives from this:
+/// definitions with external linkage become `.visible`, declarations with external linkage become
+/// `.extern`, and weak/linkonce linkages become `.weak`. Internal and private symbols get no directive
+/// and cannot be resolved by `nvlink` when doing separate compilation. Kernels marked with `#[kernel(weak)]`
+/// are given [`Linkage::WeakAny`] regardless of what rustc chose, which makes them `.weak .entry`s.
+/// Common statics become `.common` and must be zero-initialized, all of their definitions are merged into one
+/// by the LLVM linker when the CGUs and crates are linked.
 pub(crate) fn linkage_to_llvm(linkage: Linkage) -> llvm::Linkage {
     match linkage {
         Linkage::External => llvm::Linkage::ExternalLinkage,
@@ -213,6 +312,7 @@ fn check_and_apply_linkage<'ll, 'tcx>(
     ty: Ty<'tcx>,
     sym: &str,
     span_def_id: DefId,
+    address_space: AddressSpace,
 ) -> &'ll Value {
     let llty = cx.layout_of(ty).llvm_type(cx);
     if let Some(linkage) = attrs.linkage {
@@ -263,7 +363,7 @@ fn check_and_apply_linkage<'ll, 'tcx>(
             g2
         }
     } else {
-        cx.declare_global(sym, llty, AddressSpace::DATA)
+        cx.declare_global(sym, llty, address_space)
     }
 }
 
@@ -273,6 +373,9 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
         unsafe { llvm::LLVMConstBitCast(val, ty) }
     }
 
+    /// Defines a new private global initialized to `cv`. Unlike [`StaticMethods::static_addr_of`], this
+    /// never reuses a global with the same initializer because the global may be written to. Data that
+    /// can never be written to, like zero-sized allocations, should go through `static_addr_of` instead.
     pub(crate) fn static_addr_of_mut(
         &self,
         cv: &'ll Value,
@@ -293,6 +396,62 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
         }
     }
 
+    /// The address of a global holding the read-only `alloc`, as a generic pointer.
+    ///
+    /// Lookup tables computed by `const fn`s and other big constants are put in constant memory, so that
+    /// reading them goes through the constant cache instead of the L1 cache. This is done for constants of at least
+    /// [`CONST_MEMORY_MIN_SIZE`] bytes which contain no pointers (relocations cannot be in constant memory),
+    /// until the codegen unit used up its [`CONST_MEMORY_BUDGET`]. Statics are only put in constant memory
+    /// with `#[address_space(constant)]`, so a table can be kept out of constant memory by making it a static.
+    pub(crate) fn const_alloc_addr(&self, alloc: &Allocation) -> &'ll Value {
+        let init = const_alloc_to_llvm(self, alloc);
+        let size = alloc.len() as u64;
+        if size < CONST_MEMORY_MIN_SIZE || !alloc.relocations().is_empty() {
+            return self.static_addr_of(init, alloc.align, None);
+        }
+
+        let existing = self.const_memory_globals.borrow().get(&init).copied();
+        let gv = match existing {
+            Some(gv) => unsafe {
+                let llalign = alloc.align.bytes() as u32;
+                if llalign > llvm::LLVMGetAlignment(gv) {
+                    llvm::LLVMSetAlignment(gv, llalign);
+                }
+                gv
+            },
+            None => {
+                let used = self.const_memory_used.get();
+                if used + size > CONST_MEMORY_BUDGET {
+                    return self.static_addr_of(init, alloc.align, None);
+                }
+                self.const_memory_used.set(used + size);
+
+                let name = self.generate_local_symbol_name("const");
+                let gv = self
+                    .define_global(&name[..], self.val_ty(init), CONSTANT_ADDRESS_SPACE)
+                    .unwrap_or_else(|| bug!("symbol `{}` is already defined", name));
+                unsafe {
+                    llvm::LLVMRustSetLinkage(gv, llvm::Linkage::PrivateLinkage);
+                    llvm::LLVMSetInitializer(gv, init);
+                    llvm::LLVMSetAlignment(gv, alloc.align.bytes() as c_uint);
+                    llvm::SetUnnamedAddress(gv, llvm::UnnamedAddr::Global);
+                    llvm::LLVMSetGlobalConstant(gv, True);
+                }
+                self.const_memory_globals.borrow_mut().insert(init, gv);
+                gv
+            }
+        };
+        unsafe { llvm::LLVMConstPointerCast(gv, self.type_ptr_to(self.val_ty(init))) }
+    }
+
+    /// The address space of a static, which is only not the generic one for `#[address_space(constant)]` statics.
+    pub(crate) fn static_address_space(&self, def_id: DefId) -> AddressSpace {
+        match NvvmAttributes::parse(self, self.tcx.get_attrs(def_id)).addrspace {
+            Some(space) if AddressSpace(space) == CONSTANT_ADDRESS_SPACE => CONSTANT_ADDRESS_SPACE,
+            _ => AddressSpace::DATA,
+        }
+    }
+
     pub(crate) fn get_static(&self, def_id: DefId) -> &'ll Value {
         let instance = Instance::mono(self.tcx, def_id);
         if let Some(&g) = self.instances.borrow().get(&instance) {
@@ -313,6 +472,7 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
         let ty = instance.ty(self.tcx, ty::ParamEnv::reveal_all());
         let sym = self.tcx.symbol_name(instance).name;
         let fn_attrs = self.tcx.codegen_fn_attrs(def_id);
+        let address_space = self.static_address_space(def_id);
 
         let g = if def_id.is_local() && !self.tcx.is_foreign_item(def_id) {
             let llty = self.layout_of(ty).llvm_type(self);
@@ -322,7 +482,7 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
                 }
             }
 
-            let g = self.declare_global(sym, llty, AddressSpace::DATA);
+            let g = self.declare_global(sym, llty, address_space);
 
             if !self.tcx.is_reachable_non_generic(def_id) {
                 unsafe {
@@ -332,7 +492,7 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
 
             g
         } else {
-            check_and_apply_linkage(self, fn_attrs, ty, sym, def_id)
+            check_and_apply_linkage(self, fn_attrs, ty, sym, def_id, address_space)
         };
 
         if fn_attrs.flags.contains(CodegenFnAttrFlags::THREAD_LOCAL) {
@@ -369,12 +529,17 @@ impl<'ll, 'tcx> StaticMethods for CodegenCx<'ll, 'tcx> {
         unsafe {
             let attrs = self.tcx.codegen_fn_attrs(def_id);
 
-            let (v, _) = match codegen_static_initializer(self, def_id) {
+            let (v, alloc) = match codegen_static_initializer(self, def_id) {
                 Ok(v) => v,
                 // Error has already been reported
                 Err(_) => return,
             };
 
+            let instance = Instance::mono(self.tcx, def_id);
+            let ty = instance.ty(self.tcx, ty::ParamEnv::reveal_all());
+            let layout = self.layout_of(ty);
+            let v = const_simd_alloc_to_llvm(self, layout, alloc).unwrap_or(v);
+
             let g = self.get_static(def_id);
 
             let mut val_llty = self.val_ty(v);
@@ -385,9 +550,7 @@ impl<'ll, 'tcx> StaticMethods for CodegenCx<'ll, 'tcx> {
                 v
             };
 
-            let instance = Instance::mono(self.tcx, def_id);
-            let ty = instance.ty(self.tcx, ty::ParamEnv::reveal_all());
-            let llty = self.layout_of(ty).llvm_type(self);
+            let llty = layout.llvm_type(self);
             let g = if val_llty == llty {
                 g
             } else {
@@ -411,7 +574,7 @@ impl<'ll, 'tcx> StaticMethods for CodegenCx<'ll, 'tcx> {
                     name.as_ptr().cast(),
                     name.len(),
                     val_llty,
-                    AddressSpace::DATA.0,
+                    self.static_address_space(def_id).0,
                 );
 
                 llvm::LLVMRustSetLinkage(new_g, linkage);
@@ -427,24 +590,80 @@ impl<'ll, 'tcx> StaticMethods for CodegenCx<'ll, 'tcx> {
             llvm::LLVMSetAlignment(g, self.align_of(ty).bytes() as c_uint);
             llvm::LLVMSetInitializer(g, v);
 
+            // common statics are tentative definitions which are merged with every other definition of the
+            // same symbol when linking, LLVM requires them to be zero-initialized and mutable for that.
+            let is_common = llvm::LLVMRustGetLinkage(g) == llvm::Linkage::CommonLinkage;
+            if is_common && llvm::LLVMIsNull(v) == llvm::False {
+                self.tcx.sess.span_err(
+                    self.tcx.def_span(def_id),
+                    "statics with common linkage must be zero-initialized",
+                );
+            }
+
+            // PTX has no data sections, so libnvvm does not emit the section into the PTX. But it is kept in the
+            // bitcode, which is useful for tools that process our bitcode (such as with `--emit-device-bitcode`).
+            if let Some(section) = attrs.link_section {
+                let section = section.as_str();
+                let valid = !section.is_empty()
+                    && section
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'));
+                if valid {
+                    let section = CString::new(section).unwrap();
+                    llvm::LLVMSetSection(g, section.as_ptr());
+                } else {
+                    self.tcx.sess.span_err(
+                        self.tcx.def_span(def_id),
+                        &format!(
+                            "invalid link section `{}`, section names may only contain ASCII letters, digits, `_`, `.` and `$`",
+                            section
+                        ),
+                    );
+                }
+            }
+
             debug_info::create_global_var_metadata(self, def_id, g);
 
             // As an optimization, all shared statics which do not have interior
-            // mutability are placed into read-only memory.
-            if !is_mutable && self.type_is_freeze(ty) {
-                // TODO(RDambrosio016): is this the same as putting this in
-                // the __constant__ addrspace for nvvm? should we set this addrspace explicitly?
+            // mutability are placed into read-only memory. This only lets libnvvm use `ld.global.nc`,
+            // statics are only put in constant memory with `#[address_space(constant)]`.
+            if !is_mutable && !is_common && self.type_is_freeze(ty) {
                 llvm::LLVMSetGlobalConstant(g, llvm::True);
             }
 
+            if self.static_address_space(def_id) == CONSTANT_ADDRESS_SPACE {
+                if is_mutable || !self.type_is_freeze(ty) {
+                    self.tcx.sess.span_err(
+                        self.tcx.def_span(def_id),
+                        "statics in constant memory cannot be mutable or have interior mutability",
+                    );
+                } else if !alloc.relocations().is_empty() {
+                    self.tcx.sess.span_err(
+                        self.tcx.def_span(def_id),
+                        "statics in constant memory cannot contain references or pointers",
+                    );
+                }
+            }
+
             debug_info::create_global_var_metadata(self, def_id, g);
 
             if attrs.flags.contains(CodegenFnAttrFlags::THREAD_LOCAL) {
                 self.unsupported("thread locals");
             }
 
-            if attrs.flags.contains(CodegenFnAttrFlags::USED) {
-                self.add_used_global(g);
+            // `#[no_mangle]` statics are looked up by name from the host (`cuModuleGetGlobal`,
+            // `cudaMemcpyToSymbol`), so they must survive even if no device code uses them. libnvvm only
+            // keeps the used symbols of lazily added dependency modules, so treat them like `#[used]`.
+            // `#[used_by(compiler)]` only keeps the static from being removed by our own LLVM passes, libnvvm
+            // may still drop it if nothing uses it.
+            if attrs.flags.contains(CodegenFnAttrFlags::USED)
+                || attrs.flags.contains(CodegenFnAttrFlags::NO_MANGLE)
+            {
+                if NvvmAttributes::parse(self, self.tcx.get_attrs(def_id)).compiler_used {
+                    self.add_compiler_used_global(g);
+                } else {
+                    self.add_used_global(g);
+                }
             }
         }
     }
@@ -3,7 +3,7 @@ use crate::attributes::{self, Symbols};
 use crate::debug_info::{self, compile_unit_metadata, CrateDebugContext};
 use crate::llvm::{self, BasicBlock, Type, Value};
 use crate::{target, LlvmMod};
-use nvvm::NvvmOption;
+use nvvm::{NvvmArch, NvvmOption};
 use rustc_codegen_ssa::traits::ConstMethods;
 use rustc_codegen_ssa::traits::{BackendTypes, BaseTypeMethods, CoverageInfoMethods, MiscMethods};
 use rustc_data_structures::base_n;
@@ -56,6 +56,10 @@ pub(crate) struct CodegenCx<'ll, 'tcx> {
 
     /// Cache of emitted const globals (value -> global)
     pub const_globals: RefCell<FxHashMap<&'ll Value, &'ll Value>>,
+    /// Cache of the const globals placed in constant memory (value -> global), see `const_alloc_addr`.
+    pub const_memory_globals: RefCell<FxHashMap<&'ll Value, &'ll Value>>,
+    /// The bytes of constant memory used by `const_memory_globals`.
+    pub const_memory_used: Cell<u64>,
 
     /// List of globals for static variables which need to be passed to the
     /// LLVM function ReplaceAllUsesWith (RAUW) when codegen is complete.
@@ -144,6 +148,8 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
             const_cstr_cache: Default::default(),
             remapped_integer_args: Default::default(),
             const_globals: Default::default(),
+            const_memory_globals: Default::default(),
+            const_memory_used: Cell::new(0),
             statics_to_rauw: RefCell::new(Vec::new()),
             used_statics: RefCell::new(Vec::new()),
             compiler_used_statics: RefCell::new(Vec::new()),
@@ -164,6 +170,16 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
             symbols: Symbols {
                 nvvm_internal: Symbol::intern("nvvm_internal"),
                 kernel: Symbol::intern("kernel"),
+                addrspace: Symbol::intern("addrspace"),
+                launch_bounds: Symbol::intern("launch_bounds"),
+                restrict: Symbol::intern("restrict"),
+                readonly: Symbol::intern("readonly"),
+                cluster_dims: Symbol::intern("cluster_dims"),
+                occupancy: Symbol::intern("occupancy"),
+                weak: Symbol::intern("weak"),
+                used: Symbol::intern("used"),
+                compiler: Symbol::intern("compiler"),
+                shared_array: Symbol::intern("shared_array"),
             },
             dbg_cx,
             codegen_args: CodegenArgs::from_session(tcx.sess()),
@@ -437,6 +453,8 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
             let def_id = instance.def_id();
 
             unsafe {
+                // functions which are only declared in this module (such as device functions defined in another
+                // module for separate compilation) must have external linkage so that libnvvm emits them as `.extern`.
                 llvm::LLVMRustSetLinkage(llfn, llvm::Linkage::ExternalLinkage);
 
                 let is_generic = instance.substs.non_erasable_generics().next().is_some();
@@ -505,26 +523,200 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
     }
 }
 
+#[derive(Default, Clone)]
 pub struct CodegenArgs {
     pub nvvm_options: Vec<NvvmOption>,
+    /// The NVVM IR version (major, minor) the installed libnvvm must support, set with
+    /// `--require-nvvm-ir-version=MAJOR.MINOR`. This is only checked and does not change the version of
+    /// the IR we emit.
+    pub required_nvvm_ir_version: Option<(i32, i32)>,
+    /// Whether recursion which is reachable from a kernel is an error instead of a warning, set
+    /// with `--deny-recursion`.
+    pub deny_recursion: bool,
+    /// Whether to stop before libnvvm and write the optimized and linked LLVM bitcode of the crate to
+    /// `<output>.device.bc` instead of a PTX file, set with `--emit-device-bitcode`.
+    pub emit_device_bitcode: bool,
+    /// Whether to also codegen every kernel into its own PTX file, `<output>.<kernel>.ptx`,
+    /// set with `--split-kernels`.
+    pub split_kernels: bool,
+    /// Warn about kernels whose estimated local memory (stack) usage per thread is more than this
+    /// many bytes, set with `--local-memory-warning=BYTES`.
+    pub local_memory_warning: Option<u64>,
+    /// Whether to never unroll loops, neither in our optimizations nor in libnvvm's, set with `--no-unroll`.
+    pub no_unroll: bool,
+    /// Whether to write the kernels of the crate along with the layout of their params and their launch
+    /// attributes to `<output>.kernels.json`, set with `--emit-kernel-manifest`.
+    pub emit_kernel_manifest: bool,
+    /// Whether to write the externally visible functions and globals of the crate along with their mangled
+    /// names to `<output>.symbols.json`, set with `--emit-symbol-table`.
+    pub emit_symbol_table: bool,
+    /// The size in bytes up to which consts with uninit bytes are split into chunks of init and uninit
+    /// bytes instead of treating the uninit bytes as zeros, set with `--partially-uninit-const-threshold=BYTES`.
+    /// This is the same as `-Zpartially-uninit-const-threshold`, consts are never split by default.
+    pub partially_uninit_const_threshold: Option<usize>,
+    /// Whether to list the intrinsics used which need a newer architecture than the oldest one, and error
+    /// about the ones the target architecture does not have, set with `--intrinsic-report`.
+    pub intrinsic_report: bool,
+    /// Whether to run ptxas on the final PTX file and report the registers, shared memory and spills of every
+    /// kernel, set with `--ptx-report`.
+    pub ptx_report: bool,
+    /// Architectures to also generate PTX for, from the same LLVM modules, into `<output>.<sm_XX>.ptx`, set with
+    /// `--ptx-archs=sm_70,sm_80`. The crate itself is only compiled for the target architecture, so these must
+    /// not be older than it.
+    pub ptx_archs: Vec<NvvmArch>,
+    /// Whether failed bounds checks print the index and length and trap right away instead of going through
+    /// the panic handler, set with `--bounds-check-trap`.
+    pub bounds_check_trap: bool,
+    /// Options which are neither libnvvm options nor our own, these are given to LLVM's option parser
+    /// and affect the optimizations done before libnvvm, for example `-unroll-threshold=500`.
+    pub llvm_args: Vec<String>,
 }
 
 impl CodegenArgs {
     pub fn from_session(sess: &Session) -> Self {
-        match Self::parse(&sess.opts.cg.llvm_args) {
+        match Self::parse(&sess.opts.cg.llvm_args, sess.opts.cg.target_cpu.as_deref()) {
             Ok(x) => x,
             Err(err) => sess.fatal(&format!("Failed to parse codegen args: {}", err)),
         }
     }
 
     // we may want to use rustc's own option parsing facilities to have better errors in the future.
-    pub fn parse(args: &[String]) -> Result<Self, &'static str> {
-        let nvvm_options = args
+    /// Parses the args given with `-Cllvm-args`. `target_cpu` is the value of `-Ctarget-cpu`, which sets the
+    /// architecture like `-arch` does if there is no `-arch` in the args.
+    pub fn parse(args: &[String], target_cpu: Option<&str>) -> Result<Self, &'static str> {
+        let mut cg_args = Self::default();
+
+        for arg in args {
+            let arg = arg.trim();
+            if let Some(version) = arg.strip_prefix("--require-nvvm-ir-version=") {
+                cg_args.required_nvvm_ir_version = Some(parse_ir_version(version)?);
+            } else if arg == "--deny-recursion" {
+                cg_args.deny_recursion = true;
+            } else if arg == "--emit-device-bitcode" {
+                cg_args.emit_device_bitcode = true;
+            } else if arg == "--split-kernels" {
+                cg_args.split_kernels = true;
+            } else if arg == "--no-unroll" {
+                cg_args.no_unroll = true;
+            } else if arg == "--emit-kernel-manifest" {
+                cg_args.emit_kernel_manifest = true;
+            } else if arg == "--emit-symbol-table" {
+                cg_args.emit_symbol_table = true;
+            } else if arg == "--intrinsic-report" {
+                cg_args.intrinsic_report = true;
+            } else if arg == "--ptx-report" {
+                cg_args.ptx_report = true;
+            } else if arg == "--bounds-check-trap" {
+                cg_args.bounds_check_trap = true;
+            } else if let Some(archs) = arg.strip_prefix("--ptx-archs=") {
+                for arch in archs.split(',') {
+                    cg_args.ptx_archs.push(parse_target_cpu(arch.trim())?);
+                }
+            } else if let Some(bytes) = arg.strip_prefix("--partially-uninit-const-threshold=") {
+                cg_args.partially_uninit_const_threshold =
+                    Some(bytes.parse().map_err(|_| {
+                        "--partially-uninit-const-threshold expects a number of bytes"
+                    })?);
+            } else if let Some(bytes) = arg.strip_prefix("--local-memory-warning=") {
+                cg_args.local_memory_warning = Some(
+                    bytes
+                        .parse()
+                        .map_err(|_| "--local-memory-warning expects a number of bytes")?,
+                );
+            } else if is_nvvm_option(arg) {
+                cg_args.nvvm_options.push(NvvmOption::from_str(arg)?);
+            } else {
+                cg_args.llvm_args.push(arg.to_string());
+            }
+        }
+
+        let has_arch = cg_args
+            .nvvm_options
             .iter()
-            .map(|x| NvvmOption::from_str(x))
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(Self { nvvm_options })
+            .any(|opt| matches!(opt, NvvmOption::Arch(_)));
+        if let (Some(cpu), false) = (target_cpu, has_arch) {
+            cg_args
+                .nvvm_options
+                .push(NvvmOption::Arch(parse_target_cpu(cpu)?));
+        }
+
+        // libnvvm rejects this when compiling, with an error that does not say what to do.
+        if cg_args.nvvm_options.contains(&NvvmOption::GenDebugInfo)
+            && !cg_args.nvvm_options.contains(&NvvmOption::NoOpts)
+        {
+            return Err("-g needs libnvvm optimizations to be disabled with -opt=0");
+        }
+
+        // `cuda_std::arch` and `#[cfg(target_feature)]` only see the target architecture, code for it may
+        // use instructions which older architectures do not have.
+        let arch = cg_args.arch();
+        if cg_args
+            .ptx_archs
+            .iter()
+            .any(|&extra| extra < arch || (arch.is_arch_specific() && extra != arch))
+        {
+            return Err("--ptx-archs architectures must not be older than the target architecture");
+        }
+
+        Ok(cg_args)
     }
+
+    /// The architecture which is being compiled for, this is the last `-arch` option given, or libnvvm's default
+    /// if none was given.
+    pub fn arch(&self) -> NvvmArch {
+        self.nvvm_options
+            .iter()
+            .rev()
+            .find_map(|opt| match opt {
+                NvvmOption::Arch(arch) => Some(*arch),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Whether `arg` is meant for libnvvm, invalid values of libnvvm options are still errors
+/// instead of being given to LLVM.
+fn is_nvvm_option(arg: &str) -> bool {
+    const NVVM_OPTIONS: &[&str] = &[
+        "-g",
+        "-generate-line-info",
+        "-opt",
+        "-ftz",
+        "-prec-sqrt",
+        "-prec-div",
+        "-fma",
+        "-arch",
+    ];
+    let name = arg.split('=').next().unwrap_or_default();
+    NVVM_OPTIONS.contains(&name)
+}
+
+/// Parses a `-Ctarget-cpu` such as `sm_75`, `compute_75` or the architecture-specific `sm_90a`.
+fn parse_target_cpu(cpu: &str) -> Result<NvvmArch, &'static str> {
+    let capability = cpu
+        .strip_prefix("sm_")
+        .or_else(|| cpu.strip_prefix("compute_"));
+    let arch = match capability.and_then(|capability| capability.strip_suffix('a')) {
+        Some(capability) => capability
+            .parse()
+            .ok()
+            .and_then(NvvmArch::from_capability)
+            .and_then(|arch| arch.arch_specific()),
+        None => capability
+            .and_then(|capability| capability.parse().ok())
+            .and_then(NvvmArch::from_capability),
+    };
+    arch.ok_or("unknown target-cpu, expected an architecture such as `sm_75` or `compute_75`")
+}
+
+fn parse_ir_version(version: &str) -> Result<(i32, i32), &'static str> {
+    const ERR: &str = "nvvm IR version must be in the form of MAJOR.MINOR";
+    let (major, minor) = version.split_once('.').ok_or(ERR)?;
+    Ok((
+        major.parse().map_err(|_| ERR)?,
+        minor.parse().map_err(|_| ERR)?,
+    ))
 }
 
 impl<'ll, 'tcx> BackendTypes for CodegenCx<'ll, 'tcx> {
@@ -632,3 +824,34 @@ impl<'ll, 'tcx> CoverageInfoMethods<'tcx> for CodegenCx<'ll, 'tcx> {
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ir_versions_are_major_dot_minor() {
+        assert_eq!(parse_ir_version("1.7"), Ok((1, 7)));
+        assert!(parse_ir_version("1").is_err());
+        assert!(parse_ir_version("a.b").is_err());
+        assert!(parse_ir_version("1.7.0").is_err());
+    }
+
+    #[test]
+    fn required_ir_version_is_parsed() {
+        let args =
+            CodegenArgs::parse(&["--require-nvvm-ir-version=2.0".to_string()], None).unwrap();
+        assert_eq!(args.required_nvvm_ir_version, Some((2, 0)));
+        assert!(CodegenArgs::parse(&["--require-nvvm-ir-version=2".to_string()], None).is_err());
+    }
+
+    #[test]
+    fn options_which_are_not_for_libnvvm_go_to_llvm() {
+        let args = ["-unroll-threshold=500".to_string(), "-opt=0".to_string()];
+        let args = CodegenArgs::parse(&args, None).unwrap();
+        assert_eq!(args.llvm_args, ["-unroll-threshold=500"]);
+        assert_eq!(args.nvvm_options.len(), 1);
+        // invalid values of libnvvm options are not given to LLVM.
+        assert!(CodegenArgs::parse(&["-opt=9".to_string()], None).is_err());
+    }
+}
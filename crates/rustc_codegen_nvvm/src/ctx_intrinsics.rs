@@ -48,6 +48,7 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
         ifn!(map, "llvm.trap" | "llvm.sideeffect", fn() -> void);
         ifn!(map, "llvm.assume", fn(i1) -> void);
         ifn!(map, "llvm.prefetch", fn(i8p, t_i32, t_i32, t_i32) -> void);
+        ifn!(map, "llvm.nvvm.membar.gl", fn() -> void);
 
         ifn!(map, "llvm.sadd.with.overflow.i16", fn(t_i16, t_i16) -> t_i16_i1);
         ifn!(map, "llvm.sadd.with.overflow.i32", fn(t_i32, t_i32) -> t_i32_i1);
@@ -146,6 +147,15 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
         ifn!(map, "llvm.fshr.i32", fn(t_i32, t_i32, t_i32) -> t_i32);
         ifn!(map, "llvm.fshr.i64", fn(t_i64, t_i64, t_i64) -> t_i64);
 
+        ifn!(map, "llvm.fma.f32", fn(t_f32, t_f32, t_f32) -> t_f32);
+        ifn!(map, "llvm.fma.f64", fn(t_f64, t_f64, t_f64) -> t_f64);
+
+        ifn!(map, "llvm.fabs.f32", fn(t_f32) -> t_f32);
+        ifn!(map, "llvm.fabs.f64", fn(t_f64) -> t_f64);
+
+        ifn!(map, "llvm.copysign.f32", fn(t_f32, t_f32) -> t_f32);
+        ifn!(map, "llvm.copysign.f64", fn(t_f64, t_f64) -> t_f64);
+
         ifn!(map, "llvm.ctpop.i8", fn(t_i8) -> t_i8);
         ifn!(map, "llvm.ctpop.i16", fn(t_i16) -> t_i16);
         ifn!(map, "llvm.ctpop.i32", fn(t_i32) -> t_i32);
@@ -178,7 +188,9 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
 
         if self.tcx.sess.opts.debuginfo != DebugInfo::None {
             ifn!(map, "llvm.dbg.declare", fn(self.type_metadata(), self.type_metadata()) -> void);
-            ifn!(map, "llvm.dbg.value", fn(self.type_metadata(), t_i64, self.type_metadata()) -> void);
+            // LLVM passes such as SROA turn the `dbg.declare` of locals promoted to registers into `dbg.value`s,
+            // which have the LLVM 7 signature without an offset, NVVM rejects the old one.
+            ifn!(map, "llvm.dbg.value", fn(self.type_metadata(), self.type_metadata(), self.type_metadata()) -> void);
         }
 
         // misc syscalls, only the ones we use
@@ -13,7 +13,7 @@ use rustc_index::bit_set::BitSet;
 use rustc_index::vec::Idx;
 
 use super::metadata::file_metadata;
-use super::util::DIB;
+use super::util::{debug_context, DIB};
 
 /// Produces DIScope DIEs for each MIR Scope which has variables defined in it.
 pub(crate) fn compute_mir_scopes<'ll, 'tcx>(
@@ -102,8 +102,13 @@ fn make_mir_scope<'ll, 'tcx>(
                 ty::ParamEnv::reveal_all(),
                 callee,
             );
-            let callee_fn_abi = cx.fn_abi_of_instance(callee, ty::List::empty());
-            cx.dbg_scope_fn(callee, callee_fn_abi, None)
+            // reuse the subprogram of the callee across inline sites so that the inline stack in the
+            // DWARF points at one function, which is what profilers and debuggers expect.
+            let mut inlined_fn_scopes = debug_context(cx).inlined_fn_scopes.borrow_mut();
+            *inlined_fn_scopes.entry(callee).or_insert_with(|| {
+                let callee_fn_abi = cx.fn_abi_of_instance(callee, ty::List::empty());
+                cx.dbg_scope_fn(callee, callee_fn_abi, None)
+            })
         }
         None => unsafe {
             llvm::LLVMRustDIBuilderCreateLexicalBlock(
@@ -52,6 +52,9 @@ pub struct CrateDebugContext<'a, 'tcx> {
 
     type_map: RefCell<TypeMap<'a, 'tcx>>,
     namespace_map: RefCell<DefIdMap<&'a DIScope>>,
+    /// The subprograms created for functions which were inlined by MIR inlining, so that every
+    /// inlined copy of a function refers to the same subprogram instead of a fresh one per call site.
+    inlined_fn_scopes: RefCell<FxHashMap<Instance<'tcx>, &'a DIScope>>,
 
     // This collection is used to assert that composite types (structs, enums,
     // ...) have their members only set once:
@@ -79,6 +82,7 @@ impl<'a, 'tcx> CrateDebugContext<'a, 'tcx> {
             created_enum_disr_types: Default::default(),
             type_map: Default::default(),
             namespace_map: RefCell::new(Default::default()),
+            inlined_fn_scopes: Default::default(),
             composite_types_completed: Default::default(),
         }
     }
@@ -99,6 +103,8 @@ impl<'a, 'tcx> CrateDebugContext<'a, 'tcx> {
 }
 
 impl<'a, 'll, 'tcx> DebugInfoBuilderMethods for Builder<'a, 'll, 'tcx> {
+    // every variable starts out in an alloca, which libnvvm describes as a location in local memory with `-g`.
+    // If LLVM promotes the alloca to a register, it replaces the `dbg.declare` with `dbg.value`s.
     fn dbg_var_addr(
         &mut self,
         dbg_var: &'ll DIVariable,
@@ -0,0 +1,101 @@
+//! Lowering of float classification to PTX's `testp` instruction.
+//!
+//! `f32::is_nan` is `x != x`, `is_infinite` becomes `fabs(x) == inf` and `is_finite` is `fabs(x) < inf` after
+//! optimization, which is a `setp` and for the last two an `abs` in PTX. `testp.notanumber`, `testp.infinite`
+//! and `testp.finite` classify the float in a single instruction without needing the absolute value, so we
+//! replace the comparisons with inline assembly writing the predicate directly (the `b` constraint).
+
+use tracing::trace;
+
+use rustc_codegen_ssa::common::RealPredicate;
+
+use crate::llvm::{self, False, Module, TypeKind, Value};
+
+/// Replaces every float classification comparison in the module with `testp`.
+pub(crate) fn lower_float_classification(llmod: &Module) {
+    unsafe {
+        let tests = classifications(llmod);
+        if tests.is_empty() {
+            return;
+        }
+
+        let llcx = llvm::LLVMGetModuleContext(llmod);
+        let builder = llvm::LLVMCreateBuilderInContext(llcx);
+        let i1 = llvm::LLVMInt1TypeInContext(llcx);
+        for (cmp, class, x, fabs) in tests {
+            let (ty, constraint) = match llvm::LLVMRustGetTypeKind(llvm::LLVMTypeOf(x)) {
+                TypeKind::Float => ("f32", "f"),
+                _ => ("f64", "d"),
+            };
+            trace!("Lowering `{:?}` to `testp.{}.{}`", cmp, class, ty);
+            let fn_ty = llvm::LLVMFunctionType(i1, [llvm::LLVMTypeOf(x)].as_ptr(), 1, False);
+            let asm = format!("testp.{}.{} $0, $1;", class, ty);
+            let constraints = format!("=b,{}", constraint);
+            let asm = llvm::inline_asm(fn_ty, &asm, &constraints, false);
+            llvm::replace_with_call(builder, cmp, asm, &[x]);
+            // the absolute value is usually only used by the comparison.
+            if let Some(fabs) = fabs {
+                if llvm::LLVMGetFirstUse(fabs).is_none() {
+                    llvm::LLVMInstructionEraseFromParent(fabs);
+                }
+            }
+        }
+        llvm::LLVMDisposeBuilder(builder);
+    }
+}
+
+/// Finds all the float classification comparisons, along with the `testp` class, the tested float and
+/// the `fabs` call the comparison used, if any.
+#[allow(clippy::type_complexity)]
+unsafe fn classifications(llmod: &Module) -> Vec<(&Value, &'static str, &Value, Option<&Value>)> {
+    llvm::module_instructions(llmod)
+        .into_iter()
+        .filter(|i| llvm::LLVMIsAFCmpInst(i).is_some())
+        .filter_map(|i| {
+            let (class, x, fabs) = classification(i)?;
+            Some((i, class, x, fabs))
+        })
+        .collect()
+}
+
+/// The `testp` class, tested float and `fabs` call of `x != x`, `x == x`, `fabs(x) == inf`,
+/// `fabs(x) < inf` and `fabs(x) != inf`.
+unsafe fn classification(cmp: &Value) -> Option<(&'static str, &Value, Option<&Value>)> {
+    let (lhs, rhs) = (llvm::LLVMGetOperand(cmp, 0), llvm::LLVMGetOperand(cmp, 1));
+    if !matches!(
+        llvm::LLVMRustGetTypeKind(llvm::LLVMTypeOf(lhs)),
+        TypeKind::Float | TypeKind::Double
+    ) {
+        return None;
+    }
+    let pred = llvm::LLVMGetFCmpPredicate(cmp);
+
+    if lhs == rhs {
+        return match pred {
+            p if p == RealPredicate::RealUNO as u32 => Some(("notanumber", lhs, None)),
+            p if p == RealPredicate::RealORD as u32 => Some(("number", lhs, None)),
+            _ => None,
+        };
+    }
+
+    let inf = llvm::LLVMIsAConstantFP(rhs)?;
+    let mut loses_info = False;
+    if llvm::LLVMConstRealGetDouble(inf, &mut loses_info) != f64::INFINITY {
+        return None;
+    }
+    let fabs = llvm::LLVMIsACallInst(lhs)?;
+    let callee = llvm::LLVMIsAFunction(llvm::LLVMGetCalledValue(fabs))?;
+    if !llvm::get_value_name(callee).starts_with(b"llvm.fabs.") {
+        return None;
+    }
+    let x = llvm::LLVMGetOperand(fabs, 0);
+
+    // `testp.finite` is false for NaN, just like the ordered comparisons.
+    match pred {
+        p if p == RealPredicate::RealOEQ as u32 => Some(("infinite", x, Some(fabs))),
+        p if p == RealPredicate::RealOLT as u32 || p == RealPredicate::RealONE as u32 => {
+            Some(("finite", x, Some(fabs)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,95 @@
+//! Lowering of float sign selects to PTX's `slct` instruction.
+//!
+//! `if x >= 0.0 { a } else { b }` with a `f32` `x` becomes `select (fcmp oge x, 0.0), a, b`, which is a
+//! `setp` followed by a `selp` in PTX. `slct.T.f32 d, a, b, x` does the same in a single instruction,
+//! including picking `b` if `x` is NaN. Such selects are mostly created by LLVM out of branches, so this is
+//! done after optimization instead of in the builder, replacing the select with inline assembly.
+
+use tracing::trace;
+
+use rustc_codegen_ssa::common::RealPredicate;
+
+use crate::llvm::{self, False, Module, Type, TypeKind, Value};
+
+/// Replaces every select on the sign of a `f32` in the module with `slct`.
+pub(crate) fn lower_float_sign_selects(llmod: &Module) {
+    unsafe {
+        let selects = sign_selects(llmod);
+        if selects.is_empty() {
+            return;
+        }
+
+        let llcx = llvm::LLVMGetModuleContext(llmod);
+        let builder = llvm::LLVMCreateBuilderInContext(llcx);
+        let f32 = llvm::LLVMFloatTypeInContext(llcx);
+        for (select, (ty, constraint), a, b, x) in selects {
+            trace!("Lowering `{:?}` to `slct.{}.f32`", select, ty);
+            let val_ty = llvm::LLVMTypeOf(a);
+            let fn_ty = llvm::LLVMFunctionType(val_ty, [val_ty, val_ty, f32].as_ptr(), 3, False);
+            let asm = format!("slct.{}.f32 $0, $1, $2, $3;", ty);
+            let constraints = format!("={0},{0},{0},f", constraint);
+            let asm = llvm::inline_asm(fn_ty, &asm, &constraints, false);
+            llvm::replace_with_call(builder, select, asm, &[a, b, x]);
+        }
+        llvm::LLVMDisposeBuilder(builder);
+    }
+}
+
+/// Finds all the selects on the sign of a `f32`, along with the `slct` type and constraint of the selected
+/// values and its `a`, `b` and `x` operands.
+#[allow(clippy::type_complexity)]
+unsafe fn sign_selects(
+    llmod: &Module,
+) -> Vec<(&Value, (&'static str, &'static str), &Value, &Value, &Value)> {
+    llvm::module_instructions(llmod)
+        .into_iter()
+        .filter(|i| llvm::LLVMIsASelectInst(i).is_some())
+        .filter_map(|i| {
+            let (a, b, x) = sign_select_operands(i)?;
+            let ty = slct_type(llvm::LLVMTypeOf(a))?;
+            Some((i, ty, a, b, x))
+        })
+        .collect()
+}
+
+/// The `a`, `b` and `x` of `slct` if the select is `x >= 0.0 ? a : b` or the equivalent `x < 0.0 ? b : a`.
+unsafe fn sign_select_operands(select: &Value) -> Option<(&Value, &Value, &Value)> {
+    let cond = llvm::LLVMGetOperand(select, 0);
+    llvm::LLVMIsAFCmpInst(cond)?;
+    let (x, zero) = (llvm::LLVMGetOperand(cond, 0), llvm::LLVMGetOperand(cond, 1));
+    if llvm::LLVMRustGetTypeKind(llvm::LLVMTypeOf(x)) != TypeKind::Float {
+        return None;
+    }
+    // -0.0 compares equal to 0.0, so both are fine, just like for `slct`.
+    let zero = llvm::LLVMIsAConstantFP(zero)?;
+    let mut loses_info = False;
+    if llvm::LLVMConstRealGetDouble(zero, &mut loses_info) != 0.0 {
+        return None;
+    }
+
+    let (then, else_) = (
+        llvm::LLVMGetOperand(select, 1),
+        llvm::LLVMGetOperand(select, 2),
+    );
+    // `slct` picks `b` for NaN, which is what `oge` does, `ult` is its inverse so it's the same with
+    // the values swapped. Other predicates differ for NaN or -0.0.
+    match llvm::LLVMGetFCmpPredicate(cond) {
+        p if p == RealPredicate::RealOGE as u32 => Some((then, else_, x)),
+        p if p == RealPredicate::RealULT as u32 => Some((else_, then, x)),
+        _ => None,
+    }
+}
+
+/// The type suffix and inline asm constraint `slct` uses for values of this type.
+unsafe fn slct_type(ty: &Type) -> Option<(&'static str, &'static str)> {
+    match llvm::LLVMRustGetTypeKind(ty) {
+        TypeKind::Float => Some(("f32", "f")),
+        TypeKind::Double => Some(("f64", "d")),
+        TypeKind::Integer => match llvm::LLVMGetIntTypeWidth(ty) {
+            32 => Some(("b32", "r")),
+            64 => Some(("b64", "l")),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -11,6 +11,7 @@ use std::str;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Once;
 
+use crate::context::CodegenArgs;
 use crate::llvm;
 
 static POISONED: AtomicBool = AtomicBool::new(false);
@@ -36,9 +37,11 @@ pub(crate) fn init(sess: &Session) {
 }
 
 unsafe fn configure_llvm(sess: &Session) {
-    // TODO(RDambrosio016): We override the meaning of llvm-args to pass our own nvvm args,
-    // but we should probably retain a way to pass args to LLVM.
-    let n_args = sess.opts.cg.llvm_args.len() + sess.target.llvm_args.len();
+    // llvm-args are mostly our own and libnvvm's args, only the ones which are neither are given to LLVM.
+    // Errors in the args are reported when the codegen args are parsed again later.
+    let cg_args = CodegenArgs::parse(&sess.opts.cg.llvm_args, sess.opts.cg.target_cpu.as_deref())
+        .unwrap_or_default();
+    let n_args = cg_args.llvm_args.len() + sess.target.llvm_args.len();
     let mut llvm_c_strs = Vec::with_capacity(n_args + 1);
     let mut llvm_args = Vec::with_capacity(n_args + 1);
 
@@ -64,17 +67,31 @@ unsafe fn configure_llvm(sess: &Session) {
     //     .collect();
 
     {
+        // LLVM exits the process on unknown options, and some of the options below only exist in newer LLVMs.
+        let is_known = |arg: &str| {
+            let name = arg
+                .trim_start_matches('-')
+                .split('=')
+                .next()
+                .unwrap_or_default();
+            let name = CString::new(name).unwrap();
+            llvm::LLVMRustIsKnownLLVMOption(name.as_ptr())
+        };
         // This adds the given argument to LLVM. Unless `force` is true
-        // user specified arguments are *not* overridden.
-        let mut add = |arg: &str, _force: bool| {
+        // user specified arguments are *not* overridden, and arguments LLVM does not know are skipped.
+        let mut add = |arg: &str, force: bool| {
+            if !force && !is_known(arg) {
+                return;
+            }
             // if force || !user_specified_args.contains(llvm_arg_to_arg_name(arg)) {
             let s = CString::new(arg).unwrap();
             llvm_args.push(s.as_ptr());
             llvm_c_strs.push(s);
             // }
         };
-        // Set the llvm "program name" to make usage and invalid argument messages more clear.
-        // add("rustc -Cllvm-args=\"...\" with", true);
+        // Set the llvm "program name" to make usage and invalid argument messages more clear,
+        // LLVM always treats the first arg as the program name.
+        add("rustc -Cllvm-args=\"...\" with", true);
         if sess.time_llvm_passes() {
             add("-time-passes", false);
         }
@@ -110,6 +127,15 @@ unsafe fn configure_llvm(sess: &Session) {
         // for arg in sess_args {
         //     add(&(*arg), true);
         // }
+
+        // user args are added last so they override ours.
+        for arg in &cg_args.llvm_args {
+            if is_known(arg) {
+                add(arg, true);
+            } else {
+                sess.warn(&format!("unknown LLVM option `{}`, ignoring", arg));
+            }
+        }
     }
 
     llvm::LLVMInitializePasses();
@@ -0,0 +1,93 @@
+//! Lowering of integer min/max to PTX's `min`/`max` instructions.
+//!
+//! `Ord::min`/`Ord::max` on integers are optimized by LLVM into `select (icmp a, b), a, b`. PTX has single
+//! instructions for these (`min.s32`, `max.u32`, etc.), but libnvvm keeps the comparison and the select
+//! in some cases. So after optimization we replace these patterns with the nvvm min/max intrinsics,
+//! which always become the single instruction.
+
+use tracing::trace;
+
+use crate::llvm::{self, False, IntPredicate, Module, TypeKind, Value};
+
+/// Replaces every integer min/max pattern in the module with a call to the matching nvvm intrinsic.
+pub(crate) fn lower_int_min_max(llmod: &Module) {
+    unsafe {
+        let selects = min_max_selects(llmod);
+        if selects.is_empty() {
+            return;
+        }
+
+        let llcx = llvm::LLVMGetModuleContext(llmod);
+        let builder = llvm::LLVMCreateBuilderInContext(llcx);
+        for (select, name, lhs, rhs) in selects {
+            trace!("Lowering `{:?}` to `{}`", select, name);
+            let ty = llvm::LLVMTypeOf(lhs);
+            let fn_ty = llvm::LLVMFunctionType(ty, [ty, ty].as_ptr(), 2, False);
+            let func =
+                llvm::LLVMRustGetOrInsertFunction(llmod, name.as_ptr().cast(), name.len(), fn_ty);
+
+            llvm::replace_with_call(builder, select, func, &[lhs, rhs]);
+        }
+        llvm::LLVMDisposeBuilder(builder);
+    }
+}
+
+/// Finds all the selects which are an integer min or max, along with the intrinsic they should be replaced with
+/// and its operands.
+unsafe fn min_max_selects(llmod: &Module) -> Vec<(&Value, &'static str, &Value, &Value)> {
+    llvm::module_instructions(llmod)
+        .into_iter()
+        .filter(|i| llvm::LLVMIsASelectInst(i).is_some())
+        .filter_map(|i| {
+            let (name, lhs, rhs) = min_max_intrinsic(i)?;
+            Some((i, name, lhs, rhs))
+        })
+        .collect()
+}
+
+unsafe fn min_max_intrinsic(select: &Value) -> Option<(&'static str, &Value, &Value)> {
+    let cond = llvm::LLVMGetOperand(select, 0);
+    llvm::LLVMIsAICmpInst(cond)?;
+    let (lhs, rhs) = (llvm::LLVMGetOperand(cond, 0), llvm::LLVMGetOperand(cond, 1));
+    let (then, else_) = (
+        llvm::LLVMGetOperand(select, 1),
+        llvm::LLVMGetOperand(select, 2),
+    );
+
+    // `a > b ? a : b` is a max, `a > b ? b : a` is a min.
+    let swapped = if then == lhs && else_ == rhs {
+        false
+    } else if then == rhs && else_ == lhs {
+        true
+    } else {
+        return None;
+    };
+
+    let ty = llvm::LLVMTypeOf(then);
+    if llvm::LLVMRustGetTypeKind(ty) != TypeKind::Integer {
+        return None;
+    }
+    let bits = llvm::LLVMGetIntTypeWidth(ty);
+
+    use IntPredicate::*;
+    let (is_max, signed) = match llvm::LLVMGetICmpPredicate(cond) {
+        IntSGT | IntSGE => (true, true),
+        IntSLT | IntSLE => (false, true),
+        IntUGT | IntUGE => (true, false),
+        IntULT | IntULE => (false, false),
+        IntEQ | IntNE => return None,
+    };
+
+    let name = match (is_max != swapped, signed, bits) {
+        (true, true, 32) => "llvm.nvvm.max.i",
+        (true, false, 32) => "llvm.nvvm.max.ui",
+        (true, true, 64) => "llvm.nvvm.max.ll",
+        (true, false, 64) => "llvm.nvvm.max.ull",
+        (false, true, 32) => "llvm.nvvm.min.i",
+        (false, false, 32) => "llvm.nvvm.min.ui",
+        (false, true, 64) => "llvm.nvvm.min.ll",
+        (false, false, 64) => "llvm.nvvm.min.ull",
+        _ => return None,
+    };
+    Some((name, lhs, rhs))
+}
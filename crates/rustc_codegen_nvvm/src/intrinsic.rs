@@ -1,8 +1,10 @@
 use crate::abi::LlvmType;
+use crate::asm::inline_asm_call;
 use crate::llvm::{self, Value};
 use crate::target;
 use crate::ty::LayoutLlvmExt;
 use crate::{builder::Builder, context::CodegenCx};
+use rustc_ast::LlvmAsmDialect;
 use rustc_codegen_ssa::common::span_invalid_monomorphization_error;
 use rustc_codegen_ssa::mir::place::PlaceRef;
 use rustc_codegen_ssa::traits::{BaseTypeMethods, BuilderMethods, ConstMethods, OverflowOp};
@@ -98,6 +100,66 @@ fn saturating_intrinsic_impl<'a, 'll, 'tcx>(
     }
 }
 
+/// Selects 4 bytes out of the 8 bytes of `x` and `0` with a single `prmt.b32`, nibble `i` of `selector`
+/// is the index of the byte which becomes byte `i` of the result, the same as CUDA C's `__byte_perm(x, 0, selector)`.
+fn byte_perm<'a, 'll, 'tcx>(
+    b: &mut Builder<'a, 'll, 'tcx>,
+    x: &'ll Value,
+    selector: u32,
+) -> &'ll Value {
+    let i32_ty = b.type_i32();
+    inline_asm_call(
+        b,
+        &format!("prmt.b32 $0, $1, 0, {:#x};", selector),
+        "=r,r",
+        &[x],
+        i32_ty,
+        false,
+        false,
+        LlvmAsmDialect::Att,
+        &[],
+    )
+    .unwrap()
+}
+
+// llvm 7 expands bswap into a sequence of shifts and masks for every byte, but a byte permutation
+// does it in a single instruction for every 32 bits.
+fn bswap<'a, 'll, 'tcx>(b: &mut Builder<'a, 'll, 'tcx>, x: &'ll Value, width: u64) -> &'ll Value {
+    let i32_ty = b.type_i32();
+    match width {
+        // byte swap a u8/i8 is just a no-op
+        8 => x,
+        16 => {
+            let x = b.zext(x, i32_ty);
+            let swapped = byte_perm(b, x, BSWAP16_SELECTOR);
+            b.trunc(swapped, b.type_i16())
+        }
+        32 => byte_perm(b, x, BSWAP32_SELECTOR),
+        // i128 currently goes through `handle_128_bit_intrinsic`, but swapping the halves works for it too.
+        64 | 128 => {
+            let half = width / 2;
+            let half_ty = b.type_ix(half);
+            let full_ty = b.type_ix(width);
+            let lo = b.trunc(x, half_ty);
+            let hi = b.lshr(x, b.const_uint(full_ty, half));
+            let hi = b.trunc(hi, half_ty);
+            // the swapped low half becomes the high half and vice versa.
+            let lo = bswap(b, lo, half);
+            let hi = bswap(b, hi, half);
+            let lo = b.zext(lo, full_ty);
+            let lo = b.shl(lo, b.const_uint(full_ty, half));
+            let hi = b.zext(hi, full_ty);
+            b.or(hi, lo)
+        }
+        _ => bug!("bswap on a {}-bit integer", width),
+    }
+}
+
+/// `prmt.b32` selector swapping the two low bytes of a zero-extended u16.
+const BSWAP16_SELECTOR: u32 = 0x4401;
+/// `prmt.b32` selector reversing the four bytes of a u32.
+const BSWAP32_SELECTOR: u32 = 0x0123;
+
 fn get_simple_intrinsic<'ll, 'tcx>(cx: &CodegenCx<'ll, 'tcx>, name: Symbol) -> Option<&'ll Value> {
     #[rustfmt::skip]
     let llvm_name = match name {
@@ -121,16 +183,23 @@ fn get_simple_intrinsic<'ll, 'tcx>(cx: &CodegenCx<'ll, 'tcx>, name: Symbol) -> O
         sym::log10f64     => "__nv_log10",
         sym::log2f32      => "__nv_log2f",
         sym::log2f64      => "__nv_log2",
-        sym::fmaf32       => "__nv_fmaf",
-        sym::fmaf64       => "__nv_fma",
-        sym::fabsf32      => "__nv_fabsf",
-        sym::fabsf64      => "__nv_fabs",
+        // fma is correctness sensitive since it only rounds once, use llvm's fma directly
+        // so it is guaranteed to become a single `fma.rn` instruction.
+        sym::fmaf32       => "llvm.fma.f32",
+        sym::fmaf64       => "llvm.fma.f64",
+        // abs and copysign only touch the sign bit, llvm's intrinsics become the single `abs`/`copysign`
+        // instructions instead of libdevice's bit manipulation.
+        sym::fabsf32      => "llvm.fabs.f32",
+        sym::fabsf64      => "llvm.fabs.f64",
+        // rust's min and max return the other value if one of them is NaN, which is C's fmin and fmax.
+        // libdevice's versions are a single `min.f32`/`max.f32`, which also return the non-NaN value,
+        // unlike `min.NaN.f32` which propagates the NaN.
         sym::minnumf32    => "__nv_fminf",
         sym::minnumf64    => "__nv_fmin",
         sym::maxnumf32    => "__nv_fmaxf",
         sym::maxnumf64    => "__nv_fmax",
-        sym::copysignf32  => "__nv_copysignf",
-        sym::copysignf64  => "__nv_copysign",
+        sym::copysignf32  => "llvm.copysign.f32",
+        sym::copysignf64  => "llvm.copysign.f64",
         sym::floorf32     => "__nv_floorf",
         sym::floorf64     => "__nv_floor",
         sym::ceilf32      => "__nv_ceilf",
@@ -229,7 +298,19 @@ impl<'a, 'll, 'tcx> IntrinsicCallMethods<'tcx> for Builder<'a, 'll, 'tcx> {
                 self.store(self.const_i32(0), llresult, ret_align)
             }
             sym::breakpoint => {
-                // debugtrap is not supported
+                // libnvvm does not support llvm.debugtrap, so emit `brkpt` directly. Like any other call it gets
+                // the current debug location, so with `-g` the debugger stops at the right source line.
+                inline_asm_call(
+                    self,
+                    "brkpt;",
+                    "",
+                    &[],
+                    self.type_void(),
+                    true,
+                    false,
+                    LlvmAsmDialect::Att,
+                    &[span],
+                );
                 return;
             }
             sym::va_copy => {
@@ -380,18 +461,7 @@ impl<'a, 'll, 'tcx> IntrinsicCallMethods<'tcx> for Builder<'a, 'll, 'tcx> {
                             &[args[0].immediate()],
                             None,
                         ),
-                        sym::bswap => {
-                            if width == 8 {
-                                args[0].immediate() // byte swap a u8/i8 is just a no-op
-                            } else {
-                                self.call(
-                                    self.type_i1(),
-                                    self.get_intrinsic(&format!("llvm.bswap.i{}", width)),
-                                    &[args[0].immediate()],
-                                    None,
-                                )
-                            }
-                        }
+                        sym::bswap => bswap(self, args[0].immediate(), width),
                         sym::bitreverse => self.call(
                             self.type_i1(),
                             self.get_intrinsic(&format!("llvm.bitreverse.i{}", width)),
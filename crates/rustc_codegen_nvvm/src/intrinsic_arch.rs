@@ -0,0 +1,307 @@
+//! Reporting which architecture the intrinsics used by a module need, with `--intrinsic-report`.
+//!
+//! Most intrinsics in cuda_std are inline assembly, which libnvvm copies into the PTX as-is, so using one which
+//! does not exist on the target architecture is only caught by ptxas (or the driver when JIT compiling), with
+//! an error about the PTX which does not say which function is the problem. The intrinsics which have fallbacks
+//! check `cuda_std::arch::CUDA_ARCH` themselves, this centralizes the check for all of them: we look at the PTX
+//! instructions of every inline assembly call in the module, and error with the required architecture for every
+//! instruction the target does not have. Along with that every instruction which needs more than the oldest
+//! architecture is listed, as well as the oldest architecture the module can be compiled for.
+//!
+//! Blocks which are only reached through a branch on a constant are skipped, so fallbacks behind
+//! `if CUDA_ARCH >= 800` are not reported even without optimizations. The table of instructions only covers
+//! the ones which are newer than `compute_60`, instructions which only exist on some PTX ISA versions are not
+//! checked either.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use nvvm::NvvmArch;
+use rustc_errors::Handler;
+
+use crate::llvm::{self, BasicBlock, False, Module, Value};
+use crate::recursion::fn_name;
+
+/// Instructions and the architecture they were added in. An instruction matches if its dot-separated parts
+/// start with the parts of the entry, the first matching entry is used.
+const INSTRUCTIONS: &[(&str, NvvmArch)] = &[
+    ("wgmma", NvvmArch::Compute90a),
+    ("setmaxnreg", NvvmArch::Compute90a),
+    ("cp.async.bulk", NvvmArch::Compute90),
+    ("cp.reduce.async.bulk", NvvmArch::Compute90),
+    ("red.async", NvvmArch::Compute90),
+    ("st.async", NvvmArch::Compute90),
+    ("mbarrier.try_wait", NvvmArch::Compute90),
+    ("mbarrier.expect_tx", NvvmArch::Compute90),
+    ("mbarrier.arrive.expect_tx", NvvmArch::Compute90),
+    ("elect.sync", NvvmArch::Compute90),
+    ("mapa", NvvmArch::Compute90),
+    ("getctarank", NvvmArch::Compute90),
+    ("barrier.cluster", NvvmArch::Compute90),
+    ("fence.proxy.async", NvvmArch::Compute90),
+    ("griddepcontrol", NvvmArch::Compute90),
+    ("stmatrix", NvvmArch::Compute90),
+    ("atom.add.noftz.bf16", NvvmArch::Compute90),
+    ("atom.add.noftz.bf16x2", NvvmArch::Compute90),
+    ("atom.global.add.noftz.bf16", NvvmArch::Compute90),
+    ("atom.global.add.noftz.bf16x2", NvvmArch::Compute90),
+    ("abs.bf16", NvvmArch::Compute80),
+    ("neg.bf16", NvvmArch::Compute80),
+    ("cp.async", NvvmArch::Compute80),
+    ("mbarrier", NvvmArch::Compute80),
+    ("redux.sync", NvvmArch::Compute80),
+    ("createpolicy", NvvmArch::Compute80),
+    ("ldmatrix", NvvmArch::Compute75),
+    ("movmatrix", NvvmArch::Compute75),
+    ("tanh.approx", NvvmArch::Compute75),
+    ("ex2.approx.f16", NvvmArch::Compute75),
+    ("ex2.approx.f16x2", NvvmArch::Compute75),
+    ("mma", NvvmArch::Compute70),
+    ("wmma", NvvmArch::Compute70),
+    ("match.any.sync", NvvmArch::Compute70),
+    ("match.all.sync", NvvmArch::Compute70),
+    ("nanosleep", NvvmArch::Compute70),
+    ("fence", NvvmArch::Compute70),
+    ("atom.add.noftz.f16", NvvmArch::Compute70),
+    ("atom.add.noftz.f16x2", NvvmArch::Compute70),
+    ("atom.global.add.noftz.f16", NvvmArch::Compute70),
+    ("atom.global.add.noftz.f16x2", NvvmArch::Compute70),
+    ("dp4a", NvvmArch::Compute61),
+    ("dp2a", NvvmArch::Compute61),
+];
+
+/// Qualifiers which need a newer architecture than the instruction they are used on, such as `ld.acquire`.
+const QUALIFIERS: &[(&str, NvvmArch)] = &[
+    ("cluster", NvvmArch::Compute90),
+    ("L2::cache_hint", NvvmArch::Compute80),
+    ("acquire", NvvmArch::Compute70),
+    ("release", NvvmArch::Compute70),
+    ("relaxed", NvvmArch::Compute70),
+];
+
+/// Errors about every instruction which needs a newer architecture than `arch`, and lists the instructions
+/// that need more than the oldest architecture.
+pub(crate) fn report_intrinsics(handler: &Handler, llmod: &Module, arch: NvvmArch) {
+    // (instruction, required arch) -> functions using it, sorted so the report is deterministic.
+    let mut uses = BTreeMap::<(String, NvvmArch), BTreeSet<String>>::new();
+    unsafe {
+        for f in llvm::defined_functions(llmod) {
+            for asm in inline_asm_strings(f) {
+                for instruction in instructions(&asm) {
+                    if let Some(required) = required_arch(instruction) {
+                        uses.entry((instruction.to_string(), required))
+                            .or_default()
+                            .insert(fn_name(f));
+                    }
+                }
+            }
+        }
+    }
+
+    if uses.is_empty() {
+        handler.note_without_error(
+            "no intrinsics which need a newer architecture than compute_60 are used",
+        );
+        return;
+    }
+
+    for ((instruction, required), funcs) in &uses {
+        let funcs = funcs
+            .iter()
+            .map(|f| format!("`{}`", f))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if is_available(arch, *required) {
+            handler.note_without_error(&format!(
+                "`{}` needs {} and is used in {}",
+                instruction, required, funcs
+            ));
+            continue;
+        }
+
+        let help = if required.is_arch_specific() {
+            format!(
+                "`{}` only exists on {}, code using it must be compiled for exactly that architecture",
+                instruction, required
+            )
+        } else {
+            format!(
+                "compile for {} or newer (for example with `CudaBuilder::arch` or `-arch={}`), or only use it \
+                when `cuda_std::arch::CUDA_ARCH >= {}`",
+                required,
+                required,
+                required.capability() * 10
+            )
+        };
+        handler
+            .struct_err(&format!(
+                "`{}` needs {}, but the target is {}, it is used in {}",
+                instruction, required, arch, funcs
+            ))
+            .help(&help)
+            .emit();
+    }
+
+    let (instruction, needed) = uses.keys().max_by_key(|(_, required)| *required).unwrap();
+    handler.note_without_error(&format!(
+        "this module needs at least {} because of `{}`",
+        needed, instruction
+    ));
+}
+
+fn is_available(arch: NvvmArch, required: NvvmArch) -> bool {
+    if required.is_arch_specific() {
+        arch == required
+    } else {
+        arch >= required
+    }
+}
+
+/// The architecture `instruction` needs if it is newer than the oldest architectures.
+fn required_arch(instruction: &str) -> Option<NvvmArch> {
+    let parts = instruction.split('.').collect::<Vec<_>>();
+    let from_instruction = INSTRUCTIONS.iter().find_map(|(entry, arch)| {
+        let entry = entry.split('.').collect::<Vec<_>>();
+        parts.starts_with(&entry).then(|| *arch)
+    });
+    let from_qualifiers = QUALIFIERS
+        .iter()
+        .filter(|(qualifier, _)| parts[1..].contains(qualifier))
+        .map(|(_, arch)| *arch);
+    from_instruction.into_iter().chain(from_qualifiers).max()
+}
+
+/// The instructions of an inline assembly string, without their operands, guards or labels, for example
+/// `redux.sync.add.u32` for `@%p redux.sync.add.u32 $0, $1, -1;`. Directives such as `.reg` are skipped.
+fn instructions(asm: &str) -> impl Iterator<Item = &str> {
+    asm.split(|c| c == ';' || c == '\n')
+        .filter_map(|statement| {
+            let mut statement =
+                statement.trim_start_matches(|c: char| c.is_whitespace() || c == '{' || c == '}');
+            if statement.starts_with("//") {
+                return None;
+            }
+            // a label can be in front of an instruction, `L2::cache_hint` must not be mistaken for one.
+            if let Some(idx) = statement.find(|c: char| c.is_whitespace()) {
+                if statement[..idx].ends_with(':') && !statement[..idx].contains("::") {
+                    statement = statement[idx..].trim_start();
+                }
+            }
+            if statement.starts_with('@') {
+                statement = statement.split_once(char::is_whitespace)?.1.trim_start();
+            }
+            let instruction = statement.split(char::is_whitespace).next()?;
+            (!instruction.is_empty()
+                && !instruction.starts_with('.')
+                && !instruction.ends_with(':'))
+            .then(|| instruction)
+        })
+}
+
+/// The strings of every inline assembly call in `func` which can actually be reached.
+unsafe fn inline_asm_strings(func: &Value) -> Vec<String> {
+    let mut strings = vec![];
+    for block in reachable_blocks(func) {
+        for i in llvm::block_instructions(block) {
+            if llvm::LLVMIsACallInst(i).is_none() {
+                continue;
+            }
+            if let Some(asm) = llvm::LLVMIsAInlineAsm(llvm::LLVMGetCalledValue(i)) {
+                let mut len = 0;
+                let data = llvm::LLVMRustGetInlineAsmString(asm, &mut len);
+                let bytes = std::slice::from_raw_parts(data.cast(), len);
+                strings.push(String::from_utf8_lossy(bytes).into_owned());
+            }
+        }
+    }
+    strings
+}
+
+/// The blocks of `func` which are reachable from its entry, not following branches on a constant which are
+/// never taken. Without optimizations, `if CUDA_ARCH >= 800 { .. } else { .. }` is still a branch on a constant
+/// with both blocks in the function.
+unsafe fn reachable_blocks(func: &Value) -> Vec<&BasicBlock> {
+    let mut visited = HashSet::new();
+    let mut blocks = vec![];
+    let mut stack = vec![llvm::LLVMGetEntryBasicBlock(func)];
+    while let Some(block) = stack.pop() {
+        if !visited.insert(block as *const BasicBlock) {
+            continue;
+        }
+        blocks.push(block);
+        let term = match llvm::LLVMGetBasicBlockTerminator(block) {
+            Some(term) => term,
+            None => continue,
+        };
+
+        let constant_branch = llvm::LLVMIsABranchInst(term)
+            .filter(|br| llvm::LLVMIsConditional(br) != False)
+            .and_then(|br| llvm::LLVMIsAConstantInt(llvm::LLVMGetCondition(br)));
+        if let Some(cond) = constant_branch {
+            // the first successor is taken if the condition is true.
+            let taken = if llvm::LLVMConstIntGetZExtValue(cond) != 0 {
+                0
+            } else {
+                1
+            };
+            stack.push(llvm::LLVMGetSuccessor(term, taken));
+            continue;
+        }
+        for i in 0..llvm::LLVMGetNumSuccessors(term) {
+            stack.push(llvm::LLVMGetSuccessor(term, i));
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_are_skipped_but_cache_hints_are_not() {
+        let asm = "done: ld.global.L2::cache_hint.u32 $0, [$1], $2;";
+        assert_eq!(
+            instructions(asm).collect::<Vec<_>>(),
+            ["ld.global.L2::cache_hint.u32"]
+        );
+        let asm = "ld.global.L2::cache_hint.u32 $0, [$1], $2;";
+        assert_eq!(
+            instructions(asm).collect::<Vec<_>>(),
+            ["ld.global.L2::cache_hint.u32"]
+        );
+        assert_eq!(
+            required_arch("ld.global.L2::cache_hint.u32"),
+            Some(NvvmArch::Compute80)
+        );
+    }
+
+    #[test]
+    fn predicate_guards_are_skipped() {
+        let asm = "{\n.reg .pred p;\n@p redux.sync.add.u32 $0, $1, -1;\n@!%p1 nanosleep.u32 10;\n}";
+        assert_eq!(
+            instructions(asm).collect::<Vec<_>>(),
+            ["redux.sync.add.u32", "nanosleep.u32"]
+        );
+    }
+
+    #[test]
+    fn longer_entries_are_matched_first() {
+        assert_eq!(
+            required_arch("cp.async.bulk.shared::cluster.global.mbarrier::complete_tx::bytes"),
+            Some(NvvmArch::Compute90)
+        );
+        assert_eq!(
+            required_arch("cp.async.ca.shared.global"),
+            Some(NvvmArch::Compute80)
+        );
+        assert_eq!(required_arch("add.u32"), None);
+    }
+
+    #[test]
+    fn arch_specific_instructions_need_that_arch() {
+        assert!(is_available(NvvmArch::Compute90, NvvmArch::Compute80));
+        assert!(!is_available(NvvmArch::Compute70, NvvmArch::Compute80));
+        assert!(is_available(NvvmArch::Compute90a, NvvmArch::Compute90a));
+        assert!(!is_available(NvvmArch::Compute90, NvvmArch::Compute90a));
+    }
+}
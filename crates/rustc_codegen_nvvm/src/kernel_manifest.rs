@@ -0,0 +1,181 @@
+//! A machine-readable manifest of the kernels of a crate, emitted with `--emit-kernel-manifest`.
+//!
+//! Host code which launches kernels through `cuLaunchKernel` with a packed param buffer, or which wants to check
+//! the params it passes, needs the size and offset of every kernel param. Those are only known to the codegen,
+//! because they come from the ABI layout of the Rust types. Each CGU records the params of its kernels
+//! in the `rustc_codegen_nvvm.kernel_params` named metadata, and at link time we collect them together with the
+//! launch bounds and cluster dimensions from `nvvm.annotations` and write `<output>.kernels.json`:
+//!
+//! ```json
+//! {
+//!   "kernels": [
+//!     {
+//!       "name": "add",
+//!       "params": [
+//!         { "arg": 0, "type": "*const f32", "size": 8, "align": 8, "offset": 0 },
+//!         { "arg": 1, "type": "usize", "size": 8, "align": 8, "offset": 8 }
+//!       ],
+//!       "param_size": 16,
+//!       "launch_bounds": { "max_threads": 256, "min_blocks": null },
+//!       "cluster_dims": null
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `arg` is the index of the Rust param, args which are passed as multiple PTX params (like slices) have one
+//! entry per PTX param and zero sized args have none. Offsets follow the layout of the PTX param space, where
+//! every param is aligned to its alignment.
+
+use std::fmt::Write;
+
+use rustc_middle::ty::Ty;
+use rustc_target::abi::{Abi, HasDataLayout};
+
+use crate::abi::{FnAbi, PassMode};
+use crate::context::CodegenCx;
+use crate::llvm::{self, Module, Value};
+
+const KERNEL_PARAMS: &str = "rustc_codegen_nvvm.kernel_params\0";
+
+/// Records the layout of the params of `kernel` so it can be written to the manifest at link time.
+pub(crate) fn record_kernel_params<'ll, 'tcx>(
+    cx: &CodegenCx<'ll, 'tcx>,
+    kernel: &'ll Value,
+    fn_abi: &FnAbi<'tcx, Ty<'tcx>>,
+) {
+    let params = params_json(cx, fn_abi);
+    unsafe {
+        let params =
+            llvm::LLVMMDStringInContext(cx.llcx, params.as_ptr().cast(), params.len() as u32);
+        let mdvals = &[kernel, params];
+        let node = llvm::LLVMMDNodeInContext(cx.llcx, mdvals.as_ptr(), mdvals.len() as u32);
+        llvm::LLVMAddNamedMetadataOperand(cx.llmod, KERNEL_PARAMS.as_ptr().cast(), node);
+    }
+}
+
+/// The `params` array and the total `param_size` of a kernel as JSON.
+fn params_json<'tcx>(cx: &CodegenCx<'_, 'tcx>, fn_abi: &FnAbi<'tcx, Ty<'tcx>>) -> String {
+    let dl = cx.data_layout();
+    let mut params = vec![];
+    for (idx, arg) in fn_abi.args.iter().enumerate() {
+        let ty = escape(&arg.layout.ty.to_string());
+        let mut push = |size: u64, align: u64| params.push((idx, ty.clone(), size, align));
+        match arg.mode {
+            PassMode::Ignore => {}
+            PassMode::Direct(_) => push(arg.layout.size.bytes(), arg.layout.align.abi.bytes()),
+            PassMode::Pair(..) => match arg.layout.abi {
+                Abi::ScalarPair(ref a, ref b) => {
+                    push(a.value.size(dl).bytes(), a.value.align(dl).abi.bytes());
+                    push(b.value.size(dl).bytes(), b.value.align(dl).abi.bytes());
+                }
+                _ => unreachable!("pair args must be scalar pairs"),
+            },
+            PassMode::Cast(ref cast) => push(cast.size(dl).bytes(), cast.align(dl).bytes()),
+            PassMode::Indirect { on_stack: true, .. } => {
+                push(arg.layout.size.bytes(), arg.layout.align.abi.bytes())
+            }
+            PassMode::Indirect {
+                extra_attrs: None, ..
+            } => push(dl.pointer_size.bytes(), dl.pointer_align.abi.bytes()),
+            // unsized args also pass their metadata.
+            PassMode::Indirect {
+                extra_attrs: Some(_),
+                ..
+            } => {
+                push(dl.pointer_size.bytes(), dl.pointer_align.abi.bytes());
+                push(dl.pointer_size.bytes(), dl.pointer_align.abi.bytes());
+            }
+        }
+    }
+
+    let mut offset = 0;
+    let mut json = String::from("[");
+    for (i, (arg, ty, size, align)) in params.into_iter().enumerate() {
+        offset = align_to(offset, align);
+        if i != 0 {
+            json.push_str(", ");
+        }
+        write!(
+            json,
+            r#"{{ "arg": {}, "type": "{}", "size": {}, "align": {}, "offset": {} }}"#,
+            arg, ty, size, align, offset
+        )
+        .unwrap();
+        offset += size;
+    }
+    write!(json, r#"], "param_size": {}"#, offset).unwrap();
+    json
+}
+
+fn align_to(offset: u64, align: u64) -> u64 {
+    (offset + align - 1) / align * align
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '"' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+/// Builds the manifest of every kernel recorded in `llmod`, in the order they were recorded.
+pub(crate) fn kernel_manifest(llmod: &Module) -> String {
+    let annotations = llvm::nvvm_annotations(llmod);
+    let annotation = |kernel: &Value, kind: &[u8]| {
+        annotations
+            .iter()
+            .filter(|(func, k, _)| *func == kernel && *k == kind)
+            .find_map(|(_, _, val)| unsafe {
+                llvm::LLVMIsAConstantInt(val).map(|val| llvm::LLVMConstIntGetZExtValue(val))
+            })
+    };
+
+    let mut kernels = vec![];
+    for (kernel, params) in recorded_params(llmod) {
+        let name = escape(&String::from_utf8_lossy(llvm::get_value_name(kernel)));
+        let launch_bounds = match annotation(kernel, b"maxntidx") {
+            Some(max_threads) => format!(
+                r#"{{ "max_threads": {}, "min_blocks": {} }}"#,
+                max_threads,
+                annotation(kernel, b"minctasm").map_or("null".to_string(), |n| n.to_string())
+            ),
+            None => "null".to_string(),
+        };
+        let dims = [&b"cluster_dim_x"[..], b"cluster_dim_y", b"cluster_dim_z"]
+            .iter()
+            .map(|kind| annotation(kernel, kind))
+            .collect::<Option<Vec<_>>>();
+        let cluster_dims = match dims.as_deref() {
+            Some(&[x, y, z]) => format!("[{}, {}, {}]", x, y, z),
+            _ => "null".to_string(),
+        };
+        kernels.push(format!(
+            "    {{\n      \"name\": \"{}\",\n      \"params\": {},\n      \"launch_bounds\": {},\n      \"cluster_dims\": {}\n    }}",
+            name, params, launch_bounds, cluster_dims
+        ));
+    }
+
+    format!("{{\n  \"kernels\": [\n{}\n  ]\n}}\n", kernels.join(",\n"))
+}
+
+/// The kernels and their `params_json` recorded in the `rustc_codegen_nvvm.kernel_params` named metadata.
+fn recorded_params(llmod: &Module) -> Vec<(&Value, String)> {
+    llvm::named_metadata_nodes(llmod, KERNEL_PARAMS)
+        .into_iter()
+        .filter_map(|node| match llvm::md_node_operands(node)[..] {
+            [Some(kernel), Some(params)] => unsafe {
+                let mut len = 0;
+                let params = llvm::LLVMGetMDString(params, &mut len);
+                if params.is_null() {
+                    return None;
+                }
+                let params = std::slice::from_raw_parts(params.cast(), len as usize);
+                Some((kernel, String::from_utf8_lossy(params).into_owned()))
+            },
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,350 @@
+//! Validation of kernel launch bounds (`#[kernel(launch_bounds(max_threads, min_blocks))]`).
+//!
+//! Launch bounds make ptxas limit the registers of a kernel so that `min_blocks` blocks of `max_threads`
+//! threads fit on one multiprocessor at the same time. If the kernel needs more registers than that, ptxas
+//! spills them to local memory, which is slow and usually not what the user expected. ptxas only mentions
+//! this when run verbosely, so we estimate the registers a kernel needs and warn if the bounds are too tight.
+//!
+//! The estimate is the maximum amount of 32-bit values live at once in the optimized kernel, found by walking
+//! the instructions in layout order. It does not account for values live around loops or for the registers of
+//! called functions, so it is a lower bound of what ptxas will need, and a warning almost always means spilling.
+//!
+//! Kernels can also ask for an occupancy instead (`#[kernel(occupancy(percent))]`), in which case we derive
+//! launch bounds which let that percentage of the maximum threads of a multiprocessor run at once. If the
+//! estimated registers of the kernel do not allow that, the occupancy is lowered to what they allow, so that
+//! the derived bounds never force ptxas to spill.
+//!
+//! The bounds are given to libnvvm as `nvvm.annotations`, which it turns into the `.maxntid` and `.minnctapersm`
+//! directives of the kernel (and `.reqntid` for `reqntid` annotations). libnvvm silently ignores annotations it
+//! does not understand, so once the PTX is generated we check that every kernel actually has the directives
+//! its annotations ask for, and warn if one was dropped.
+
+use std::collections::HashMap;
+
+use nvvm::NvvmArch;
+use rustc_codegen_ssa::traits::ConstMethods;
+use rustc_errors::Handler;
+
+use crate::context::CodegenCx;
+use crate::llvm::{self, Module, Type, TypeKind, Value};
+
+const OCCUPANCY_TARGETS: &str = "rustc_codegen_nvvm.occupancy\0";
+
+/// The maximum amount of registers a single thread can use on every architecture.
+const MAX_REGISTERS_PER_THREAD: u32 = 255;
+
+/// Registers are allocated to warps in chunks of 256, so each thread gets a multiple of 8.
+const REGISTER_ALLOCATION_UNIT: u32 = 8;
+
+const WARP_SIZE: u32 = 32;
+
+const MAX_THREADS_PER_BLOCK: u32 = 1024;
+
+/// The amount of 32-bit registers in the register file of one multiprocessor.
+fn registers_per_multiprocessor(arch: NvvmArch) -> u32 {
+    match arch {
+        NvvmArch::Compute37 => 128 * 1024,
+        _ => 64 * 1024,
+    }
+}
+
+fn max_threads_per_multiprocessor(arch: NvvmArch) -> u32 {
+    match arch {
+        NvvmArch::Compute75 => 1024,
+        _ => 2048,
+    }
+}
+
+/// Records the occupancy `kernel` asked for, launch bounds are derived from it after optimization
+/// by [`derive_launch_bounds`] because that is when its registers can be estimated.
+pub(crate) fn record_occupancy_target<'ll>(
+    cx: &CodegenCx<'ll, '_>,
+    kernel: &'ll Value,
+    percent: u32,
+) {
+    unsafe {
+        let mdvals = &[kernel, cx.const_i32(percent as i32)];
+        let node = llvm::LLVMMDNodeInContext(cx.llcx, mdvals.as_ptr(), mdvals.len() as u32);
+        llvm::LLVMAddNamedMetadataOperand(cx.llmod, OCCUPANCY_TARGETS.as_ptr().cast(), node);
+    }
+}
+
+/// Adds `maxntidx` and `minctasm` annotations to every kernel with an occupancy target, then removes the targets.
+pub(crate) fn derive_launch_bounds(handler: &Handler, llmod: &Module, arch: NvvmArch) {
+    let targets = unsafe { occupancy_targets(llmod) };
+    if targets.is_empty() {
+        return;
+    }
+
+    let max_threads = max_threads_per_multiprocessor(arch);
+    for (kernel, percent) in targets {
+        let name = String::from_utf8_lossy(llvm::get_value_name(kernel));
+        let wanted = (max_threads * percent.min(100) / 100 + WARP_SIZE - 1) / WARP_SIZE * WARP_SIZE;
+        let wanted = wanted.max(WARP_SIZE);
+
+        let registers = unsafe { estimate_registers(kernel) }.max(1);
+        let registers = (registers + REGISTER_ALLOCATION_UNIT - 1) / REGISTER_ALLOCATION_UNIT
+            * REGISTER_ALLOCATION_UNIT;
+        let allowed = (registers_per_multiprocessor(arch) / registers) / WARP_SIZE * WARP_SIZE;
+        let threads = if registers > MAX_REGISTERS_PER_THREAD || allowed < wanted {
+            let threads = allowed.max(WARP_SIZE);
+            handler.warn(&format!(
+                "kernel `{}` needs an estimated {} registers per thread, which limits it to {}% occupancy on {} \
+                instead of the requested {}%",
+                name,
+                registers,
+                threads * 100 / max_threads,
+                arch,
+                percent
+            ));
+            threads
+        } else {
+            wanted
+        };
+
+        // split the threads into as few blocks as possible, a block has at most 1024 threads.
+        let min_blocks = (threads + MAX_THREADS_PER_BLOCK - 1) / MAX_THREADS_PER_BLOCK;
+        let max_threads_per_block = threads / min_blocks / WARP_SIZE * WARP_SIZE;
+        llvm::add_nvvm_annotation(llmod, kernel, "maxntidx", max_threads_per_block);
+        llvm::add_nvvm_annotation(llmod, kernel, "minctasm", min_blocks);
+    }
+
+    unsafe {
+        llvm::LLVMRustEraseNamedMetadata(llmod, OCCUPANCY_TARGETS.as_ptr().cast());
+    }
+}
+
+/// The kernels and their occupancy percentage recorded in the `rustc_codegen_nvvm.occupancy` named metadata.
+unsafe fn occupancy_targets(llmod: &Module) -> Vec<(&Value, u32)> {
+    llvm::named_metadata_nodes(llmod, OCCUPANCY_TARGETS)
+        .into_iter()
+        .filter_map(|node| match llvm::md_node_operands(node)[..] {
+            [Some(kernel), Some(percent)] => {
+                let percent = llvm::LLVMIsAConstantInt(percent)?;
+                Some((kernel, llvm::LLVMConstIntGetZExtValue(percent) as u32))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Warns about kernels whose launch bounds cannot be satisfied on `arch`, or which will likely force
+/// ptxas to spill registers.
+pub(crate) fn check_launch_bounds(handler: &Handler, llmod: &Module, arch: NvvmArch) {
+    // (kernel, max threads, min blocks) in the order of the annotations so warnings are deterministic.
+    let mut bounds: Vec<(&Value, Option<u32>, u32)> = vec![];
+    for (func, kind, val) in llvm::nvvm_annotations(llmod) {
+        let val = match unsafe { llvm::LLVMIsAConstantInt(val) } {
+            Some(val) => unsafe { llvm::LLVMConstIntGetZExtValue(val) as u32 },
+            None => continue,
+        };
+        let pos = match bounds.iter().position(|(f, _, _)| *f == func) {
+            Some(pos) => pos,
+            None => {
+                bounds.push((func, None, 1));
+                bounds.len() - 1
+            }
+        };
+        match kind {
+            b"maxntidx" => bounds[pos].1 = Some(val),
+            b"minctasm" => bounds[pos].2 = val,
+            _ => {}
+        }
+    }
+
+    for (kernel, max_threads, min_blocks) in bounds {
+        let max_threads = match max_threads {
+            Some(max_threads) => max_threads,
+            None => continue,
+        };
+        let name = String::from_utf8_lossy(llvm::get_value_name(kernel));
+
+        if max_threads == 0 || max_threads > MAX_THREADS_PER_BLOCK {
+            handler.warn(&format!(
+                "kernel `{}` has launch bounds of {} threads per block, but a block can have 1 to {} threads",
+                name, max_threads, MAX_THREADS_PER_BLOCK
+            ));
+            continue;
+        }
+        let threads = (max_threads + WARP_SIZE - 1) / WARP_SIZE * WARP_SIZE * min_blocks.max(1);
+        if threads > max_threads_per_multiprocessor(arch) {
+            handler.warn(&format!(
+                "kernel `{}` has launch bounds of {} blocks of {} threads per multiprocessor, but {} can only run {} threads per multiprocessor",
+                name,
+                min_blocks,
+                max_threads,
+                arch,
+                max_threads_per_multiprocessor(arch)
+            ));
+            continue;
+        }
+
+        let available = (registers_per_multiprocessor(arch) / threads) / REGISTER_ALLOCATION_UNIT
+            * REGISTER_ALLOCATION_UNIT;
+        let available = available.min(MAX_REGISTERS_PER_THREAD);
+        let estimate = unsafe { estimate_registers(kernel) };
+        if estimate > available {
+            handler.warn(&format!(
+                "kernel `{}` needs an estimated {} registers per thread, but its launch bounds limit it to {} on {}, \
+                registers will be spilled to local memory",
+                name, estimate, available, arch
+            ));
+        }
+    }
+}
+
+/// Estimates the registers a function needs as the maximum amount of 32-bit values live at the same time.
+unsafe fn estimate_registers(func: &Value) -> u32 {
+    let mut values = vec![];
+    for i in 0..llvm::LLVMCountParams(func) {
+        values.push((llvm::LLVMGetParam(func, i), 0));
+    }
+
+    // number every instruction in layout order, a value is live from its definition to its last use.
+    let mut index = HashMap::new();
+    for i in llvm::function_instructions(func) {
+        let idx = index.len() + 1;
+        index.insert(i, idx);
+        values.push((i, idx));
+    }
+
+    let mut live = vec![0i64; index.len() + 2];
+    for (val, def) in values {
+        let units = register_units(llvm::LLVMTypeOf(val));
+        if units == 0 {
+            continue;
+        }
+        let mut last = def;
+        let mut use_ = llvm::LLVMGetFirstUse(val);
+        while let Some(u) = use_ {
+            if let Some(&idx) = index.get(&llvm::LLVMGetUser(u)) {
+                last = last.max(idx);
+            }
+            use_ = llvm::LLVMGetNextUse(u);
+        }
+        live[def] += units as i64;
+        live[last] -= units as i64;
+    }
+
+    let mut current = 0;
+    let mut max = 0;
+    for delta in live {
+        current += delta;
+        max = max.max(current);
+    }
+    max as u32
+}
+
+/// The amount of 32-bit registers a value of this type takes, predicates (`i1`) live in
+/// separate registers and aggregates are split up by the backend, so they count as `0`.
+unsafe fn register_units(ty: &Type) -> u32 {
+    match llvm::LLVMRustGetTypeKind(ty) {
+        TypeKind::Integer => match llvm::LLVMGetIntTypeWidth(ty) {
+            1 => 0,
+            width => (width + 31) / 32,
+        },
+        TypeKind::Half | TypeKind::Float => 1,
+        TypeKind::Double | TypeKind::Pointer => 2,
+        _ => 0,
+    }
+}
+
+/// The `.maxntid`, `.reqntid` and `.minnctapersm` directives every kernel in `llmod` should get from its
+/// annotations, with the thread counts being the total of every dimension. Sorted so warnings are deterministic.
+pub(crate) fn expected_ptx_directives(llmod: &Module) -> Vec<(String, Vec<(&'static str, u32)>)> {
+    let mut kernels: Vec<(&Value, Vec<(&'static str, u32)>)> = vec![];
+    for (func, kind, val) in llvm::nvvm_annotations(llmod) {
+        let directive = match kind {
+            b"maxntidx" | b"maxntidy" | b"maxntidz" => ".maxntid",
+            b"reqntidx" | b"reqntidy" | b"reqntidz" => ".reqntid",
+            b"minctasm" => ".minnctapersm",
+            _ => continue,
+        };
+        let val = match unsafe { llvm::LLVMIsAConstantInt(val) } {
+            Some(val) => unsafe { llvm::LLVMConstIntGetZExtValue(val) as u32 },
+            None => continue,
+        };
+        let pos = match kernels.iter().position(|(f, _)| *f == func) {
+            Some(pos) => pos,
+            None => {
+                kernels.push((func, vec![]));
+                kernels.len() - 1
+            }
+        };
+        let directives = &mut kernels[pos].1;
+        match directives.iter_mut().find(|(d, _)| *d == directive) {
+            Some((_, total)) => *total *= val,
+            None => directives.push((directive, val)),
+        }
+    }
+
+    let mut kernels = kernels
+        .into_iter()
+        .map(|(func, mut directives)| {
+            directives.sort_unstable();
+            let name = String::from_utf8_lossy(llvm::get_value_name(func)).into_owned();
+            (name, directives)
+        })
+        .collect::<Vec<_>>();
+    kernels.sort();
+    kernels
+}
+
+/// Warns about every directive of [`expected_ptx_directives`] which is missing or different in the PTX
+/// libnvvm generated.
+pub(crate) fn verify_ptx_directives(
+    handler: &Handler,
+    expected: &[(String, Vec<(&'static str, u32)>)],
+    ptx: &str,
+) {
+    for (kernel, directives) in expected {
+        let header = match entry_header(ptx, kernel) {
+            Some(header) => header,
+            None => continue,
+        };
+        for &(directive, value) in directives {
+            let found = directive_value(header, directive);
+            if found == Some(value) {
+                continue;
+            }
+            let found = match found {
+                Some(found) => format!("`{} {}`", directive, found),
+                None => "nothing".to_string(),
+            };
+            handler.warn(&format!(
+                "kernel `{}` should have `{} {}` in the PTX, but libnvvm emitted {}, its launch bounds are not applied",
+                kernel, directive, value, found
+            ));
+        }
+    }
+}
+
+/// The directives between the params and the body of the `.entry` of `kernel`:
+///
+/// ```text
+/// .visible .entry add(
+///     .param .u64 add_param_0
+/// )
+/// .maxntid 256, 1, 1
+/// .minnctapersm 2
+/// {
+/// ```
+fn entry_header<'a>(ptx: &'a str, kernel: &str) -> Option<&'a str> {
+    let start = ptx.find(&format!(".entry {}(", kernel))?;
+    let entry = &ptx[start..];
+    entry.get(entry.find(')')?..entry.find('{')?)
+}
+
+/// The total of the values of `directive` in the header of an entry, `.maxntid 256, 1, 1` is `256`.
+fn directive_value(header: &str, directive: &str) -> Option<u32> {
+    header.lines().find_map(|line| {
+        let values = line.trim().strip_prefix(directive)?;
+        if !values.starts_with(char::is_whitespace) {
+            return None;
+        }
+        values
+            .split(',')
+            .map(|value| value.trim().parse::<u32>().ok())
+            .product()
+    })
+}
@@ -36,19 +36,35 @@ mod consts;
 mod context;
 mod ctx_intrinsics;
 mod debug_info;
+mod float_class;
+mod float_select;
 mod init;
+mod int_min_max;
 mod int_replace;
 mod intrinsic;
+mod intrinsic_arch;
+mod kernel_manifest;
+mod launch_bounds;
 mod link;
 mod llvm;
+mod local_memory;
 mod lto;
 mod mono_item;
 mod nvvm;
+mod ptx_report;
+mod recursion;
+mod reduction;
+mod reflect;
+mod shared_array;
+mod symbol_table;
 mod target;
 mod ty;
+mod unsupported_std;
 
+use ::nvvm::NvvmArch;
 use abi::readjust_fn_abi;
 use back::target_machine_factory;
+use context::CodegenArgs;
 use lto::ThinBuffer;
 use rustc_codegen_ssa::{
     back::{
@@ -67,6 +83,7 @@ use rustc_middle::{
     ty::TyCtxt,
 };
 use rustc_session::{cstore::MetadataLoaderDyn, Session};
+use rustc_span::Symbol;
 use tracing::debug;
 
 use std::{ffi::CString, sync::Arc, sync::Mutex};
@@ -117,6 +134,18 @@ impl CodegenBackend for NvvmCodegenBackend {
     }
     fn provide_extern(&self, _providers: &mut query::Providers) {}
 
+    /// Exposes the target architecture to code as `target_feature`s. Every architecture up to and including
+    /// the target architecture is enabled, so `#[cfg(target_feature = "compute_70")]` is true when compiling for
+    /// compute_70 or above. Architecture-specific variants such as compute_90a are only enabled for themselves.
+    fn target_features(&self, sess: &Session) -> Vec<Symbol> {
+        let arch = CodegenArgs::from_session(sess).arch();
+        NvvmArch::ALL
+            .iter()
+            .filter(|x| **x <= arch && (!x.is_arch_specific() || **x == arch))
+            .map(|x| Symbol::intern(&x.to_string()))
+            .collect()
+    }
+
     fn codegen_crate(
         &self,
         tcx: TyCtxt<'_>,
@@ -1,3 +1,4 @@
+use nvvm::{NvvmArch, NvvmOption};
 use rustc_codegen_ssa::traits::ThinBufferMethods;
 use rustc_codegen_ssa::CodegenResults;
 use rustc_codegen_ssa::CompiledModule;
@@ -5,7 +6,7 @@ use rustc_codegen_ssa::NativeLib;
 use rustc_data_structures::owning_ref::OwningRef;
 use rustc_data_structures::rustc_erase_owner;
 use rustc_data_structures::sync::MetadataRef;
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 use rustc_middle::middle::dependency_format::Linkage;
 use rustc_session::cstore::MetadataLoader;
 use rustc_session::output::out_filename;
@@ -28,10 +29,12 @@ use tracing::{debug, trace};
 
 use crate::context::CodegenArgs;
 use crate::create_module;
+use crate::llvm;
 use crate::llvm::Context;
 use crate::llvm::LLVMLinkModules2;
 use crate::llvm::LLVMRustParseBitcodeForLTO;
 use crate::lto::ThinBuffer;
+use crate::nvvm::LIBINTRINSICS;
 use crate::LlvmMod;
 
 pub(crate) struct NvvmMetadataLoader;
@@ -201,7 +204,7 @@ fn link_exe(
         std::fs::create_dir_all(&out_dir)?;
     }
 
-    codegen_into_ptx_file(allocator, deps, sess, &objects, &rlibs, out_filename)
+    codegen_into_ptx_file(allocator, deps, sess, &objects, &rlibs, out_filename, &out_dir)
 }
 
 /// This is the meat of the codegen, taking all of the llvm bitcode modules we have, and giving them to
@@ -213,6 +216,7 @@ fn codegen_into_ptx_file(
     objects: &[PathBuf],
     rlibs: &[PathBuf],
     out_filename: &Path,
+    out_dir: &Path,
 ) -> io::Result<()> {
     debug!("Codegenning crate into PTX, allocator: {}, deps:\n{:#?}, objects:\n{:#?}, rlibs:\n{:#?}, out_filename:\n{:#?}",
         allocator.is_some(),
@@ -286,6 +290,14 @@ fn codegen_into_ptx_file(
         main_modules.push((bc, String::from("allocator")));
     }
 
+    // libnvvm links the main modules like `merge_cgus` would, but without removing the copies of the same
+    // function in every CGU, so give it a single module instead.
+    if main_modules.len() > 1 {
+        let name = main_modules[0].1.clone();
+        let cgus = main_modules.into_iter().map(|(bc, _)| bc).collect();
+        main_modules = vec![(merge_cgus(cgus, cx.llcx, name.clone()), name)];
+    }
+
     let sorted_deps = deps.into_iter().filter_map(|x| {
         for (bc, name) in &rlib_deps {
             let new_name = name.split_once("-").expect("uh oh rustc changed the format of rlib file names, better go make an angry zulip thread.").0;
@@ -299,14 +311,55 @@ fn codegen_into_ptx_file(
         None
     }).collect::<Vec<_>>();
 
+    // rustc cleans up the object files after linking, and the rlib modules only ever exist in memory,
+    // so with `-C save-temps` we dump the exact modules (and the order) given to libnvvm so libnvvm issues
+    // can be reproduced manually.
+    if sess.opts.cg.save_temps {
+        save_nvvm_inputs(out_dir, &main_modules, &sorted_deps)?;
+    }
+
     // now that we have our nice bitcode modules, we just need to find libdevice and give our
     // modules to nvvm to make a final ptx file
 
     // we need to actually parse the codegen args again, because codegencx is not available at link time.
-    let nvvm_opts = CodegenArgs::from_session(sess).nvvm_options;
+    let args = CodegenArgs::from_session(sess);
+
+    if args.emit_kernel_manifest {
+        write_kernel_manifest(cx.llcx, &main_modules, out_filename)?;
+    }
+
+    if args.emit_symbol_table {
+        write_symbol_table(cx.llcx, &main_modules, out_filename)?;
+    }
+
+    if args.emit_device_bitcode {
+        return write_device_bitcode(cx.llcx, main_modules, sorted_deps, out_filename);
+    }
+
+    if args.split_kernels {
+        codegen_kernels_separately(
+            &args,
+            sess,
+            cx.llcx,
+            &main_modules,
+            &sorted_deps,
+            out_filename,
+        )?;
+    }
+
+    // libnvvm lowers the launch bounds annotations to PTX directives, check that it did not drop any.
+    let launch_bounds = with_main_module(
+        cx.llcx,
+        &main_modules,
+        crate::launch_bounds::expected_ptx_directives,
+    );
+
+    for &arch in &args.ptx_archs {
+        codegen_for_arch(&args, sess, arch, &main_modules, &sorted_deps, out_filename)?;
+    }
 
     let ptx_bytes =
-        match crate::nvvm::codegen_bitcode_modules(&nvvm_opts, sess, main_modules, sorted_deps) {
+        match crate::nvvm::codegen_bitcode_modules(&args, sess, main_modules, sorted_deps) {
             Ok(bytes) => bytes,
             Err(err) => {
                 // TODO(RDambrosio016): maybe include the nvvm log with this fatal error
@@ -314,7 +367,220 @@ fn codegen_into_ptx_file(
             }
         };
 
-    std::fs::write(out_filename, ptx_bytes)
+    crate::launch_bounds::verify_ptx_directives(
+        sess.diagnostic(),
+        &launch_bounds,
+        &String::from_utf8_lossy(&ptx_bytes),
+    );
+    std::fs::write(out_filename, ptx_bytes)?;
+    if args.ptx_report {
+        crate::ptx_report::report_ptx_resources(sess, &args, out_filename);
+    }
+    Ok(())
+}
+
+/// Codegens every kernel into its own PTX file named `<out_filename stem>.<kernel>.ptx`, so runtimes can load
+/// only the kernels they need. Every file contains a single `.entry` along with all of the device functions it
+/// uses, device functions used by multiple kernels are duplicated into each file.
+fn codegen_kernels_separately(
+    args: &CodegenArgs,
+    sess: &Session,
+    llcx: &Context,
+    main_modules: &[(Vec<u8>, String)],
+    deps: &[(Vec<u8>, String)],
+    out_filename: &Path,
+) -> io::Result<()> {
+    let stem = out_filename
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .unwrap_or("kernels")
+        .to_string();
+    let merged = merge_cgus(
+        main_modules.iter().map(|(bc, _)| bc.clone()).collect(),
+        llcx,
+        stem.clone(),
+    );
+    let cstr = CString::new(stem.clone()).unwrap();
+    let parse = || unsafe {
+        LLVMRustParseBitcodeForLTO(llcx, merged.as_ptr(), merged.len(), cstr.as_ptr())
+            .expect("Failed to parse merged bitcode")
+    };
+
+    let module = parse();
+    let kernels = llvm::nvvm_annotations(module)
+        .into_iter()
+        .filter(|(_, kind, _)| *kind == b"kernel")
+        .map(|(kernel, _, _)| String::from_utf8_lossy(llvm::get_value_name(kernel)).into_owned())
+        .collect::<Vec<_>>();
+    unsafe { llvm::LLVMDisposeModule(module) };
+
+    for kernel in kernels {
+        let module = parse();
+        unsafe { keep_only_kernel(module, &kernel) };
+        let bitcode = ThinBuffer::new(module).data().to_vec();
+        unsafe { llvm::LLVMDisposeModule(module) };
+
+        let ptx = match crate::nvvm::codegen_bitcode_modules(
+            args,
+            sess,
+            vec![(bitcode, kernel.clone())],
+            deps.to_vec(),
+        ) {
+            Ok(bytes) => bytes,
+            Err(err) => sess.fatal(&format!("Failed to codegen kernel `{}`: {}", kernel, err)),
+        };
+        std::fs::write(
+            out_filename.with_file_name(format!("{}.{}.ptx", stem, kernel)),
+            ptx,
+        )?;
+    }
+    Ok(())
+}
+
+/// Generates PTX for `arch` instead of the target architecture into `<out_filename stem>.<sm_XX>.ptx`, for
+/// shipping PTX for several architectures.
+fn codegen_for_arch(
+    args: &CodegenArgs,
+    sess: &Session,
+    arch: NvvmArch,
+    main_modules: &[(Vec<u8>, String)],
+    deps: &[(Vec<u8>, String)],
+    out_filename: &Path,
+) -> io::Result<()> {
+    let mut args = args.clone();
+    args.nvvm_options
+        .retain(|opt| !matches!(opt, NvvmOption::Arch(_)));
+    args.nvvm_options.push(NvvmOption::Arch(arch));
+    // `compute_80` -> `sm_80`, like the `.target` of the PTX.
+    let target = arch.to_string().replace("compute_", "sm_");
+
+    let ptx = match crate::nvvm::codegen_bitcode_modules(
+        &args,
+        sess,
+        main_modules.to_vec(),
+        deps.to_vec(),
+    ) {
+        Ok(bytes) => bytes,
+        Err(err) => sess.fatal(&format!("Failed to codegen for {}: {}", target, err)),
+    };
+    std::fs::write(out_filename.with_extension(format!("{}.ptx", target)), ptx)
+}
+
+/// Removes the kernel annotations of every kernel except `kernel` and deletes those kernels, so libnvvm
+/// only emits `kernel` and the functions it uses.
+unsafe fn keep_only_kernel(llmod: &llvm::Module, kernel: &str) {
+    let removed = llvm::nvvm_annotations(llmod)
+        .into_iter()
+        .filter(|(val, kind, _)| {
+            *kind == b"kernel"
+                && llvm::LLVMIsAFunction(val).is_some()
+                && llvm::get_value_name(val) != kernel.as_bytes()
+        })
+        .map(|(val, _, _)| val)
+        .collect::<Vec<_>>();
+
+    // the nodes are kept alive by the context, so they can be added back as they are.
+    let nodes = llvm::nvvm_annotation_nodes(llmod);
+    llvm::LLVMRustEraseNamedMetadata(llmod, "nvvm.annotations\0".as_ptr().cast());
+    for node in nodes {
+        let annotated = llvm::md_node_operands(node).first().copied().flatten();
+        if annotated.map_or(false, |val| removed.contains(&val)) {
+            continue;
+        }
+        llvm::LLVMAddNamedMetadataOperand(llmod, "nvvm.annotations\0".as_ptr().cast(), node);
+    }
+
+    for func in removed {
+        if llvm::LLVMGetFirstUse(func).is_none() {
+            llvm::LLVMDeleteFunction(func);
+        } else {
+            llvm::LLVMRustSetLinkage(func, llvm::Linkage::InternalLinkage);
+        }
+    }
+}
+
+/// Writes the manifest of the kernels in the modules of this crate to `<out_filename>.kernels.json`, see
+/// the `kernel_manifest` module for its format.
+fn write_kernel_manifest(
+    llcx: &Context,
+    main_modules: &[(Vec<u8>, String)],
+    out_filename: &Path,
+) -> io::Result<()> {
+    let manifest = with_main_module(llcx, main_modules, crate::kernel_manifest::kernel_manifest);
+    std::fs::write(out_filename.with_extension("kernels.json"), manifest)
+}
+
+/// Writes the externally visible symbols of the modules of this crate to `<out_filename>.symbols.json`, see
+/// the `symbol_table` module for its format.
+fn write_symbol_table(
+    llcx: &Context,
+    main_modules: &[(Vec<u8>, String)],
+    out_filename: &Path,
+) -> io::Result<()> {
+    let symbols = with_main_module(llcx, main_modules, crate::symbol_table::symbol_table);
+    std::fs::write(out_filename.with_extension("symbols.json"), symbols)
+}
+
+/// Parses the module of this crate which is given to libnvvm (the main modules are linked into a single
+/// module before this is called) and calls `f` with it, the module is disposed of afterwards.
+fn with_main_module<R>(
+    llcx: &Context,
+    main_modules: &[(Vec<u8>, String)],
+    f: impl FnOnce(&llvm::Module) -> R,
+) -> R {
+    debug_assert_eq!(main_modules.len(), 1);
+    let (bitcode, name) = &main_modules[0];
+    let cstr = CString::new(name.as_str()).unwrap();
+    unsafe {
+        let module =
+            LLVMRustParseBitcodeForLTO(llcx, bitcode.as_ptr(), bitcode.len(), cstr.as_ptr())
+                .expect("Failed to parse the main module");
+        let res = f(module);
+        llvm::LLVMDisposeModule(module);
+        res
+    }
+}
+
+/// Links every module that would be given to libnvvm, except for libdevice, into a single bitcode module
+/// and writes it to `<out_filename>.device.bc`, so it can be linked with clang or other LLVM tools.
+/// Unlike libnvvm, this links in every function of the dependencies, not just the used ones.
+fn write_device_bitcode(
+    llcx: &Context,
+    main_modules: Vec<(Vec<u8>, String)>,
+    deps: Vec<(Vec<u8>, String)>,
+    out_filename: &Path,
+) -> io::Result<()> {
+    let name = out_filename
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .unwrap_or("device")
+        .to_string();
+    let modules = main_modules
+        .into_iter()
+        .chain(deps)
+        .map(|(bc, _)| bc)
+        .chain(std::iter::once(LIBINTRINSICS.to_vec()))
+        .collect();
+    let bitcode = merge_cgus(modules, llcx, name);
+    std::fs::write(out_filename.with_extension("device.bc"), bitcode)
+}
+
+/// Writes every module given to libnvvm into `out_dir`, along with a `nvvm_inputs.txt` file listing
+/// the modules in the order they are added to the nvvm program.
+fn save_nvvm_inputs(
+    out_dir: &Path,
+    main: &[(Vec<u8>, String)],
+    lazy: &[(Vec<u8>, String)],
+) -> io::Result<()> {
+    let mut list = String::new();
+    for (kind, modules) in [("main", main), ("lazy", lazy)] {
+        for (bc, name) in modules {
+            let path = out_dir.join(name).with_extension("bc");
+            std::fs::write(&path, bc)?;
+            list.push_str(&format!("{} {}\n", kind, path.display()));
+        }
+    }
+    std::fs::write(out_dir.join("nvvm_inputs.txt"), list)
 }
 
 /// Merges multiple codegen units into a single codegen unit. This is needed because
@@ -323,16 +589,65 @@ fn codegen_into_ptx_file(
 fn merge_cgus(cgus: Vec<Vec<u8>>, llcx: &Context, crate_name: String) -> Vec<u8> {
     let cstr = CString::new(crate_name.clone()).unwrap();
     let module = unsafe { create_module(llcx, &crate_name) };
-    for cgu in cgus {
-        unsafe {
-            let tmp = LLVMRustParseBitcodeForLTO(llcx, cgu.as_ptr(), cgu.len(), cstr.as_ptr())
-                .expect("Failed to parse CGU bitcode");
-            LLVMLinkModules2(module, tmp);
+    let cgus = cgus
+        .iter()
+        .map(|cgu| unsafe {
+            LLVMRustParseBitcodeForLTO(llcx, cgu.as_ptr(), cgu.len(), cstr.as_ptr())
+                .expect("Failed to parse CGU bitcode")
+        })
+        .collect::<Vec<_>>();
+
+    unsafe {
+        let shared = dedup_local_copies(&cgus);
+        for cgu in cgus {
+            LLVMLinkModules2(module, cgu);
+        }
+        for name in shared {
+            let cstr = CString::new(name).unwrap();
+            if let Some(func) = llvm::LLVMGetNamedFunction(module, cstr.as_ptr()) {
+                llvm::LLVMRustSetLinkage(func, llvm::Linkage::InternalLinkage);
+            }
         }
     }
 
-    let thin = ThinBuffer::new(module);
-    thin.data().to_vec()
+    let bitcode = ThinBuffer::new(module).data().to_vec();
+    unsafe { llvm::LLVMDisposeModule(module) };
+    bitcode
+}
+
+/// rustc copies `#[inline]` functions and generic instances which are only used locally into every CGU
+/// using them, with internal linkage. Linking the CGUs would keep every one of those copies (renamed to
+/// `name.1`, `name.2`, etc.), even though they are the same instance with the same symbol name. So the
+/// first definition of every duplicated internal function is made external for the link, and the others are
+/// made `available_externally`, which the linker drops in favor of the external definition.
+///
+/// Returns the names of the functions which must be made internal again after linking.
+unsafe fn dedup_local_copies(cgus: &[&llvm::Module]) -> Vec<Vec<u8>> {
+    let mut first = FxHashMap::<Vec<u8>, &llvm::Value>::default();
+    let mut shared = vec![];
+    for cgu in cgus {
+        for f in llvm::defined_functions(cgu) {
+            if llvm::LLVMRustGetLinkage(f) != llvm::Linkage::InternalLinkage {
+                continue;
+            }
+            let name = llvm::get_value_name(f).to_vec();
+            match first.get(&name) {
+                None => {
+                    first.insert(name, f);
+                }
+                // a function of the codegen which happens to have the same name, but is not the same.
+                Some(def) if llvm::LLVMTypeOf(def) != llvm::LLVMTypeOf(f) => {}
+                Some(def) => {
+                    if llvm::LLVMRustGetLinkage(def) == llvm::Linkage::InternalLinkage {
+                        llvm::LLVMRustSetLinkage(def, llvm::Linkage::ExternalLinkage);
+                        shared.push(name);
+                    }
+                    llvm::LLVMRustSetLinkage(f, llvm::Linkage::AvailableExternallyLinkage);
+                }
+            }
+        }
+    }
+    shared
 }
 
 fn create_archive(sess: &Session, files: &[&Path], metadata: &[u8], out_filename: &Path) {
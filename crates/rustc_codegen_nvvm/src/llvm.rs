@@ -136,6 +136,170 @@ pub(crate) fn set_value_name(value: &Value, name: &[u8]) {
     }
 }
 
+/// Gets the nodes of the `nvvm.annotations` named metadata, every node is a value followed by one or more
+/// `kind, operand` pairs, for example `!{kernel, !"kernel", i32 1, !"maxntidx", i32 256}`.
+pub(crate) fn nvvm_annotation_nodes(llmod: &Module) -> Vec<&Value> {
+    named_metadata_nodes(llmod, "nvvm.annotations\0")
+}
+
+/// Gets the nodes of the named metadata `name`, which must be nul-terminated. Empty if there is no such metadata.
+pub(crate) fn named_metadata_nodes<'a>(llmod: &'a Module, name: &str) -> Vec<&'a Value> {
+    assert!(
+        name.ends_with('\0'),
+        "metadata name `{}` is not nul-terminated",
+        name
+    );
+    unsafe {
+        let name = name.as_ptr().cast();
+        let count = LLVMGetNamedMetadataNumOperands(llmod, name) as usize;
+        let mut nodes = Vec::with_capacity(count);
+        LLVMGetNamedMetadataOperands(llmod, name, nodes.as_mut_ptr());
+        nodes.set_len(count);
+        nodes
+    }
+}
+
+/// Gets the operands of a metadata node, `None` for operands which are not values.
+pub(crate) fn md_node_operands(node: &Value) -> Vec<Option<&Value>> {
+    unsafe {
+        let count = LLVMGetMDNodeNumOperands(node) as usize;
+        let mut operands = Vec::with_capacity(count);
+        LLVMGetMDNodeOperands(node, operands.as_mut_ptr());
+        operands.set_len(count);
+        operands
+    }
+}
+
+/// Reads the entries of the `nvvm.annotations` named metadata as `(value, kind, operand)`,
+/// for example `(kernel, "kernel", i32 1)` or `(kernel, "maxntidx", i32 256)`. Nodes with multiple
+/// annotations yield one entry for every annotation. Malformed entries are skipped.
+pub(crate) fn nvvm_annotations(llmod: &Module) -> Vec<(&Value, &[u8], &Value)> {
+    nvvm_annotation_nodes(llmod)
+        .into_iter()
+        .flat_map(|node| {
+            let operands = md_node_operands(node);
+            let value = operands.first().copied().flatten();
+            operands
+                .get(1..)
+                .unwrap_or(&[])
+                .chunks_exact(2)
+                .filter_map(|pair| unsafe {
+                    let mut len = 0;
+                    let kind = LLVMGetMDString(pair[0]?, &mut len);
+                    if kind.is_null() {
+                        return None;
+                    }
+                    let kind = std::slice::from_raw_parts(kind.cast(), len as usize);
+                    Some((value?, kind, pair[1]?))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Adds a `(value, kind, i32 operand)` entry to the `nvvm.annotations` named metadata.
+pub(crate) fn add_nvvm_annotation(llmod: &Module, value: &Value, kind: &str, operand: u32) {
+    unsafe {
+        let llcx = LLVMGetModuleContext(llmod);
+        let kind = LLVMMDStringInContext(llcx, kind.as_ptr().cast(), kind.len() as u32);
+        let operand = LLVMConstInt(LLVMInt32TypeInContext(llcx), operand as u64, False);
+        let mdvals = &[value, kind, operand];
+        let node = LLVMMDNodeInContext(llcx, mdvals.as_ptr(), mdvals.len() as u32);
+        LLVMAddNamedMetadataOperand(llmod, "nvvm.annotations\0".as_ptr().cast(), node);
+    }
+}
+
+/// Gets the functions of the module which have a body.
+pub(crate) fn defined_functions(llmod: &Module) -> Vec<&Value> {
+    let mut funcs = vec![];
+    unsafe {
+        let mut func = LLVMGetFirstFunction(llmod);
+        while let Some(f) = func {
+            func = LLVMGetNextFunction(f);
+            if LLVMIsDeclaration(f) == False {
+                funcs.push(f);
+            }
+        }
+    }
+    funcs
+}
+
+/// Gets the instructions of a basic block in order. They are collected first, so the block may be changed
+/// while going through them.
+pub(crate) fn block_instructions(block: &BasicBlock) -> Vec<&Value> {
+    let mut insts = vec![];
+    unsafe {
+        let mut inst = LLVMGetFirstInstruction(block);
+        while let Some(i) = inst {
+            inst = LLVMGetNextInstruction(i);
+            insts.push(i);
+        }
+    }
+    insts
+}
+
+/// Gets the instructions of every basic block of a defined function in layout order.
+pub(crate) fn function_instructions(func: &Value) -> Vec<&Value> {
+    let mut insts = vec![];
+    unsafe {
+        let mut bb = Some(LLVMGetFirstBasicBlock(func));
+        while let Some(b) = bb {
+            insts.extend(block_instructions(b));
+            bb = LLVMGetNextBasicBlock(b);
+        }
+    }
+    insts
+}
+
+/// Gets the instructions of every function defined in the module.
+pub(crate) fn module_instructions(llmod: &Module) -> Vec<&Value> {
+    defined_functions(llmod)
+        .into_iter()
+        .flat_map(function_instructions)
+        .collect()
+}
+
+/// Prepares inline assembly of type `fn_ty` in the AT&T dialect, which is what the nvptx backend uses.
+pub(crate) fn inline_asm<'a>(
+    fn_ty: &'a Type,
+    asm: &str,
+    constraints: &str,
+    side_effects: bool,
+) -> &'a Value {
+    unsafe {
+        LLVMRustInlineAsm(
+            fn_ty,
+            asm.as_ptr().cast(),
+            asm.len(),
+            constraints.as_ptr().cast(),
+            constraints.len(),
+            side_effects as Bool,
+            False,
+            AsmDialect::Att,
+        )
+    }
+}
+
+/// Replaces the instruction `inst` with a call to `callee` (a function or [`inline_asm`]) with `args`,
+/// making the uses of `inst` use the result of the call instead. Returns the call.
+pub(crate) fn replace_with_call<'a>(
+    builder: &Builder<'a>,
+    inst: &'a Value,
+    callee: &'a Value,
+    args: &[&'a Value],
+) -> &'a Value {
+    unsafe {
+        LLVMPositionBuilderBefore(builder, inst);
+        let call = LLVMRustBuildCall(builder, callee, args.as_ptr(), args.len() as c_uint, None);
+        // instructions without uses may not produce a value of the same type, such as `red` for an `atomicrmw`.
+        if LLVMGetFirstUse(inst).is_some() {
+            LLVMReplaceAllUsesWith(inst, call);
+        }
+        LLVMInstructionEraseFromParent(inst);
+        call
+    }
+}
+
 pub fn last_error() -> Option<String> {
     unsafe {
         let cstr = LLVMRustGetLastError();
@@ -320,6 +484,7 @@ impl TypeKind {
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub(crate) enum MetadataType {
+    MD_prof = 2,
     MD_range = 4,
     MD_invariant_load = 6,
     MD_nontemporal = 9,
@@ -344,6 +509,72 @@ impl AsmDialect {
     }
 }
 
+/// LLVMAtomicRMWBinOp
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub(crate) enum AtomicRmwBinOp {
+    AtomicXchg = 0,
+    AtomicAdd = 1,
+    AtomicSub = 2,
+    AtomicAnd = 3,
+    AtomicNand = 4,
+    AtomicOr = 5,
+    AtomicXor = 6,
+    AtomicMax = 7,
+    AtomicMin = 8,
+    AtomicUMax = 9,
+    AtomicUMin = 10,
+}
+
+impl AtomicRmwBinOp {
+    pub fn from_generic(op: rustc_codegen_ssa::common::AtomicRmwBinOp) -> Self {
+        use rustc_codegen_ssa::common::AtomicRmwBinOp as Op;
+        match op {
+            Op::AtomicXchg => AtomicRmwBinOp::AtomicXchg,
+            Op::AtomicAdd => AtomicRmwBinOp::AtomicAdd,
+            Op::AtomicSub => AtomicRmwBinOp::AtomicSub,
+            Op::AtomicAnd => AtomicRmwBinOp::AtomicAnd,
+            Op::AtomicNand => AtomicRmwBinOp::AtomicNand,
+            Op::AtomicOr => AtomicRmwBinOp::AtomicOr,
+            Op::AtomicXor => AtomicRmwBinOp::AtomicXor,
+            Op::AtomicMax => AtomicRmwBinOp::AtomicMax,
+            Op::AtomicMin => AtomicRmwBinOp::AtomicMin,
+            Op::AtomicUMax => AtomicRmwBinOp::AtomicUMax,
+            Op::AtomicUMin => AtomicRmwBinOp::AtomicUMin,
+        }
+    }
+}
+
+/// LLVMAtomicOrdering
+#[derive(Copy, Clone)]
+#[repr(C)]
+#[allow(dead_code)] // Variants constructed by C++.
+pub(crate) enum AtomicOrdering {
+    NotAtomic = 0,
+    Unordered = 1,
+    Monotonic = 2,
+    // Consume = 3,  // Not specified yet.
+    Acquire = 4,
+    Release = 5,
+    AcquireRelease = 6,
+    SequentiallyConsistent = 7,
+}
+
+impl AtomicOrdering {
+    pub fn from_generic(ao: rustc_codegen_ssa::common::AtomicOrdering) -> Self {
+        use rustc_codegen_ssa::common::AtomicOrdering as Common;
+        match ao {
+            Common::NotAtomic => AtomicOrdering::NotAtomic,
+            Common::Unordered => AtomicOrdering::Unordered,
+            Common::Monotonic => AtomicOrdering::Monotonic,
+            Common::Acquire => AtomicOrdering::Acquire,
+            Common::Release => AtomicOrdering::Release,
+            Common::AcquireRelease => AtomicOrdering::AcquireRelease,
+            Common::SequentiallyConsistent => AtomicOrdering::SequentiallyConsistent,
+        }
+    }
+}
+
 /// LLVMRustDiagnosticKind
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -447,6 +678,12 @@ extern "C" {
 extern "C" {
     pub(crate) type BasicBlock;
 }
+extern "C" {
+    pub(crate) type Use;
+}
+extern "C" {
+    pub(crate) type TargetData;
+}
 #[repr(C)]
 pub(crate) struct Builder<'a> {
     _inv: InvariantOpaque<'a>,
@@ -647,6 +884,7 @@ extern "C" {
     pub(crate) fn LLVMInitializeNVPTXAsmPrinter();
     pub(crate) fn LLVMInitializePasses();
     pub(crate) fn LLVMRustSetLLVMOptions(Argc: c_int, Argv: *const *const c_char);
+    pub(crate) fn LLVMRustIsKnownLLVMOption(Name: *const c_char) -> bool;
 }
 
 // use rustc_codegen_nvvm_macros::trace_ffi_calls;
@@ -659,8 +897,45 @@ extern "C" {
         AddressSpace: c_uint,
     ) -> &'a Value;
     pub(crate) fn LLVMGetOperand(Val: &Value, Index: c_uint) -> &Value;
+    pub(crate) fn LLVMGetFirstUse(Val: &Value) -> Option<&Use>;
+    pub(crate) fn LLVMGetNextUse(U: &Use) -> Option<&Use>;
+    pub(crate) fn LLVMGetUser(U: &Use) -> &Value;
+    pub(crate) fn LLVMIsACallInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAAllocaInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAConstantExpr(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAConstantStruct(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAConstantDataSequential(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsConstantString(C: &Value) -> Bool;
+    pub(crate) fn LLVMGetAsString(C: &Value, Length: *mut size_t) -> *const c_char;
+    pub(crate) fn LLVMInstructionEraseFromParent(Inst: &Value);
+    pub(crate) fn LLVMGetFirstFunction(M: &Module) -> Option<&Value>;
+    pub(crate) fn LLVMGetNextFunction(Fn: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAFunction(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMGetNextBasicBlock(BB: &BasicBlock) -> Option<&BasicBlock>;
+    pub(crate) fn LLVMGetFirstInstruction(BB: &BasicBlock) -> Option<&Value>;
+    pub(crate) fn LLVMGetNextInstruction(Inst: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMGetCalledValue(Instr: &Value) -> &Value;
+    pub(crate) fn LLVMGetNamedMetadataNumOperands(M: &Module, Name: *const c_char) -> c_uint;
+    pub(crate) fn LLVMGetNamedMetadataOperands<'a>(
+        M: &'a Module,
+        Name: *const c_char,
+        Dest: *mut &'a Value,
+    );
+    pub(crate) fn LLVMGetMDNodeNumOperands(V: &Value) -> c_uint;
+    pub(crate) fn LLVMGetMDNodeOperands<'a>(V: &'a Value, Dest: *mut Option<&'a Value>);
+    pub(crate) fn LLVMGetMDString(V: &Value, Length: *mut c_uint) -> *const c_char;
+    pub(crate) fn LLVMIsAICmpInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMGetICmpPredicate(Inst: &Value) -> IntPredicate;
+    pub(crate) fn LLVMIsAFCmpInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMGetFCmpPredicate(Inst: &Value) -> c_uint;
+    pub(crate) fn LLVMIsAConstantFP(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMConstRealGetDouble(ConstantVal: &Value, LosesInfo: *mut Bool) -> f64;
+    pub(crate) fn LLVMPositionBuilderBefore<'a>(Builder: &Builder<'a>, Instr: &'a Value);
+    pub(crate) fn LLVMGetPointerAddressSpace(PointerTy: &Type) -> c_uint;
     pub(crate) fn LLVMIsABitCastInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAGetElementPtrInst(Val: &Value) -> Option<&Value>;
     pub(crate) fn LLVMIsASelectInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAAtomicRMWInst(Val: &Value) -> Option<&Value>;
     pub(crate) fn LLVMRustGetFunctionType(V: &Value) -> &Type;
     pub(crate) fn LLVMLinkModules2(Dest: &Module, Src: &Module) -> Bool;
     pub(crate) fn LLVMParseIRInContext<'ll, 'a, 'b>(
@@ -963,6 +1238,7 @@ extern "C" {
 
     pub(crate) fn LLVMRustRunFunctionPassManager(PM: &PassManager, M: &Module);
     pub(crate) fn LLVMRustAddAlwaysInlinePass(P: &PassManagerBuilder, AddLifetimes: bool);
+    pub(crate) fn LLVMRustCreateAlwaysInlinePass(AddLifetimes: bool) -> &'static mut Pass;
 
     pub(crate) fn LLVMRustAddBuilderLibraryInfo(
         PMB: &PassManagerBuilder,
@@ -1061,7 +1337,7 @@ extern "C" {
     pub(crate) fn LLVMGetReturnType(FunctionTy: &Type) -> &Type;
     pub(crate) fn LLVMGetParams(Fn: &Value, Params: *mut &Value);
     pub(crate) fn LLVMGetEntryBasicBlock(Fn: &Value) -> &BasicBlock;
-    pub(crate) fn LLVMGetNamedFunction(M: &Module, Name: *const c_char) -> &Value;
+    pub(crate) fn LLVMGetNamedFunction(M: &Module, Name: *const c_char) -> Option<&Value>;
     pub(crate) fn LLVMRustGetFunctionReturnType(V: &Value) -> &Type;
 
     pub(crate) fn LLVMSetTarget(M: &Module, Triple: *const c_char);
@@ -1075,8 +1351,12 @@ extern "C" {
         ModuleID: *const c_char,
         C: &Context,
     ) -> &Module;
+    pub(crate) fn LLVMDisposeModule(M: &Module);
 
     pub(crate) fn LLVMSetDataLayout(M: &Module, Triple: *const c_char);
+    pub(crate) fn LLVMGetModuleDataLayout(M: &Module) -> &TargetData;
+    pub(crate) fn LLVMABISizeOfType(TD: &TargetData, Ty: &Type) -> c_ulonglong;
+    pub(crate) fn LLVMABIAlignmentOfType(TD: &TargetData, Ty: &Type) -> c_uint;
 
     pub(crate) fn LLVMRustAppendModuleInlineAsm(M: &Module, Asm: *const c_char, AsmLen: size_t);
 
@@ -1143,6 +1423,7 @@ extern "C" {
 
     // Operations on constants of any type
     pub(crate) fn LLVMConstNull(Ty: &Type) -> &Value;
+    pub(crate) fn LLVMIsNull(Val: &Value) -> Bool;
     pub(crate) fn LLVMGetUndef(Ty: &Type) -> &Value;
 
     // Operations on metadata
@@ -1236,6 +1517,9 @@ extern "C" {
     pub(crate) fn LLVMSetInitializer<'a>(GlobalVar: &'a Value, ConstantVal: &'a Value);
     pub(crate) fn LLVMIsGlobalConstant(GlobalVar: &Value) -> Bool;
     pub(crate) fn LLVMSetGlobalConstant(GlobalVar: &Value, IsConstant: Bool);
+    pub(crate) fn LLVMRustEraseNamedMetadata(M: &Module, Name: *const c_char);
+    pub(crate) fn LLVMRustDisableLoopUnrolling(M: &Module);
+    pub(crate) fn LLVMDeleteFunction(Fn: &Value);
     pub(crate) fn LLVMRustGetNamedValue(
         M: &Module,
         Name: *const c_char,
@@ -1260,9 +1544,17 @@ extern "C" {
     pub(crate) fn LLVMIsAArgument(Val: &Value) -> Option<&Value>;
     pub(crate) fn LLVMCountParams(Fn: &Value) -> c_uint;
     pub(crate) fn LLVMGetParam(Fn: &Value, Index: c_uint) -> &Value;
+    pub(crate) fn LLVMGetParamParent(Inst: &Value) -> &Value;
 
     // Operations on basic blocks
     pub(crate) fn LLVMGetBasicBlockParent(BB: &BasicBlock) -> &Value;
+    pub(crate) fn LLVMGetInstructionParent(Inst: &Value) -> &BasicBlock;
+    pub(crate) fn LLVMGetBasicBlockTerminator(BB: &BasicBlock) -> Option<&Value>;
+    pub(crate) fn LLVMGetNumSuccessors(Term: &Value) -> c_uint;
+    pub(crate) fn LLVMGetSuccessor(Term: &Value, i: c_uint) -> &BasicBlock;
+    pub(crate) fn LLVMIsConditional(Branch: &Value) -> Bool;
+    pub(crate) fn LLVMGetCondition(Branch: &Value) -> &Value;
+    pub(crate) fn LLVMIsABranchInst(Val: &Value) -> Option<&Value>;
     pub(crate) fn LLVMAppendBasicBlockInContext<'a>(
         C: &'a Context,
         Fn: &'a Value,
@@ -1511,6 +1803,25 @@ extern "C" {
 
     pub(crate) fn LLVMBuildStore<'a>(B: &Builder<'a>, Val: &'a Value, Ptr: &'a Value) -> &'a Value;
 
+    pub(crate) fn LLVMBuildAtomicRMW<'a>(
+        B: &Builder<'a>,
+        Op: AtomicRmwBinOp,
+        LHS: &'a Value,
+        RHS: &'a Value,
+        Order: AtomicOrdering,
+        SingleThreaded: Bool,
+    ) -> &'a Value;
+    pub(crate) fn LLVMRustBuildAtomicCmpXchg<'a>(
+        B: &Builder<'a>,
+        LHS: &'a Value,
+        CMP: &'a Value,
+        RHS: &'a Value,
+        Order: AtomicOrdering,
+        FailureOrder: AtomicOrdering,
+        Weak: Bool,
+    ) -> &'a Value;
+    pub(crate) fn LLVMRustGetAtomicRMWBinOp(AtomicRMW: &Value) -> AtomicRmwBinOp;
+
     pub(crate) fn LLVMBuildGEP<'a>(
         B: &Builder<'a>,
         Pointer: &'a Value,
@@ -1611,6 +1922,12 @@ extern "C" {
         DestTy: &'a Type,
         Name: *const c_char,
     ) -> &'a Value;
+    pub(crate) fn LLVMBuildAddrSpaceCast<'a>(
+        B: &Builder<'a>,
+        Val: &'a Value,
+        DestTy: &'a Type,
+        Name: *const c_char,
+    ) -> &'a Value;
     pub(crate) fn LLVMRustBuildIntCast<'a>(
         B: &Builder<'a>,
         Val: &'a Value,
@@ -1788,6 +2105,8 @@ extern "C" {
         Constraints: *const c_char,
         ConstraintsLen: size_t,
     ) -> bool;
+    pub(crate) fn LLVMIsAInlineAsm(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMRustGetInlineAsmString(InlineAsm: &Value, Len: *mut size_t) -> *const c_char;
 
     pub(crate) fn LLVMIsAConstantInt(value_ref: &Value) -> Option<&ConstantInt>;
 
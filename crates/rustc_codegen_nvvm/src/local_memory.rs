@@ -0,0 +1,112 @@
+//! Estimation of the local memory (stack) which kernels use per thread.
+//!
+//! Allocas which are still around after optimization cannot be put in registers and end up in local memory,
+//! which is slow like global memory. This usually comes from large local arrays or from big structs which are
+//! passed by value and spilled to the stack by `ArgAbiExt::store`. ptxas only reports the stack frame size when
+//! run verbosely, so with `--local-memory-warning=BYTES` we warn about kernels which likely use more than that.
+//!
+//! The estimate of a function is the size of its static allocas plus the largest estimate of the functions it
+//! calls. Calls which are part of a cycle are ignored (see [`recursion`](crate::recursion)), and ptxas may
+//! still need more for spilled registers, so this is a lower bound.
+
+use std::collections::HashMap;
+
+use rustc_errors::Handler;
+
+use crate::llvm::{self, Module, TargetData, Value};
+use crate::recursion::{call_graph, fn_name, kernels};
+
+/// Warns about every kernel in the module whose estimated local memory usage per thread is more than `limit` bytes.
+pub(crate) fn check_local_memory(handler: &Handler, llmod: &Module, limit: u64) {
+    let kernels = unsafe { kernels(llmod) };
+    if kernels.is_empty() {
+        return;
+    }
+    let graph = unsafe { call_graph(llmod) };
+    let data_layout = unsafe { llvm::LLVMGetModuleDataLayout(llmod) };
+
+    let mut frames = HashMap::new();
+    for func in graph.keys() {
+        frames.insert(*func, unsafe { frame_size(data_layout, func) });
+    }
+
+    let mut marks = HashMap::new();
+    for kernel in kernels {
+        let (usage, _) = total_size(&graph, &frames, kernel, 0, &mut marks);
+        if usage > limit {
+            handler.warn(&format!(
+                "kernel `{}` uses an estimated {} bytes of local memory per thread, which is more than the limit of {} bytes, \
+                large local arrays or structs passed by value are stored in slow local memory",
+                fn_name(kernel),
+                usage,
+                limit
+            ));
+        }
+    }
+}
+
+/// The state of a function while computing the totals, functions without a mark have not been visited yet.
+#[derive(Clone, Copy)]
+enum Mark {
+    /// The function is on the call stack at this depth.
+    Visiting(usize),
+    /// The total of the function is known.
+    Done(u64),
+}
+
+/// The size of the frame of `func` plus the largest size of the functions it calls, and the lowest depth
+/// of a function on the call stack which was called while computing it (`usize::MAX` if there is none).
+fn total_size<'ll>(
+    graph: &HashMap<&'ll Value, Vec<&'ll Value>>,
+    frames: &HashMap<&'ll Value, u64>,
+    func: &'ll Value,
+    depth: usize,
+    marks: &mut HashMap<&'ll Value, Mark>,
+) -> (u64, usize) {
+    match marks.get(func) {
+        Some(&Mark::Done(total)) => return (total, usize::MAX),
+        // recursive calls do not add anything.
+        Some(&Mark::Visiting(depth)) => return (0, depth),
+        None => {}
+    }
+    // neither do declarations.
+    let frame = match frames.get(func) {
+        Some(&frame) => frame,
+        None => return (0, usize::MAX),
+    };
+
+    marks.insert(func, Mark::Visiting(depth));
+    let mut callees = 0;
+    let mut lowest = usize::MAX;
+    for callee in graph.get(func).into_iter().flatten() {
+        let (total, callee_lowest) = total_size(graph, frames, callee, depth + 1, marks);
+        callees = callees.max(total);
+        lowest = lowest.min(callee_lowest);
+    }
+
+    let total = frame + callees;
+    // if a function further up the stack was called, the total left out that function's frame because it was
+    // being visited, so it depends on which function of the cycle was visited first and is not cached.
+    if lowest >= depth {
+        marks.insert(func, Mark::Done(total));
+    } else {
+        marks.remove(func);
+    }
+    (total, lowest)
+}
+
+/// The size in bytes of every alloca with a constant amount of elements in `func`.
+unsafe fn frame_size(data_layout: &TargetData, func: &Value) -> u64 {
+    let mut size = 0;
+    for i in llvm::function_instructions(func) {
+        if llvm::LLVMIsAAllocaInst(i).is_some() {
+            // the allocated type is the pointee of the alloca, operand 0 is the amount of elements.
+            let ty = llvm::LLVMGetElementType(llvm::LLVMTypeOf(i));
+            if let Some(count) = llvm::LLVMIsAConstantInt(llvm::LLVMGetOperand(i, 0)) {
+                size += llvm::LLVMABISizeOfType(data_layout, ty)
+                    * llvm::LLVMConstIntGetZExtValue(count);
+            }
+        }
+    }
+    size
+}
@@ -1,18 +1,21 @@
-use crate::abi::FnAbiLlvmExt;
+use crate::abi::{ArgAttribute, ArgAttributes, ArgAttributesExt, FnAbi, FnAbiLlvmExt, PassMode};
 use crate::attributes;
 use crate::attributes::NvvmAttributes;
 use crate::consts::linkage_to_llvm;
 use crate::context::CodegenCx;
-use crate::llvm;
+use crate::kernel_manifest;
+use crate::launch_bounds;
+use crate::llvm::{self, AttributePlace, Value};
 use crate::ty::LayoutLlvmExt;
+use nvvm::NvvmArch;
 use rustc_codegen_ssa::traits::*;
 use rustc_hir::def_id::{DefId, LOCAL_CRATE};
 pub use rustc_middle::mir::mono::MonoItem;
 use rustc_middle::mir::mono::{Linkage, Visibility};
 use rustc_middle::ty::layout::FnAbiOf;
 use rustc_middle::ty::layout::LayoutOf;
-use rustc_middle::ty::{self, Instance, TypeFoldable};
-use rustc_target::abi::AddressSpace;
+use rustc_middle::ty::{self, Instance, Ty, TypeFoldable};
+use rustc_target::abi::{self, Primitive::Pointer};
 use tracing::trace;
 
 pub(crate) fn visibility_to_llvm(linkage: Visibility) -> llvm::Visibility {
@@ -35,9 +38,26 @@ impl<'ll, 'tcx> PreDefineMethods<'tcx> for CodegenCx<'ll, 'tcx> {
         let instance = Instance::mono(self.tcx, def_id);
         let ty = instance.ty(self.tcx, ty::ParamEnv::reveal_all());
         let llty = self.layout_of(ty).llvm_type(self);
+        let address_space = self.static_address_space(def_id);
+
+        // common statics are tentative definitions, the ones in this CGU share the same global (which is only a
+        // declaration until it is defined), and the LLVM linker merges them with the ones of other CGUs and crates.
+        if linkage == Linkage::Common {
+            if let Some(existing) = self.get_declared_value(symbol_name) {
+                if self.val_ty(existing) != self.type_ptr_to_ext(llty, address_space) {
+                    self.sess().span_fatal(
+                        self.tcx.def_span(def_id),
+                        &format!(
+                            "common symbol `{}` is defined with different types",
+                            symbol_name
+                        ),
+                    );
+                }
+            }
+        }
 
         let g = self
-            .define_global(symbol_name, llty, AddressSpace::DATA)
+            .define_global(symbol_name, llty, address_space)
             .unwrap_or_else(|| {
                 self.sess().span_fatal(
                     self.tcx.def_span(def_id),
@@ -100,10 +120,10 @@ impl<'ll, 'tcx> PreDefineMethods<'tcx> for CodegenCx<'ll, 'tcx> {
         unsafe {
             // if this function is marked as being a kernel, add it
             // to nvvm.annotations per the nvvm ir docs.
-            if nvvm_attrs.kernel {
-                trace!("Marking function `{:?}` as a kernel", symbol_name);
-                let kernel = llvm::LLVMMDStringInContext(self.llcx, "kernel".as_ptr().cast(), 6);
-                let mdvals = &[lldecl, kernel, self.const_i32(1)];
+            let annotate = |kind: &str, val: i32| {
+                let kind =
+                    llvm::LLVMMDStringInContext(self.llcx, kind.as_ptr().cast(), kind.len() as u32);
+                let mdvals = &[lldecl, kind, self.const_i32(val)];
                 let node =
                     llvm::LLVMMDNodeInContext(self.llcx, mdvals.as_ptr(), mdvals.len() as u32);
                 llvm::LLVMAddNamedMetadataOperand(
@@ -111,9 +131,211 @@ impl<'ll, 'tcx> PreDefineMethods<'tcx> for CodegenCx<'ll, 'tcx> {
                     "nvvm.annotations\0".as_ptr().cast(),
                     node,
                 );
+            };
+
+            if nvvm_attrs.kernel {
+                trace!("Marking function `{:?}` as a kernel", symbol_name);
+                annotate("kernel", 1);
+
+                // `.weak .entry`, so that a kernel with the same name in another object wins when linking.
+                // Not ODR, the whole point is that the other definition is different.
+                if nvvm_attrs.weak {
+                    llvm::LLVMRustSetLinkage(lldecl, linkage_to_llvm(Linkage::WeakAny));
+                }
+
+                if self.codegen_args.emit_kernel_manifest {
+                    kernel_manifest::record_kernel_params(self, lldecl, fn_abi);
+                }
+
+                // kernels must return void in PTX, a value returned through an sret pointer would make
+                // the kernel take a param the launcher never passes.
+                if !matches!(fn_abi.ret.mode, PassMode::Ignore) {
+                    self.tcx.sess.span_err(
+                        self.tcx.def_span(def_id),
+                        "kernels cannot return a value, write the result to a pointer param instead",
+                    );
+                }
+
+                // the equivalent of CUDA C's `__launch_bounds__(max_threads, min_blocks)`, like clang
+                // this only sets the x dimension, which ptxas treats as the total amount of threads.
+                if let Some((max_threads, min_blocks)) = nvvm_attrs.launch_bounds {
+                    annotate("maxntidx", max_threads as i32);
+                    if let Some(min_blocks) = min_blocks {
+                        annotate("minctasm", min_blocks as i32);
+                    }
+                }
+
+                if let Some(percent) = nvvm_attrs.occupancy {
+                    if nvvm_attrs.launch_bounds.is_some() {
+                        self.tcx.sess.span_err(
+                            self.tcx.def_span(def_id),
+                            "a kernel cannot have both launch bounds and an occupancy target",
+                        );
+                    } else {
+                        launch_bounds::record_occupancy_target(self, lldecl, percent);
+                    }
+                }
+
+                // the equivalent of CUDA C's `__cluster_dims__(x, y, z)`.
+                if let Some((x, y, z)) = nvvm_attrs.cluster_dims {
+                    if self.codegen_args.arch() < NvvmArch::Compute90 {
+                        self.tcx.sess.span_err(
+                            self.tcx.def_span(def_id),
+                            &format!(
+                                "thread block clusters require compute_90 or above, but the target is {}",
+                                self.codegen_args.arch()
+                            ),
+                        );
+                    }
+                    annotate("cluster_dim_x", x as i32);
+                    annotate("cluster_dim_y", y as i32);
+                    annotate("cluster_dim_z", z as i32);
+                }
+
+                // structs passed by value become `.param .align A .b8 name[S]` params. S is the size of the Rust
+                // type (the padding is part of the LLVM struct), but libnvvm takes A from the LLVM struct type, which
+                // is only aligned to its most aligned field. For `#[repr(align(N))]` structs the alignment must be
+                // given explicitly, otherwise the struct and every param after it would be at different offsets in
+                // the param buffer than the ones CUDA C's launch API and the kernel manifest use. The value of the
+                // annotation is the index of the param (starting at 1, 0 is the return value) in the high 16 bits and
+                // the alignment in the low 16 bits.
+                for (idx, align) in by_value_alignments(self, fn_abi, lldecl) {
+                    annotate("align", ((idx << 16) | align) as i32);
+                }
+
+                // the equivalent of CUDA C's `__restrict__` and `const __restrict__` params.
+                let indices = direct_param_indices(fn_abi);
+                let param_attrs = nvvm_attrs
+                    .restrict
+                    .iter()
+                    .map(|&idx| (idx, ArgAttribute::NoAlias))
+                    .chain(
+                        nvvm_attrs
+                            .readonly
+                            .iter()
+                            .map(|&idx| (idx, ArgAttribute::ReadOnly)),
+                    );
+                for (idx, attr) in param_attrs {
+                    let is_ptr = fn_abi.args.get(idx as usize).map_or(false, |arg| {
+                        matches!(arg.layout.abi, abi::Abi::Scalar(ref scalar) if scalar.value == Pointer)
+                    });
+                    // writes through interior mutability (like with `&AtomicU32`) are still writes.
+                    let interior_mut = attr == ArgAttribute::ReadOnly
+                        && fn_abi.args.get(idx as usize).map_or(false, |arg| {
+                            arg.layout
+                                .ty
+                                .builtin_deref(true)
+                                .map_or(false, |pointee| !self.type_is_freeze(pointee.ty))
+                        });
+                    match indices.get(idx as usize) {
+                        Some(_) if interior_mut => self.tcx.sess.span_err(
+                            self.tcx.def_span(def_id),
+                            &format!("`readonly` cannot be used on pointers to types with interior mutability, but param {} is one", idx),
+                        ),
+                        Some(&Some(param)) if is_ptr => {
+                            let mut attrs = ArgAttributes::new();
+                            attrs.set(attr);
+                            attrs.apply_attrs_to_llfn(AttributePlace::Argument(param), self, lldecl);
+                        }
+                        _ => self.tcx.sess.span_err(
+                            self.tcx.def_span(def_id),
+                            &format!("`restrict` and `readonly` can only be used on thin pointer params, but param {} is not one", idx),
+                        ),
+                    }
+                }
+            } else if nvvm_attrs.launch_bounds.is_some() || nvvm_attrs.occupancy.is_some() {
+                self.tcx.sess.span_err(
+                    self.tcx.def_span(def_id),
+                    "launch bounds and occupancy targets can only be set on kernels",
+                );
+            }
+            if !nvvm_attrs.kernel && nvvm_attrs.weak {
+                self.tcx.sess.span_err(
+                    self.tcx.def_span(def_id),
+                    "only kernels can be marked as `weak`",
+                );
+            }
+            if !nvvm_attrs.kernel && nvvm_attrs.cluster_dims.is_some() {
+                self.tcx.sess.span_err(
+                    self.tcx.def_span(def_id),
+                    "cluster dimensions can only be set on kernels",
+                );
+            }
+            if !nvvm_attrs.kernel
+                && (!nvvm_attrs.restrict.is_empty() || !nvvm_attrs.readonly.is_empty())
+            {
+                self.tcx.sess.span_err(
+                    self.tcx.def_span(def_id),
+                    "`restrict` and `readonly` params can only be used on kernels",
+                );
             }
         }
 
         self.instances.borrow_mut().insert(instance, lldecl);
     }
 }
+
+/// The LLVM param index (starting at 1) and alignment of every aggregate passed by value which is more
+/// aligned than its LLVM type.
+fn by_value_alignments<'ll>(
+    cx: &CodegenCx<'ll, '_>,
+    fn_abi: &FnAbi<'_, Ty<'_>>,
+    llfn: &'ll Value,
+) -> Vec<(u32, u32)> {
+    let data_layout = unsafe { llvm::LLVMGetModuleDataLayout(cx.llmod) };
+    fn_abi
+        .args
+        .iter()
+        .zip(direct_param_indices(fn_abi))
+        .filter_map(|(arg, idx)| {
+            let idx = idx?;
+            let aggregate = matches!(
+                arg.layout.abi,
+                abi::Abi::Aggregate { .. } | abi::Abi::ScalarPair(..)
+            );
+            if !aggregate || !matches!(arg.mode, PassMode::Direct(_)) {
+                return None;
+            }
+            let llty = unsafe { llvm::LLVMTypeOf(llvm::get_param(llfn, idx)) };
+            let llvm_align = unsafe { llvm::LLVMABIAlignmentOfType(data_layout, llty) } as u64;
+            let align = arg.layout.align.abi.bytes();
+            (align > llvm_align).then(|| (idx + 1, align as u32))
+        })
+        .collect()
+}
+
+/// The index of the LLVM param of every arg which is passed directly as a single param, `None`
+/// for args which are ignored or passed as multiple params.
+fn direct_param_indices(fn_abi: &FnAbi<'_, Ty<'_>>) -> Vec<Option<u32>> {
+    let mut indices = vec![];
+    // params are shifted by one if the return value is passed as a pointer.
+    let mut idx = match fn_abi.ret.mode {
+        PassMode::Indirect { .. } => 1,
+        _ => 0,
+    };
+    for arg in fn_abi.args.iter() {
+        if arg.pad.is_some() {
+            idx += 1;
+        }
+        match arg.mode {
+            PassMode::Ignore => indices.push(None),
+            PassMode::Direct(_) => {
+                indices.push(Some(idx));
+                idx += 1;
+            }
+            PassMode::Pair(..)
+            | PassMode::Indirect {
+                extra_attrs: Some(_),
+                ..
+            } => {
+                indices.push(None);
+                idx += 2;
+            }
+            _ => {
+                indices.push(None);
+                idx += 1;
+            }
+        }
+    }
+    indices
+}
@@ -2,6 +2,7 @@
 //!
 //! This module also includes a safe wrapper over the nvvm_sys module.
 
+use crate::context::CodegenArgs;
 use find_cuda_helper::find_cuda_root;
 use nvvm::*;
 use rustc_session::Session;
@@ -12,7 +13,7 @@ use std::path::Path;
 use tracing::debug;
 
 // see libintrinsics.ll on what this is.
-const LIBINTRINSICS: &[u8] = include_bytes!("../libintrinsics.bc");
+pub(crate) const LIBINTRINSICS: &[u8] = include_bytes!("../libintrinsics.bc");
 
 pub enum CodegenErr {
     Nvvm(NvvmError),
@@ -47,7 +48,7 @@ impl Display for CodegenErr {
 /// Note that this will implicitly try to find libdevice and add it, so don't do that
 /// step before this. It will fatal error if it cannot find it.
 pub fn codegen_bitcode_modules(
-    opts: &[NvvmOption],
+    args: &CodegenArgs,
     sess: &Session,
     main: Vec<(Vec<u8>, String)>,
     lazy: Vec<(Vec<u8>, String)>,
@@ -57,10 +58,21 @@ pub fn codegen_bitcode_modules(
     // make sure the nvvm version is high enough so users don't get confusing compilation errors.
     let (major, minor) = nvvm::ir_version();
 
-    if minor < 6 || major < 1 {
+    if (major, minor) < (1, 6) {
         sess.fatal("rustc_codegen_nvvm requires at least libnvvm 1.6 (CUDA 11.2)");
     }
 
+    // libnvvm only accepts IR of its own major version, and rejects IR newer than it with a cryptic
+    // error, so check that the requested version is something the installed libnvvm can handle.
+    if let Some((req_major, req_minor)) = args.required_nvvm_ir_version {
+        if req_major != major || req_minor > minor {
+            sess.fatal(&format!(
+                "NVVM IR version {}.{} was requested, but the installed libnvvm only supports NVVM IR {}.{}",
+                req_major, req_minor, major, minor
+            ));
+        }
+    }
+
     // first, create the nvvm program we will add modules to.
     let prog = NvvmProgram::new()?;
 
@@ -102,7 +114,7 @@ pub fn codegen_bitcode_modules(
         );
     }
 
-    let res = match prog.compile(opts) {
+    let res = match prog.compile(&args.nvvm_options) {
         Ok(b) => b,
         Err(_) => {
             // this should never happen, if it does, something went really bad or its a bug on libnvvm's end
@@ -0,0 +1,190 @@
+//! Reporting the resources every kernel uses after ptxas, with `--ptx-report`.
+//!
+//! The register estimate of [`launch_bounds`](crate::launch_bounds) is a lower bound computed before libnvvm, the
+//! actual register count, shared memory and register spills of a kernel are only known after ptxas compiled the
+//! PTX for the target architecture. Tuning the occupancy of a kernel needs those, so with `--ptx-report` we run
+//! `ptxas -v` on the final PTX file (and throw away the cubin), parse what it prints about every entry function,
+//! and report it along with the amount of PTX instructions of the kernel:
+//!
+//! ```text
+//! note: kernel `add`: 10 registers, 0 bytes of shared memory, 0 bytes of stack, 0 bytes of spill stores,
+//!       0 bytes of spill loads, 23 PTX instructions
+//! ```
+//!
+//! ptxas is looked up in the `bin` directory of the CUDA toolkit and then in the `PATH`, not finding it only warns.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use find_cuda_helper::find_cuda_root;
+use rustc_session::Session;
+
+use crate::context::CodegenArgs;
+
+/// What ptxas reports about a kernel.
+#[derive(Debug, Default, Clone, Copy)]
+struct KernelResources {
+    registers: u64,
+    shared_memory: u64,
+    stack: u64,
+    spill_stores: u64,
+    spill_loads: u64,
+}
+
+/// Runs ptxas on the PTX file at `ptx_path` and reports the resources of every kernel in it.
+pub(crate) fn report_ptx_resources(sess: &Session, args: &CodegenArgs, ptx_path: &Path) {
+    let ptx = match std::fs::read_to_string(ptx_path) {
+        Ok(ptx) => ptx,
+        Err(err) => {
+            sess.warn(&format!(
+                "could not read the PTX file for the PTX report: {}",
+                err
+            ));
+            return;
+        }
+    };
+
+    // `compute_90a` -> `sm_90a`.
+    let arch = args.arch().to_string().replace("compute_", "sm_");
+    let cubin = ptx_path.with_extension("report.cubin");
+    let output = Command::new(ptxas())
+        .arg("-v")
+        .arg(format!("-arch={}", arch))
+        .arg(ptx_path)
+        .arg("-o")
+        .arg(&cubin)
+        .output();
+    let _ = std::fs::remove_file(&cubin);
+
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            sess.warn(&format!(
+                "ptxas failed on the PTX file, the PTX report is not available:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+            return;
+        }
+        Err(err) => {
+            sess.warn(&format!(
+                "could not run ptxas, the PTX report is not available: {}",
+                err
+            ));
+            return;
+        }
+    };
+
+    // ptxas prints its info to stderr, but some versions use stdout.
+    let log = String::from_utf8_lossy(&output.stderr).into_owned()
+        + &String::from_utf8_lossy(&output.stdout);
+    let resources = parse_ptxas_log(&log);
+    let instructions = instruction_counts(&ptx);
+
+    let mut kernels = resources.into_iter().collect::<Vec<_>>();
+    kernels.sort_by(|a, b| a.0.cmp(&b.0));
+    for (kernel, res) in kernels {
+        sess.note_without_error(&format!(
+            "kernel `{}`: {} registers, {} bytes of shared memory, {} bytes of stack, {} bytes of spill stores, \
+            {} bytes of spill loads, {} PTX instructions",
+            kernel,
+            res.registers,
+            res.shared_memory,
+            res.stack,
+            res.spill_stores,
+            res.spill_loads,
+            instructions.get(kernel.as_str()).copied().unwrap_or(0)
+        ));
+    }
+}
+
+fn ptxas() -> PathBuf {
+    let name = format!("ptxas{}", std::env::consts::EXE_SUFFIX);
+    find_cuda_root()
+        .map(|root| root.join("bin").join(&name))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// Parses the output of `ptxas -v`, which looks like this for every entry function:
+///
+/// ```text
+/// ptxas info    : Compiling entry function 'add' for 'sm_61'
+/// ptxas info    : Function properties for add
+///     0 bytes stack frame, 0 bytes spill stores, 0 bytes spill loads
+/// ptxas info    : Used 10 registers, 1024 bytes smem, 344 bytes cmem[0]
+/// ```
+///
+/// Device functions which are not inlined get function properties too, but they are not entry functions.
+fn parse_ptxas_log(log: &str) -> HashMap<String, KernelResources> {
+    let mut kernels = HashMap::<String, KernelResources>::new();
+    // the entry function the `Used` lines are about, and the function the properties are about.
+    let mut entry = None;
+    let mut properties_of = None;
+    for line in log.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.split("Compiling entry function '").nth(1) {
+            let name = rest.split('\'').next().unwrap_or_default().to_string();
+            kernels.entry(name.clone()).or_default();
+            entry = Some(name);
+        } else if let Some(rest) = line.split("Function properties for ").nth(1) {
+            properties_of = Some(rest.trim().to_string());
+        } else if line.contains("bytes stack frame") {
+            if let Some(res) = properties_of.take().and_then(|name| kernels.get_mut(&name)) {
+                res.stack = number_before(line, "bytes stack frame").unwrap_or(0);
+                res.spill_stores = number_before(line, "bytes spill stores").unwrap_or(0);
+                res.spill_loads = number_before(line, "bytes spill loads").unwrap_or(0);
+            }
+        } else if line.contains("Used ") {
+            if let Some(res) = entry.take().and_then(|name| kernels.get_mut(&name)) {
+                res.registers = number_before(line, "registers").unwrap_or(0);
+                res.shared_memory = number_before(line, "bytes smem").unwrap_or(0);
+            }
+        }
+    }
+    kernels
+}
+
+/// The number in front of `what` in `line`, such as `10` in `Used 10 registers`.
+fn number_before(line: &str, what: &str) -> Option<u64> {
+    let before = line[..line.find(what)?].trim_end();
+    let start = before
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |idx| idx + 1);
+    before[start..].parse().ok()
+}
+
+/// The amount of instructions in the body of every `.entry` of `ptx`. Directives such as `.reg` and labels
+/// are not instructions.
+fn instruction_counts(ptx: &str) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+    let mut current = None;
+    let mut depth = 0;
+    let mut count = 0;
+    for line in ptx.lines() {
+        let line = line.trim();
+        let name = match current {
+            Some(name) => name,
+            None => {
+                if let Some(rest) = line.split(".entry ").nth(1) {
+                    current = rest.split(|c: char| c == '(' || c.is_whitespace()).next();
+                    depth = 0;
+                    count = 0;
+                }
+                continue;
+            }
+        };
+
+        let opened = line.matches('{').count() as i32;
+        let closed = line.matches('}').count() as i32;
+        if depth > 0 && line.ends_with(';') && !line.starts_with('.') && !line.starts_with("//") {
+            count += 1;
+        }
+        depth += opened - closed;
+        if depth == 0 && closed > 0 {
+            counts.insert(name, count);
+            current = None;
+        }
+    }
+    counts
+}
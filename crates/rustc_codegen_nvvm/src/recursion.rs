@@ -0,0 +1,120 @@
+//! Detection of recursion which is reachable from kernels.
+//!
+//! The stack of a GPU thread is small and its size must be known when launching a kernel, so
+//! recursion is almost always a mistake and overflows at runtime in ways that are hard to debug.
+//! libnvvm cannot compute the stack size of a recursive call chain and silently falls back to
+//! a default, so we warn about it instead (or error with `--deny-recursion`).
+//!
+//! This runs on the optimized module, so tail recursion that LLVM turned into a loop is not
+//! reported. Only calls inside of one module are seen, recursion across codegen units is not detected.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc_errors::Handler;
+
+use crate::llvm::{self, Module, Value};
+
+/// Warns (or errors if `deny` is set) about every call cycle which is reachable from a kernel in the module.
+pub(crate) fn check_kernel_recursion(handler: &Handler, llmod: &Module, deny: bool) {
+    let kernels = unsafe { kernels(llmod) };
+    if kernels.is_empty() {
+        return;
+    }
+    let graph = unsafe { call_graph(llmod) };
+
+    let mut visited = HashSet::new();
+    let mut reported = HashSet::new();
+    for kernel in kernels {
+        let mut cycles = vec![];
+        find_cycles(&graph, kernel, &mut vec![], &mut visited, &mut cycles);
+
+        for cycle in cycles {
+            // the same cycle may be reachable from multiple kernels, only report it once.
+            let mut key = cycle.iter().map(|f| *f as *const Value).collect::<Vec<_>>();
+            key.sort();
+            if !reported.insert(key) {
+                continue;
+            }
+
+            let path = cycle
+                .iter()
+                .chain(std::iter::once(&cycle[0]))
+                .map(|f| format!("`{}`", fn_name(f)))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            let msg = format!(
+                "kernel `{}` may recurse, which can overflow the stack: {}",
+                fn_name(kernel),
+                path
+            );
+            if deny {
+                handler.err(&msg);
+            } else {
+                handler.warn(&msg);
+            }
+        }
+    }
+}
+
+fn find_cycles<'ll>(
+    graph: &HashMap<&'ll Value, Vec<&'ll Value>>,
+    func: &'ll Value,
+    stack: &mut Vec<&'ll Value>,
+    visited: &mut HashSet<&'ll Value>,
+    cycles: &mut Vec<Vec<&'ll Value>>,
+) {
+    if let Some(pos) = stack.iter().position(|f| *f == func) {
+        cycles.push(stack[pos..].to_vec());
+        return;
+    }
+    if !visited.insert(func) {
+        return;
+    }
+    stack.push(func);
+    for callee in graph.get(func).into_iter().flatten() {
+        find_cycles(graph, callee, stack, visited, cycles);
+    }
+    stack.pop();
+}
+
+pub(crate) fn fn_name(func: &Value) -> String {
+    let name = String::from_utf8_lossy(llvm::get_value_name(func));
+    match rustc_demangle::try_demangle(&name) {
+        Ok(demangled) => format!("{:#}", demangled),
+        Err(_) => name.into_owned(),
+    }
+}
+
+/// Gets the functions marked as kernels in `nvvm.annotations`.
+pub(crate) unsafe fn kernels(llmod: &Module) -> Vec<&Value> {
+    llvm::nvvm_annotations(llmod)
+        .into_iter()
+        .filter(|(_, kind, _)| *kind == b"kernel")
+        .filter_map(|(func, _, _)| llvm::LLVMIsAFunction(func))
+        .collect()
+}
+
+/// Builds a map of every defined function in the module to the functions it directly calls.
+pub(crate) unsafe fn call_graph(llmod: &Module) -> HashMap<&Value, Vec<&Value>> {
+    let mut graph = HashMap::new();
+    for f in llvm::defined_functions(llmod) {
+        let mut callees = vec![];
+        for i in llvm::function_instructions(f) {
+            if llvm::LLVMIsACallInst(i).is_none() {
+                continue;
+            }
+            let mut callee = llvm::LLVMGetCalledValue(i);
+            // calls to functions with a different signature go through a bitcast.
+            while llvm::LLVMIsAConstantExpr(callee).is_some() {
+                callee = llvm::LLVMGetOperand(callee, 0);
+            }
+            if let Some(callee) = llvm::LLVMIsAFunction(callee) {
+                if !callees.contains(&callee) {
+                    callees.push(callee);
+                }
+            }
+        }
+        graph.insert(f, callees);
+    }
+    graph
+}
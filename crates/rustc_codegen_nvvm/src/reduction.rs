@@ -0,0 +1,122 @@
+//! Lowering of atomics whose result is unused to PTX's `red` instruction.
+//!
+//! `AtomicU32::fetch_add` on a counter whose old value is never read is an `atomicrmw` without uses, which
+//! libnvvm may still emit as an `atom`, which has to wait for the old value to come back from memory. `red`
+//! does the same operation without returning anything, so the thread can continue right away. Whether the
+//! result is used is only known after optimization, so this is done on the optimized module.
+//!
+//! Atomics are always emitted as monotonic with separate fences for stronger orderings, and `red` is
+//! relaxed too, so replacing them does not change their ordering.
+
+use tracing::trace;
+
+use crate::builder::unnamed;
+use crate::llvm::{self, AtomicRmwBinOp, False, Module, Value};
+use crate::recursion::kernels;
+
+/// Replaces every `atomicrmw` in the module whose result is unused with a `red` instruction.
+pub(crate) fn lower_unused_atomics(llmod: &Module) {
+    unsafe {
+        let atomics = unused_atomics(llmod);
+        if atomics.is_empty() {
+            return;
+        }
+
+        let llcx = llvm::LLVMGetModuleContext(llmod);
+        let builder = llvm::LLVMCreateBuilderInContext(llcx);
+        let void = llvm::LLVMVoidTypeInContext(llcx);
+        let data_layout = llvm::LLVMGetModuleDataLayout(llmod);
+        let kernels = kernels(llmod);
+        for (atomic, op) in atomics {
+            let mut ptr = llvm::LLVMGetOperand(atomic, 0);
+            let val = llvm::LLVMGetOperand(atomic, 1);
+            let space = match llvm::LLVMGetPointerAddressSpace(llvm::LLVMTypeOf(ptr)) {
+                0 if is_kernel_param(ptr, &kernels) => {
+                    let global_ty =
+                        llvm::LLVMPointerType(llvm::LLVMGetElementType(llvm::LLVMTypeOf(ptr)), 1);
+                    llvm::LLVMPositionBuilderBefore(builder, atomic);
+                    ptr = llvm::LLVMBuildAddrSpaceCast(builder, ptr, global_ty, unnamed());
+                    ".global"
+                }
+                0 => "",
+                1 => ".global",
+                3 => ".shared",
+                _ => continue,
+            };
+            let ptr_constraint = match llvm::LLVMABISizeOfType(data_layout, llvm::LLVMTypeOf(ptr)) {
+                8 => "l",
+                _ => "r",
+            };
+            let (op, ty, val_constraint) = op;
+            trace!("Lowering `{:?}` to `red{}.{}.{}`", atomic, space, op, ty);
+
+            let fn_ty = llvm::LLVMFunctionType(
+                void,
+                [llvm::LLVMTypeOf(ptr), llvm::LLVMTypeOf(val)].as_ptr(),
+                2,
+                False,
+            );
+            let asm = format!("red{}.{}.{} [$0], $1;", space, op, ty);
+            let constraints = format!("{},{},~{{memory}}", ptr_constraint, val_constraint);
+            let asm = llvm::inline_asm(fn_ty, &asm, &constraints, true);
+            llvm::replace_with_call(builder, atomic, asm, &[ptr, val]);
+        }
+        llvm::LLVMDisposeBuilder(builder);
+    }
+}
+
+/// Finds all the `atomicrmw`s without uses which have a `red` equivalent, along with the `red` operation,
+/// type and the inline asm constraint of the value.
+#[allow(clippy::type_complexity)]
+unsafe fn unused_atomics(
+    llmod: &Module,
+) -> Vec<(&Value, (&'static str, &'static str, &'static str))> {
+    llvm::module_instructions(llmod)
+        .into_iter()
+        .filter(|i| llvm::LLVMIsAAtomicRMWInst(i).is_some() && llvm::LLVMGetFirstUse(i).is_none())
+        .filter_map(|i| Some((i, red_op(i)?)))
+        .collect()
+}
+
+/// Whether `ptr` is a parameter of a kernel, or an offset or cast of one. Pointers given to kernels point to
+/// global memory, which libnvvm uses to infer the address space of accesses, but it cannot look into inline
+/// assembly, so we have to use `.global` ourselves.
+unsafe fn is_kernel_param(mut ptr: &Value, kernels: &[&Value]) -> bool {
+    loop {
+        if let Some(param) = llvm::LLVMIsAArgument(ptr) {
+            return kernels.contains(&llvm::LLVMGetParamParent(param));
+        }
+        if llvm::LLVMIsABitCastInst(ptr).is_none() && llvm::LLVMIsAGetElementPtrInst(ptr).is_none()
+        {
+            return false;
+        }
+        ptr = llvm::LLVMGetOperand(ptr, 0);
+    }
+}
+
+/// The `red` operation, type and value constraint of an `atomicrmw`, `None` for exchanges and subtractions
+/// which `red` does not have.
+unsafe fn red_op(atomic: &Value) -> Option<(&'static str, &'static str, &'static str)> {
+    let val_ty = llvm::LLVMTypeOf(llvm::LLVMGetOperand(atomic, 1));
+    let is_64 = match llvm::LLVMGetIntTypeWidth(val_ty) {
+        32 => false,
+        64 => true,
+        _ => return None,
+    };
+    let (op, ty32, ty64) = match llvm::LLVMRustGetAtomicRMWBinOp(atomic) {
+        AtomicRmwBinOp::AtomicAdd => ("add", "u32", "u64"),
+        AtomicRmwBinOp::AtomicAnd => ("and", "b32", "b64"),
+        AtomicRmwBinOp::AtomicOr => ("or", "b32", "b64"),
+        AtomicRmwBinOp::AtomicXor => ("xor", "b32", "b64"),
+        AtomicRmwBinOp::AtomicMax => ("max", "s32", "s64"),
+        AtomicRmwBinOp::AtomicMin => ("min", "s32", "s64"),
+        AtomicRmwBinOp::AtomicUMax => ("max", "u32", "u64"),
+        AtomicRmwBinOp::AtomicUMin => ("min", "u32", "u64"),
+        _ => return None,
+    };
+    Some(if is_64 {
+        (op, ty64, "l")
+    } else {
+        (op, ty32, "r")
+    })
+}
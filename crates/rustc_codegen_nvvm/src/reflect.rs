@@ -0,0 +1,92 @@
+//! Resolution of `__nvvm_reflect` calls.
+//!
+//! `__nvvm_reflect("name")` is the mechanism libdevice (and CUDA C++ code) uses to query settings such as
+//! whether denormals are flushed to zero. libnvvm resolves these calls for libdevice when it links it in,
+//! but calls in our own modules are resolved here using the same options that are given to libnvvm, so
+//! that our optimization passes can fold the constant and delete the branches which are never taken.
+
+use std::ffi::{CStr, CString};
+
+use libc::size_t;
+use nvvm::NvvmOption;
+use tracing::trace;
+
+use crate::context::CodegenArgs;
+use crate::llvm::{self, False, Module, Value};
+
+const REFLECT_FN: &str = "__nvvm_reflect";
+
+/// Replaces every call to `__nvvm_reflect` in the module with the value of the queried setting.
+/// Unknown reflect names become `0`, which matches what libnvvm does. Calls whose argument is not
+/// a constant string (yet) are left for libnvvm to resolve.
+pub(crate) fn resolve_nvvm_reflect(llmod: &Module, args: &CodegenArgs) {
+    unsafe {
+        // the wrapper reads the name until a nul, so the name must be nul-terminated.
+        let name = CString::new(REFLECT_FN).unwrap();
+        let reflect = match llvm::LLVMRustGetNamedValue(llmod, name.as_ptr(), REFLECT_FN.len()) {
+            Some(reflect) => reflect,
+            None => return,
+        };
+
+        for call in reflect_calls(reflect) {
+            // leave anything we cannot make sense of to libnvvm.
+            let name = match reflect_arg_name(llvm::LLVMGetOperand(call, 0)) {
+                Some(name) => name,
+                None => continue,
+            };
+            let value = match name.as_str() {
+                "__CUDA_FTZ" => args.nvvm_options.contains(&NvvmOption::Ftz) as u64,
+                "__CUDA_PREC_DIV" => !args.nvvm_options.contains(&NvvmOption::FastDiv) as u64,
+                "__CUDA_PREC_SQRT" => !args.nvvm_options.contains(&NvvmOption::FastSqrt) as u64,
+                "__CUDA_ARCH" => args.arch().capability() as u64 * 10,
+                _ => 0,
+            };
+            trace!("Resolving `__nvvm_reflect(\"{}\")` to `{}`", name, value);
+
+            let value = llvm::LLVMConstInt(llvm::LLVMTypeOf(call), value, False);
+            llvm::LLVMReplaceAllUsesWith(call, value);
+            llvm::LLVMInstructionEraseFromParent(call);
+        }
+    }
+}
+
+/// Collects all the calls to the reflect function, including calls through a bitcast of it, which is
+/// what we get if the function was declared with multiple different signatures.
+unsafe fn reflect_calls(val: &Value) -> Vec<&Value> {
+    let mut calls = vec![];
+    let mut use_ = llvm::LLVMGetFirstUse(val);
+    while let Some(u) = use_ {
+        let user = llvm::LLVMGetUser(u);
+        if llvm::LLVMIsACallInst(user).is_some() {
+            calls.push(user);
+        } else if llvm::LLVMIsAConstantExpr(user).is_some() {
+            calls.extend(reflect_calls(user));
+        }
+        use_ = llvm::LLVMGetNextUse(u);
+    }
+    calls
+}
+
+/// Extracts the name given to a reflect call, the argument must be a (possibly casted) pointer
+/// to a constant nul-terminated string.
+unsafe fn reflect_arg_name(mut arg: &Value) -> Option<String> {
+    while llvm::LLVMIsAConstantExpr(arg).is_some() {
+        arg = llvm::LLVMGetOperand(arg, 0);
+    }
+    llvm::LLVMIsAGlobalVariable(arg)?;
+    let mut init = llvm::LLVMGetInitializer(arg)?;
+    // rustc allocations are emitted as packed structs of their chunks.
+    if llvm::LLVMIsAConstantStruct(init).is_some() {
+        init = llvm::LLVMGetOperand(init, 0);
+    }
+    llvm::LLVMIsAConstantDataSequential(init)?;
+    if llvm::LLVMIsConstantString(init) == False {
+        return None;
+    }
+
+    let mut len: size_t = 0;
+    let ptr = llvm::LLVMGetAsString(init, &mut len);
+    let bytes = std::slice::from_raw_parts(ptr.cast::<u8>(), len);
+    let name = CStr::from_bytes_with_nul(bytes).ok()?;
+    name.to_str().ok().map(ToString::to_string)
+}
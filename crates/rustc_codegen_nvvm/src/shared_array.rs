@@ -0,0 +1,151 @@
+//! Shared memory arrays sized by the generic params of the function using them, `cuda_std::shared_array!`.
+//!
+//! Statics cannot be placed in shared memory, and could not use the generic params of the function they are declared
+//! in anyway. Instead `shared_array!` calls `cuda_std::shared::shared_array::<Marker, A>()` with a `Marker` type which
+//! is unique to the call site, and instead of codegenning the body of that function we define it to return a `.shared`
+//! global of type `A`. Every instantiation with a different `A` (such as a different const generic tile size)
+//! therefore gets its own correctly sized global, and every call site its own memory. `Marker` does not depend on the
+//! generic params of the function around the call site, so instantiations of it with the same `A` share the global.
+//!
+//! Static shared memory is limited to 48 KB per thread block on every architecture, anything above that must be
+//! allocated dynamically when launching the kernel, so we also error about kernels which statically use more.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc_codegen_ssa::traits::BuilderMethods;
+use rustc_errors::Handler;
+use rustc_middle::ty::layout::{FnAbiOf, LayoutOf};
+use rustc_middle::ty::{self, Instance};
+use rustc_target::abi::AddressSpace;
+
+use crate::attributes::NvvmAttributes;
+use crate::builder::Builder;
+use crate::context::CodegenCx;
+use crate::llvm::{self, AttributePlace::Function, Module, Value};
+use crate::recursion::{call_graph, fn_name, kernels};
+use crate::ty::LayoutLlvmExt;
+
+const SHARED_ADDRESS_SPACE: AddressSpace = AddressSpace(3);
+
+/// The most static shared memory a thread block can use.
+const MAX_STATIC_SHARED_MEMORY: u64 = 48 * 1024;
+
+/// Whether `instance` is `cuda_std::shared::shared_array`, whose body is defined by [`define_shared_array`].
+pub(crate) fn is_shared_array<'tcx>(cx: &CodegenCx<'_, 'tcx>, instance: Instance<'tcx>) -> bool {
+    NvvmAttributes::parse(cx, cx.tcx.get_attrs(instance.def_id())).shared_array
+}
+
+/// Defines the predefined `instance` of `shared_array::<Marker, A>` to return a pointer to a new `.shared`
+/// global of type `A`.
+pub(crate) fn define_shared_array<'ll, 'tcx>(cx: &CodegenCx<'ll, 'tcx>, instance: Instance<'tcx>) {
+    let llfn = cx.get_fn(instance);
+    let layout = cx.layout_of(instance.substs.type_at(1));
+    let llty = layout.llvm_type(cx);
+    let name = format!("{}_shared", cx.tcx.symbol_name(instance).name);
+    let ret_ty = cx
+        .fn_abi_of_instance(instance, ty::List::empty())
+        .ret
+        .layout
+        .immediate_llvm_type(cx);
+
+    unsafe {
+        // shared memory cannot be initialized, every thread block starts out with garbage.
+        let global = cx.declare_global(&name, llty, SHARED_ADDRESS_SPACE);
+        llvm::LLVMSetInitializer(global, llvm::LLVMGetUndef(llty));
+        llvm::LLVMRustSetLinkage(global, llvm::Linkage::InternalLinkage);
+        llvm::LLVMSetAlignment(global, layout.align.abi.bytes() as u32);
+
+        // the function is `#[inline(never)]` so that MIR inlining never sees its body, but it should be inlined
+        // now that it only returns a constant.
+        llvm::Attribute::NoInline.unapply_llfn(Function, llfn);
+        llvm::Attribute::AlwaysInline.apply_llfn(Function, llfn);
+
+        let llbb = Builder::append_block(cx, llfn, "start");
+        let mut bx = Builder::build(cx, llbb);
+        bx.ret(llvm::LLVMConstPointerCast(global, ret_ty));
+    }
+}
+
+/// Errors about every kernel in the module which statically uses more shared memory than a thread block can have.
+/// Only the shared globals used by functions in this module are known, so this is a lower bound.
+pub(crate) fn check_shared_memory(handler: &Handler, llmod: &Module) {
+    let kernels = unsafe { kernels(llmod) };
+    if kernels.is_empty() {
+        return;
+    }
+    let graph = unsafe { call_graph(llmod) };
+    let data_layout = unsafe { llvm::LLVMGetModuleDataLayout(llmod) };
+
+    // function -> the shared globals it uses directly, and their sizes.
+    let mut uses = HashMap::<&Value, Vec<&Value>>::new();
+    let mut sizes = HashMap::new();
+    unsafe {
+        let mut global = llvm::LLVMGetFirstGlobal(llmod);
+        while let Some(g) = global {
+            global = llvm::LLVMGetNextGlobal(g);
+            let ty = llvm::LLVMTypeOf(g);
+            if llvm::LLVMGetPointerAddressSpace(ty) != SHARED_ADDRESS_SPACE.0 {
+                continue;
+            }
+            sizes.insert(
+                g,
+                llvm::LLVMABISizeOfType(data_layout, llvm::LLVMGetElementType(ty)),
+            );
+            for func in using_functions(g) {
+                uses.entry(func).or_default().push(g);
+            }
+        }
+    }
+    if sizes.is_empty() {
+        return;
+    }
+
+    for kernel in kernels {
+        let mut globals = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![kernel];
+        while let Some(func) = stack.pop() {
+            if !visited.insert(func) {
+                continue;
+            }
+            globals.extend(uses.get(func).into_iter().flatten().copied());
+            stack.extend(graph.get(func).into_iter().flatten().copied());
+        }
+
+        let total = globals.iter().map(|g| sizes[g]).sum::<u64>();
+        if total > MAX_STATIC_SHARED_MEMORY {
+            handler
+                .struct_err(&format!(
+                    "kernel `{}` uses {} bytes of static shared memory, but a thread block can only have {} bytes",
+                    fn_name(kernel),
+                    total,
+                    MAX_STATIC_SHARED_MEMORY
+                ))
+                .help("use dynamic shared memory for larger buffers, it is given when launching the kernel")
+                .emit();
+        }
+    }
+}
+
+/// The functions which use `value` in one of their instructions, including through constant expressions.
+unsafe fn using_functions(value: &Value) -> Vec<&Value> {
+    let mut funcs = vec![];
+    let mut use_ = llvm::LLVMGetFirstUse(value);
+    while let Some(u) = use_ {
+        use_ = llvm::LLVMGetNextUse(u);
+        let user = llvm::LLVMGetUser(u);
+        if let Some(inst) = llvm::LLVMIsAInstruction(user) {
+            let func = llvm::LLVMGetBasicBlockParent(llvm::LLVMGetInstructionParent(inst));
+            if !funcs.contains(&func) {
+                funcs.push(func);
+            }
+        } else if llvm::LLVMIsAConstantExpr(user).is_some() {
+            for func in using_functions(user) {
+                if !funcs.contains(&func) {
+                    funcs.push(func);
+                }
+            }
+        }
+    }
+    funcs
+}
@@ -0,0 +1,106 @@
+//! A table of the externally visible symbols of a crate, emitted with `--emit-symbol-table`.
+//!
+//! Host code using the driver API resolves kernels and globals by their name in the PTX with
+//! `cuModuleGetFunction` and `cuModuleGetGlobal`. Kernels are `#[no_mangle]`, but other device functions and
+//! statics have mangled names which are not stable across compiler versions, so we list every symbol of the
+//! crate which libnvvm makes visible to other modules in `<output>.symbols.json`:
+//!
+//! ```json
+//! {
+//!   "symbols": [
+//!     { "name": "add", "demangled": "add", "kind": "kernel", "linkage": "visible" },
+//!     { "name": "_ZN4crate6helper17h0123456789abcdefE", "demangled": "crate::helper", "kind": "function", "linkage": "visible" },
+//!     { "name": "COUNTER", "demangled": "COUNTER", "kind": "global", "linkage": "visible", "address_space": 1, "size": 4 }
+//!   ]
+//! }
+//! ```
+//!
+//! `linkage` is the PTX linking directive of the symbol, `visible`, `weak` or `common` (see
+//! [`linkage_to_llvm`](crate::consts::linkage_to_llvm)), the symbols with internal linkage have no directive
+//! and are not listed. `address_space` is the NVVM address space of a global, `1` is global memory and `4` is
+//! constant memory. Only the symbols of the crate itself are listed, functions of dependencies are only linked
+//! in by libnvvm if they are used.
+
+use crate::kernel_manifest::escape;
+use crate::llvm::{self, Linkage, Module, Value};
+
+/// Builds the symbol table of every visible function and global defined in `llmod`.
+pub(crate) fn symbol_table(llmod: &Module) -> String {
+    let kernels = llvm::nvvm_annotations(llmod)
+        .into_iter()
+        .filter(|(_, kind, _)| *kind == b"kernel")
+        .map(|(func, _, _)| func)
+        .collect::<Vec<_>>();
+
+    let mut symbols = vec![];
+    unsafe {
+        let mut func = llvm::LLVMGetFirstFunction(llmod);
+        while let Some(f) = func {
+            func = llvm::LLVMGetNextFunction(f);
+            if let Some(linkage) = directive(f) {
+                let kind = if kernels.contains(&f) {
+                    "kernel"
+                } else {
+                    "function"
+                };
+                symbols.push(format!(
+                    r#"    {{ {}, "kind": "{}", "linkage": "{}" }}"#,
+                    names(f),
+                    kind,
+                    linkage
+                ));
+            }
+        }
+
+        let data_layout = llvm::LLVMGetModuleDataLayout(llmod);
+        let mut global = llvm::LLVMGetFirstGlobal(llmod);
+        while let Some(g) = global {
+            global = llvm::LLVMGetNextGlobal(g);
+            if let Some(linkage) = directive(g) {
+                let ty = llvm::LLVMTypeOf(g);
+                symbols.push(format!(
+                    r#"    {{ {}, "kind": "global", "linkage": "{}", "address_space": {}, "size": {} }}"#,
+                    names(g),
+                    linkage,
+                    llvm::LLVMGetPointerAddressSpace(ty),
+                    llvm::LLVMABISizeOfType(data_layout, llvm::LLVMGetElementType(ty))
+                ));
+            }
+        }
+    }
+
+    format!("{{\n  \"symbols\": [\n{}\n  ]\n}}\n", symbols.join(",\n"))
+}
+
+/// The PTX linking directive libnvvm gives a definition, `None` for declarations and symbols which are only
+/// visible inside of the module. `llvm.` globals such as `llvm.used` are never emitted.
+unsafe fn directive(val: &Value) -> Option<&'static str> {
+    if llvm::LLVMIsDeclaration(val) != llvm::False
+        || llvm::get_value_name(val).starts_with(b"llvm.")
+    {
+        return None;
+    }
+    match llvm::LLVMRustGetLinkage(val) {
+        Linkage::ExternalLinkage => Some("visible"),
+        Linkage::WeakAnyLinkage
+        | Linkage::WeakODRLinkage
+        | Linkage::LinkOnceAnyLinkage
+        | Linkage::LinkOnceODRLinkage => Some("weak"),
+        Linkage::CommonLinkage => Some("common"),
+        _ => None,
+    }
+}
+
+/// The `name` and `demangled` fields of a symbol.
+fn names(val: &Value) -> String {
+    let name = String::from_utf8_lossy(llvm::get_value_name(val));
+    let demangled = match rustc_demangle::try_demangle(&name) {
+        Ok(demangled) => format!("{:#}", demangled),
+        Err(_) => name.to_string(),
+    };
+    format!(
+        r#""name": "{}", "demangled": "{}""#,
+        escape(&name),
+        escape(&demangled)
+    )
+}
@@ -1,4 +1,5 @@
 use crate::abi::{FnAbiLlvmExt, LlvmType};
+use crate::attributes::NvvmAttributes;
 use crate::context::CodegenCx;
 use crate::llvm::{self, Bool, False, True, Type, Value};
 use crate::rustc_target::abi::TyAbiInterface;
@@ -329,6 +330,11 @@ impl<'tcx> LayoutLlvmExt<'tcx> for TyAndLayout<'tcx> {
                 ty::FnPtr(sig) => {
                     cx.fn_ptr_backend_type(cx.fn_abi_of_fn_ptr(sig, ty::List::empty()))
                 }
+                ty::Adt(..) if address_space_of(cx, self.ty).is_some() => {
+                    let address_space = address_space_of(cx, self.ty).unwrap();
+                    let pointee = address_space_pointee(cx, *self);
+                    cx.type_ptr_to_ext(cx.layout_of(pointee).llvm_type(cx), address_space)
+                }
                 _ => self.scalar_llvm_type_at(cx, scalar, Size::ZERO),
             };
             cx.scalar_lltypes.borrow_mut().insert(self.ty, llty);
@@ -405,6 +411,12 @@ impl<'tcx> LayoutLlvmExt<'tcx> for TyAndLayout<'tcx> {
                     } else {
                         (cx.type_i8(), AddressSpace::DATA)
                     };
+                // pointer wrappers marked with `#[address_space(N)]` are pointers in that address space.
+                let address_space = if offset == Size::ZERO {
+                    address_space_of(cx, self.ty).unwrap_or(address_space)
+                } else {
+                    address_space
+                };
                 cx.type_ptr_to_ext(pointee, address_space)
             }
         }
@@ -490,6 +502,42 @@ impl<'tcx> LayoutLlvmExt<'tcx> for TyAndLayout<'tcx> {
     }
 }
 
+/// Returns the address space of a pointer wrapper struct marked with `#[address_space(N)]`, or `None`
+/// if `ty` is not such a struct.
+pub(crate) fn address_space_of<'tcx>(
+    cx: &CodegenCx<'_, 'tcx>,
+    ty: Ty<'tcx>,
+) -> Option<AddressSpace> {
+    let def = match ty.kind() {
+        ty::Adt(def, _) => def,
+        _ => return None,
+    };
+    let attrs = cx.tcx.get_attrs(def.did);
+    let addrspace = NvvmAttributes::parse(cx, attrs).addrspace?;
+    if !def.is_struct() || !def.repr.transparent() {
+        cx.tcx.sess.span_fatal(
+            cx.tcx.def_span(def.did),
+            "`#[address_space]` can only be used on `#[repr(transparent)]` structs containing a raw pointer",
+        );
+    }
+    Some(AddressSpace(addrspace))
+}
+
+/// Gets the type pointed to by the raw pointer inside of a `#[address_space(N)]` pointer wrapper.
+fn address_space_pointee<'tcx>(cx: &CodegenCx<'_, 'tcx>, layout: TyAndLayout<'tcx>) -> Ty<'tcx> {
+    (0..layout.fields.count())
+        .find_map(|i| match layout.field(cx, i).ty.kind() {
+            ty::RawPtr(ty::TypeAndMut { ty, .. }) => Some(*ty),
+            _ => None,
+        })
+        .unwrap_or_else(|| {
+            cx.tcx.sess.fatal(&format!(
+                "`#[address_space]` struct `{}` must contain a raw pointer",
+                layout.ty
+            ))
+        })
+}
+
 fn uncached_llvm_type<'a, 'tcx>(
     cx: &CodegenCx<'a, 'tcx>,
     layout: TyAndLayout<'tcx>,
@@ -0,0 +1,147 @@
+//! Detection of `std` functionality which cannot work on the GPU being reachable from kernels.
+//!
+//! Things like file IO, threads or the environment need an operating system, and code using them
+//! fails deep inside of codegen (thread locals are unsupported) or in libnvvm, with errors which do not
+//! say which kernel is the problem. So before codegen we walk the calls of the MIR of every kernel in the
+//! codegen unit, and error with the call chain if it reaches one of those `std` modules.
+//!
+//! Only direct calls are followed, calls through function pointers and trait objects are not seen.
+
+use std::collections::{HashMap, VecDeque};
+
+use rustc_middle::mir::mono::{CodegenUnit, MonoItem};
+use rustc_middle::mir::TerminatorKind;
+use rustc_middle::ty::{Instance, InstanceDef, ParamEnv, TyCtxt, TyKind};
+use rustc_span::def_id::DefId;
+use rustc_span::{sym, Symbol};
+
+/// The modules of `std` which need an operating system.
+const UNSUPPORTED_MODULES: &[&str] = &[
+    "std::env::",
+    "std::fs::",
+    "std::io::stdio::",
+    "std::net::",
+    "std::process::",
+    "std::sys::",
+    "std::sys_common::",
+    "std::thread::",
+];
+
+/// Errors about every unsupported `std` function reachable from a kernel in `cgu`.
+pub(crate) fn check_unsupported_std<'tcx>(tcx: TyCtxt<'tcx>, cgu: &CodegenUnit<'tcx>) {
+    let mut errored = false;
+    for (kernel, _) in cgu.items_in_deterministic_order(tcx) {
+        let kernel = match kernel {
+            MonoItem::Fn(instance) if is_kernel(tcx, instance.def_id()) => instance,
+            _ => continue,
+        };
+
+        for chain in unsupported_calls(tcx, kernel) {
+            let path = chain
+                .iter()
+                .map(|instance| format!("`{}`", tcx.def_path_str(instance.def_id())))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            tcx.sess
+                .struct_span_err(
+                    tcx.def_span(kernel.def_id()),
+                    &format!(
+                        "kernel `{}` uses `{}`, which is not supported on the GPU",
+                        tcx.def_path_str(kernel.def_id()),
+                        tcx.def_path_str(chain.last().unwrap().def_id())
+                    ),
+                )
+                .note(&format!("it is reachable through {}", path))
+                .emit();
+            errored = true;
+        }
+    }
+
+    // codegen would fail on these in ways which are much harder to understand.
+    if errored {
+        tcx.sess.abort_if_errors();
+    }
+}
+
+fn is_kernel(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    tcx.get_attrs(def_id).iter().any(|attr| {
+        attr.has_name(Symbol::intern("nvvm_internal"))
+            && attr
+                .meta_item_list()
+                .unwrap_or_default()
+                .first()
+                .map_or(false, |arg| arg.has_name(Symbol::intern("kernel")))
+    })
+}
+
+fn is_unsupported(tcx: TyCtxt<'_>, def_id: DefId) -> bool {
+    if tcx.crate_name(def_id.krate) != sym::std {
+        return false;
+    }
+    let path = tcx.def_path_str(def_id);
+    UNSUPPORTED_MODULES
+        .iter()
+        .any(|module| path.starts_with(module))
+}
+
+/// The shortest call chains from `kernel` to every unsupported function it reaches, the chains start with the kernel.
+fn unsupported_calls<'tcx>(tcx: TyCtxt<'tcx>, kernel: Instance<'tcx>) -> Vec<Vec<Instance<'tcx>>> {
+    // breadth first so the reported chains are the shortest ones, `callers` is also the visited set.
+    let mut callers: HashMap<Instance<'tcx>, Option<Instance<'tcx>>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    let mut found = vec![];
+    callers.insert(kernel, None);
+    queue.push_back(kernel);
+
+    while let Some(instance) = queue.pop_front() {
+        if is_unsupported(tcx, instance.def_id()) {
+            found.push(instance);
+            continue;
+        }
+        for callee in callees(tcx, instance) {
+            if !callers.contains_key(&callee) {
+                callers.insert(callee, Some(instance));
+                queue.push_back(callee);
+            }
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|mut instance| {
+            let mut chain = vec![instance];
+            while let Some(&Some(caller)) = callers.get(&instance) {
+                chain.push(caller);
+                instance = caller;
+            }
+            chain.reverse();
+            chain
+        })
+        .collect()
+}
+
+/// The functions `instance` calls directly, if its MIR is available.
+fn callees<'tcx>(tcx: TyCtxt<'tcx>, instance: Instance<'tcx>) -> Vec<Instance<'tcx>> {
+    if !matches!(instance.def, InstanceDef::Item(_)) || !tcx.is_mir_available(instance.def_id()) {
+        return vec![];
+    }
+    let body = tcx.instance_mir(instance.def);
+    let param_env = ParamEnv::reveal_all();
+
+    let mut callees = vec![];
+    for block in body.basic_blocks() {
+        if let TerminatorKind::Call { ref func, .. } = block.terminator().kind {
+            let ty = instance.subst_mir_and_normalize_erasing_regions(
+                tcx,
+                param_env,
+                func.ty(body, tcx),
+            );
+            if let TyKind::FnDef(def_id, substs) = *ty.kind() {
+                if let Ok(Some(callee)) = Instance::resolve(tcx, param_env, def_id, substs) {
+                    callees.push(callee);
+                }
+            }
+        }
+    }
+    callees
+}